@@ -0,0 +1,145 @@
+use nalgebra::{Point2, Vector2};
+
+// Barnes-Hut quadtree, approximating the gravity on each of n bodies in O(log n) rather than O(n).
+// Space is split into squares until each holds one body. Seen from far enough away, a square's contents pull
+// like a single point at their centre of mass: when the square's width over its distance is below theta.
+// Anything closer is opened up, down to individual bodies, which pull exactly.
+
+const MAX_DEPTH: usize = 32;    // Bodies closer than this many halvings of the root end up in the same leaf
+
+#[derive(Clone, Copy, Debug)]
+pub struct TreeBody {
+  pub position: Point2<f32>,
+  pub mass: f32,
+  pub radius: f32,
+}
+
+struct Node {
+  width: f32,
+  mass: f32,
+  centre_of_mass: Point2<f32>,
+  children: Vec<usize>,   // Non-empty quadrants, empty for a leaf
+  bodies: Vec<usize>,     // Only in leaves
+}
+
+pub struct QuadTree<'a> {
+  bodies: &'a [TreeBody],
+  nodes: Vec<Node>,
+  theta: f32,
+  g: f32,
+}
+
+impl<'a> QuadTree<'a> {
+  pub fn new(bodies: &'a [TreeBody], theta: f32, g: f32) -> Self {
+    let mut tree = Self { bodies, nodes: Vec::new(), theta, g };
+    if bodies.is_empty() {
+      return tree;
+    }
+
+    let (mut min, mut max) = (bodies[0].position, bodies[0].position);
+    for body in bodies.iter() {
+      min = min.inf(&body.position);
+      max = max.sup(&body.position);
+    }
+    let width = (max - min).max().max(f32::EPSILON) * 1.001;   // Grown slightly so the far edges fall inside
+    tree.build((0..bodies.len()).collect(), nalgebra::center(&min, &max), width, 0);
+    tree
+  }
+
+  // Adds the node holding `indices` within the square at `centre`, returning its index
+  fn build(&mut self, indices: Vec<usize>, centre: Point2<f32>, width: f32, depth: usize) -> usize {
+    let mass: f32 = indices.iter().map(|&i| self.bodies[i].mass).sum();
+    let weighted: Vector2<f32> = indices.iter().map(|&i| self.bodies[i].position.coords * self.bodies[i].mass).sum();
+    let centre_of_mass = if mass > 0.0 { Point2::from(weighted/mass) } else { centre };
+
+    let index = self.nodes.len();
+    self.nodes.push(Node { width, mass, centre_of_mass, children: Vec::new(), bodies: Vec::new() });
+    if indices.len() <= 1 || depth >= MAX_DEPTH {
+      self.nodes[index].bodies = indices;
+      return index;
+    }
+
+    let mut quadrants: [Vec<usize>; 4] = Default::default();
+    for i in indices {
+      let position = self.bodies[i].position;
+      let quadrant = (position.x >= centre.x) as usize + 2 * (position.y >= centre.y) as usize;
+      quadrants[quadrant].push(i);
+    }
+
+    let quarter = width/4.0;
+    for (quadrant, indices) in quadrants.into_iter().enumerate() {
+      if indices.is_empty() {
+        continue;
+      }
+      let offset = Vector2::new(
+        if quadrant & 1 == 0 { -quarter } else { quarter },
+        if quadrant & 2 == 0 { -quarter } else { quarter },
+      );
+      let child = self.build(indices, centre + offset, width/2.0, depth + 1);
+      self.nodes[index].children.push(child);
+    }
+    index
+  }
+
+  // Calls `visit` with the position, mass and, for single bodies, radius of everything body `i` interacts with:
+  // squares far enough away to take whole, and the other bodies in any leaf too close to take whole
+  fn interactions(&self, i: usize, mut visit: impl FnMut(Point2<f32>, f32, Option<f32>)) {
+    if self.nodes.is_empty() {
+      return;
+    }
+
+    let position = self.bodies[i].position;
+    let mut stack = vec![0];
+    while let Some(index) = stack.pop() {
+      let node = &self.nodes[index];
+      if node.children.is_empty() {
+        for &j in node.bodies.iter().filter(|&&j| j != i) {
+          let other = &self.bodies[j];
+          visit(other.position, other.mass, Some(other.radius));
+        }
+        continue;
+      }
+
+      if node.width.powi(2) < self.theta.powi(2) * (node.centre_of_mass - position).magnitude_squared() {
+        visit(node.centre_of_mass, node.mass, None);
+      } else {
+        stack.extend(node.children.iter().copied());
+      }
+    }
+  }
+
+  // Gravitational force on body `i` from every other body in the tree.
+  // Bodies that overlap pull as if their centres were just touching, so the force stays finite.
+  pub fn force_on(&self, i: usize) -> Vector2<f32> {
+    let body = &self.bodies[i];
+    let mut force = Vector2::new(0.0, 0.0);
+    self.interactions(i, |position, mass, radius| {
+      let dist_vec = position - body.position;
+      let dist_squared = match radius {
+        Some(radius) => dist_vec.magnitude_squared().max((body.radius + radius).powi(2)),
+        None => dist_vec.magnitude_squared(),
+      };
+      force += dist_vec * (self.g * body.mass * mass/dist_squared.sqrt().powi(3));
+    });
+    force
+  }
+
+  // Potential energy of body `i` with every other body in the tree, approximated the same way as the force.
+  // Summed over every body this counts each pair twice. Overlapping bodies are treated as in
+  // `tools::gravitational_potential_energy`, to match the force.
+  pub fn potential_energy_of(&self, i: usize) -> f64 {
+    let body = &self.bodies[i];
+    let mut energy = 0.0;
+    self.interactions(i, |position, mass, radius| {
+      let gmm = self.g as f64 * body.mass as f64 * mass as f64;
+      let dist = (position - body.position).cast::<f64>().norm();
+      let min_dist = radius.map_or(0.0, |radius| (body.radius + radius) as f64);
+      energy -= if dist >= min_dist {
+        gmm/dist
+      } else {
+        gmm * (3.0 * min_dist.powi(2) - dist.powi(2))/(2.0 * min_dist.powi(3))
+      };
+    });
+    energy
+  }
+}
@@ -0,0 +1,219 @@
+// Runs scenarios without a window, for batch jobs and long runs:
+//   orbits-cli run scenario.toml --steps 100000 --dt 0.001 --out traj.csv
+// Exits with status 1 on invalid input or files that can't be read or written, and 2 if the run blows up.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+
+use orbits::cli::Args;
+use orbits::export::{ExportFormat, TrajectoryExporter};
+use orbits::scenario::{Scenario, ScenarioError};
+use orbits::simulation::{Simulation, Integrator, GravitySolver};
+use orbits::snapshot;
+
+const USAGE: &str = "\
+Usage: orbits-cli run <scenario.toml> [options]
+
+Options:
+  --steps <n>           Number of steps to run (default 1000)
+  --dt <seconds>        Simulated time per step (default 1/60)
+  --integrator <name>   euler or leapfrog (default euler)
+  --gravity <solver>    direct or barnes-hut (default direct)
+  --seed <n>            Seed for randomly generated bodies, for repeatable runs
+  --out <path>          Export trajectories here, as CSV or a directory of NumPy columns
+  --format <format>     csv or columnar (default: csv if --out ends in .csv, otherwise columnar)
+  --interval <seconds>  Simulated time between exported samples (default 0, every step)
+  --bodies <ids>        Comma separated body IDs to export (default all)
+  --report <n>          Print progress every n steps (default steps/10)
+  --save <path>         Save a snapshot of the final state, loadable in the viewer
+";
+
+struct RunOptions {
+  scenario: PathBuf,
+  steps: u64,
+  dt: f32,
+  integrator: Integrator,
+  gravity_solver: GravitySolver,
+  seed: Option<u64>,
+  out: Option<PathBuf>,
+  format: Option<ExportFormat>,
+  interval: f64,
+  bodies: Option<Vec<usize>>,
+  report: Option<u64>,
+  save: Option<PathBuf>,
+}
+
+enum Failure {
+  Usage(String),      // Bad arguments
+  Input(String),      // Files that can't be read or written
+  Blowup(String),     // The simulation produced NaNs
+}
+
+fn parse_run(mut args: Args) -> Result<RunOptions, String> {
+  let scenario = args.next_arg().ok_or("run needs a scenario file")?;
+  let mut options = RunOptions {
+    scenario: PathBuf::from(scenario),
+    steps: 1000,
+    dt: 1.0/60.0,
+    integrator: Integrator::default(),
+    gravity_solver: GravitySolver::default(),
+    seed: None,
+    out: None,
+    format: None,
+    interval: 0.0,
+    bodies: None,
+    report: None,
+    save: None,
+  };
+
+  while let Some(flag) = args.next_arg() {
+    match flag.as_str() {
+      "--steps" => options.steps = args.value(&flag)?,
+      "--dt" => options.dt = args.value(&flag)?,
+      "--integrator" => options.integrator = args.value(&flag)?,
+      "--gravity" => options.gravity_solver = args.value(&flag)?,
+      "--seed" => options.seed = Some(args.value(&flag)?),
+      "--out" => options.out = Some(args.value(&flag)?),
+      "--format" => options.format = Some(match args.value::<String>(&flag)?.as_str() {
+        "csv" => ExportFormat::Csv,
+        "columnar" => ExportFormat::Columnar,
+        other => return Err(format!("unknown format {:?}, expected csv or columnar", other)),
+      }),
+      "--interval" => options.interval = args.value(&flag)?,
+      "--bodies" => options.bodies = Some(
+        args.value::<String>(&flag)?
+          .split(',')
+          .map(|id| id.trim().parse().map_err(|_| format!("invalid body ID {:?} for --bodies", id)))
+          .collect::<Result<_, _>>()?
+      ),
+      "--report" => options.report = Some(args.value(&flag)?),
+      "--save" => options.save = Some(args.value(&flag)?),
+      _ => return Err(format!("unknown option {:?}", flag)),
+    }
+  }
+
+  if options.steps == 0 {
+    return Err("--steps must be at least 1".to_string());
+  }
+  if !(options.dt.is_finite() && options.dt > 0.0) {
+    return Err("--dt must be a positive number".to_string());
+  }
+  if !(options.interval.is_finite() && options.interval >= 0.0) {
+    return Err("--interval can't be negative".to_string());
+  }
+  if options.report == Some(0) {
+    return Err("--report must be at least 1".to_string());
+  }
+  Ok(options)
+}
+
+fn run(options: RunOptions) -> Result<(), Failure> {
+  let scenario_error = |error| match error {
+    ScenarioError::Io { .. } => Failure::Input(error.to_string()),
+    _ => Failure::Input(format!("{}: {}", options.scenario.display(), error)),
+  };
+  let scenario = Scenario::load(&options.scenario).map_err(scenario_error)?;
+  let mut simulation = Simulation::new();
+  simulation.integrator = options.integrator;
+  simulation.gravity_solver = options.gravity_solver;
+  if let Some(seed) = options.seed {
+    simulation.seed(seed);
+  }
+  scenario.apply(&mut simulation).map_err(scenario_error)?;
+
+  let mut exporter = match &options.out {
+    Some(out) => {
+      let format = options.format.unwrap_or_else(|| {
+        if out.extension().is_some_and(|extension| extension == "csv") { ExportFormat::Csv } else { ExportFormat::Columnar }
+      });
+      let exporter = TrajectoryExporter::new(format, out, options.bodies.as_deref(), options.interval)
+        .map_err(|error| Failure::Input(error.to_string()))?;
+      Some(exporter)
+    },
+    None => None,
+  };
+
+  println!(
+    "Running {} ({} bodies) for {} steps of {}s",
+    scenario.name.as_deref().unwrap_or("untitled scenario"),
+    simulation.planets.len(),
+    options.steps,
+    options.dt,
+  );
+
+  let report_every = options.report.unwrap_or((options.steps/10).max(1));
+  let started = Instant::now();
+  if let Some(exporter) = exporter.as_mut() {
+    exporter.sample(&simulation).map_err(|error| Failure::Input(error.to_string()))?;
+  }
+
+  for step in 1..=options.steps {
+    simulation.step(options.dt);
+
+    if let Some(id) = simulation.find_non_finite() {
+      return Err(Failure::Blowup(format!(
+        "body {} became NaN or infinite at step {} (t = {:.4}s); try a smaller --dt", id, step, simulation.time,
+      )));
+    }
+    if let Some(exporter) = exporter.as_mut() {
+      exporter.sample(&simulation).map_err(|error| Failure::Input(error.to_string()))?;
+    }
+
+    if step % report_every == 0 || step == options.steps {
+      let diagnostics = simulation.diagnostics();
+      let drift = simulation.drift();
+      println!(
+        "step {:>8} ({:>5.1}%)  t = {:.3}s  bodies {:>5}  E = {:.4e} (drift {:+.2e})  p drift {:.2e}  L drift {:+.2e}  {:.1}s elapsed",
+        step,
+        100.0 * step as f64/options.steps as f64,
+        simulation.time,
+        simulation.planets.len(),
+        diagnostics.total_energy(),
+        drift.energy,
+        drift.momentum,
+        drift.angular_momentum,
+        started.elapsed().as_secs_f32(),
+      );
+    }
+  }
+
+  if let Some(exporter) = exporter {
+    let rows = exporter.finish().map_err(|error| Failure::Input(error.to_string()))?;
+    println!("Exported {} rows to {}", rows, options.out.as_ref().unwrap().display());
+  }
+  if let Some(path) = &options.save {
+    snapshot::save(path, &simulation, None).map_err(|error| Failure::Input(error.to_string()))?;
+    println!("Saved snapshot to {}", path.display());
+  }
+  Ok(())
+}
+
+fn main() -> ExitCode {
+  let mut args = Args::from_env();
+  let result = match args.next_arg().as_deref() {
+    Some("run") => parse_run(args).map_err(Failure::Usage).and_then(run),
+    Some("help") | Some("--help") | Some("-h") => {
+      print!("{}", USAGE);
+      return ExitCode::SUCCESS;
+    },
+    Some(command) => Err(Failure::Usage(format!("unknown command {:?}", command))),
+    None => Err(Failure::Usage("no command given".to_string())),
+  };
+
+  match result {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(Failure::Usage(message)) => {
+      eprintln!("error: {}\n\n{}", message, USAGE);
+      ExitCode::from(1)
+    },
+    Err(Failure::Input(message)) => {
+      eprintln!("error: {}", message);
+      ExitCode::from(1)
+    },
+    Err(Failure::Blowup(message)) => {
+      eprintln!("error: {}", message);
+      ExitCode::from(2)
+    },
+  }
+}
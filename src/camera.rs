@@ -0,0 +1,140 @@
+use nalgebra::{Point2, Vector2};
+
+use crate::frame::Pose;
+use crate::simulation::Simulation;
+
+const MIN_ZOOM: f32 = 1.0e-4;
+const MAX_ZOOM: f32 = 1.0e4;
+
+// What the camera keeps in the middle of the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Follow {
+  Free,           // Stays wherever it was panned to
+  Body(usize),    // Until the body is gone
+  Heaviest,
+  Barycentre,
+}
+
+impl Follow {
+  // The modes F steps through. Following a particular body is chosen separately.
+  pub fn next(self) -> Self {
+    match self {
+      Follow::Free | Follow::Body(_) => Follow::Heaviest,
+      Follow::Heaviest => Follow::Barycentre,
+      Follow::Barycentre => Follow::Free,
+    }
+  }
+
+  pub fn describe(self) -> String {
+    match self {
+      Follow::Free => "free".to_string(),
+      Follow::Body(id) => format!("following body {}", id),
+      Follow::Heaviest => "following the heaviest body".to_string(),
+      Follow::Barycentre => "following the barycentre".to_string(),
+    }
+  }
+}
+
+// Maps the simulation's world coordinates onto the window: `centre` is the world point shown in the middle of
+// the window, and `zoom` is how many pixels one unit of world length takes up. With a rotating frame, world
+// points are carried into the frame first, and `centre` is a point in the frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+  pub centre: Point2<f32>,
+  pub zoom: f32,
+  pub follow: Follow,
+  pub frame: Option<Pose>,    // Current pose of the rotating frame being viewed in, if any
+  screen_dims: (f32, f32),
+}
+
+impl Camera {
+  // Starts out showing one world unit per pixel with the world origin at the top left, as if there were no camera
+  pub fn new(screen_dims: (f32, f32)) -> Self {
+    Self {
+      centre: Point2::new(screen_dims.0/2.0, screen_dims.1/2.0),
+      zoom: 1.0,
+      follow: Follow::Free,
+      frame: None,
+      screen_dims,
+    }
+  }
+
+  // Back to the starting view, still in whatever frame it was in
+  pub fn reset(&mut self) {
+    *self = Self { frame: self.frame, ..Self::new(self.screen_dims) };
+  }
+
+  fn screen_centre(&self) -> Point2<f32> {
+    Point2::new(self.screen_dims.0/2.0, self.screen_dims.1/2.0)
+  }
+
+  pub fn to_screen(&self, world: Point2<f32>) -> Point2<f32> {
+    self.view_to_screen(self.world_to_view(world))
+  }
+
+  // Where a world point was drawn when the frame had `pose`, for things left behind like trails
+  pub fn to_screen_posed(&self, world: Point2<f32>, pose: &Pose) -> Point2<f32> {
+    self.view_to_screen(pose.to_frame(world))
+  }
+
+  pub fn to_world(&self, screen: Point2<f32>) -> Point2<f32> {
+    let view = self.screen_to_view(screen);
+    self.frame.map_or(view, |pose| pose.from_frame(view))
+  }
+
+  // World velocity of something at `screen` moving at `screen_velocity` pixels per second on screen, so bodies
+  // dragged out in a rotating frame move as drawn in it
+  pub fn velocity_to_world(&self, screen: Point2<f32>, screen_velocity: Vector2<f32>) -> Vector2<f32> {
+    let velocity = screen_velocity/self.zoom;
+    match self.frame {
+      Some(pose) => pose.velocity_from_frame(self.screen_to_view(screen), velocity),
+      None => velocity,
+    }
+  }
+
+  // How far the frame has turned, for drawing anything that spins with the view
+  pub fn rotation(&self) -> f32 {
+    self.frame.map_or(0.0, |pose| pose.angle)
+  }
+
+  // World, or frame coordinates when rotating
+  fn world_to_view(&self, world: Point2<f32>) -> Point2<f32> {
+    self.frame.map_or(world, |pose| pose.to_frame(world))
+  }
+
+  fn view_to_screen(&self, view: Point2<f32>) -> Point2<f32> {
+    self.screen_centre() + (view - self.centre) * self.zoom
+  }
+
+  fn screen_to_view(&self, screen: Point2<f32>) -> Point2<f32> {
+    self.centre + (screen - self.screen_centre())/self.zoom
+  }
+
+  // Moves the view so the world follows the mouse, which stops it following anything
+  pub fn pan(&mut self, screen_delta: Vector2<f32>) {
+    self.centre -= screen_delta/self.zoom;
+    self.follow = Follow::Free;
+  }
+
+  // Centres on whatever is being followed, going free if a followed body has gone
+  pub fn track(&mut self, simulation: &Simulation) {
+    let target = match self.follow {
+      Follow::Free => return,
+      Follow::Body(id) => simulation.planets.get(&id).map(|planet| planet.borrow().position),
+      Follow::Heaviest => simulation.heaviest_body().map(|id| simulation.planets[&id].borrow().position),
+      Follow::Barycentre => simulation.centre_of_momentum().map(|(centre, _)| centre),
+    };
+    match target {
+      Some(target) => self.centre = self.world_to_view(target),
+      None if matches!(self.follow, Follow::Body(_)) => self.follow = Follow::Free,
+      None => (),
+    }
+  }
+
+  // Zooms by `factor`, keeping whatever is under `screen_point` where it is
+  pub fn zoom_at(&mut self, screen_point: Point2<f32>, factor: f32) {
+    let before = self.screen_to_view(screen_point);
+    self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+    self.centre += before - self.screen_to_view(screen_point);
+  }
+}
@@ -0,0 +1,29 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::str::FromStr;
+
+// Minimal command line parsing shared by the binaries: flags are read in order, each followed by its value
+pub struct Args {
+  args: VecDeque<String>,
+}
+
+impl Args {
+  pub fn new(args: impl IntoIterator<Item = String>) -> Self {
+    Self { args: args.into_iter().collect() }
+  }
+
+  // The program's own arguments, without its name
+  pub fn from_env() -> Self {
+    Self::new(std::env::args().skip(1))
+  }
+
+  pub fn next_arg(&mut self) -> Option<String> {
+    self.args.pop_front()
+  }
+
+  // Parses the argument following `flag`
+  pub fn value<T: FromStr>(&mut self, flag: &str) -> Result<T, String> where T::Err: Display {
+    let value = self.next_arg().ok_or_else(|| format!("{} needs a value", flag))?;
+    value.parse().map_err(|error| format!("invalid value {:?} for {}: {}", value, flag, error))
+  }
+}
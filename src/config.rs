@@ -0,0 +1,189 @@
+use serde::{Serialize, Deserialize};
+
+use crate::SCREEN_DIMS;
+
+// Settings the simulation reads while it runs. Saved with snapshots, given in a scenario's [config] table,
+// and adjustable from the viewer one `Setting` at a time.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+  pub gravitational_constant: f32,
+  pub planet_density: f32,        // Mass per unit volume of bodies added without a mass
+  pub spawn_radius: f32,          // Radius of bodies added with the mouse
+  pub teleport_on_edges: bool,    // Bodies leaving one edge of the region come back in at the other
+  pub edges: [f32; 2],            // Width and height of that region, with its top left corner at the origin
+  pub trail_node_period: f32,     // Simulated seconds between the nodes of a trail
+  pub trail_node_spacing: f32,    // Distance a body can move before a node goes down, however soon that is
+  pub trail_node_lifetime: f32,   // Simulated seconds before a trail node fades out
+  pub trail_max_nodes: u32,       // Oldest nodes are dropped past this, however young
+  pub preview_horizon: f32,       // Simulated seconds ahead to predict the path of a body being dragged out
+  pub export_interval: f32,       // Simulated seconds between the samples the viewer exports
+  // Soft-sphere contact parameters
+  pub contact_stiffness: f32,     // Effective Young's modulus used by the Hertzian normal force
+  pub contact_restitution: f32,   // Ratio of separation to approach speed in a head-on contact
+  pub contact_friction: f32,      // Coulomb friction coefficient
+  // Barnes-Hut opening angle: lower is more accurate but slower. Must stay below 0.7 so a body's own square
+  // is always opened.
+  pub barnes_hut_theta: f32,
+}
+
+impl Default for SimConfig {
+  fn default() -> Self {
+    Self {
+      gravitational_constant: 0.0001,
+      planet_density: 5000.0,
+      spawn_radius: 5.0,
+      teleport_on_edges: false,
+      edges: [SCREEN_DIMS.0, SCREEN_DIMS.1],
+      trail_node_period: 0.02,
+      trail_node_spacing: 10.0,
+      trail_node_lifetime: 0.7,
+      trail_max_nodes: 500,
+      preview_horizon: 5.0,
+      export_interval: 0.1,
+      contact_stiffness: 5.0e7,
+      contact_restitution: 0.5,
+      contact_friction: 0.5,
+      barnes_hut_theta: 0.5,
+    }
+  }
+}
+
+impl SimConfig {
+  // Describes the first setting that would break the simulation
+  pub fn validate(&self) -> Result<(), String> {
+    let check = |ok: bool, reason: &str| if ok { Ok(()) } else { Err(reason.to_string()) };
+    check(self.gravitational_constant.is_finite() && self.gravitational_constant >= 0.0, "`gravitational_constant` can't be negative")?;
+    check(self.planet_density > 0.0, "`planet_density` must be positive")?;
+    check(self.spawn_radius > 0.0, "`spawn_radius` must be positive")?;
+    check(self.edges[0] > 0.0 && self.edges[1] > 0.0, "`edges` must be a positive width and height")?;
+    check(self.trail_node_period > 0.0, "`trail_node_period` must be positive")?;
+    check(self.trail_node_spacing > 0.0, "`trail_node_spacing` must be positive")?;
+    check(self.trail_node_lifetime >= 0.0, "`trail_node_lifetime` can't be negative")?;
+    check(self.trail_max_nodes >= 2, "`trail_max_nodes` must be at least 2")?;
+    check(self.preview_horizon > 0.0, "`preview_horizon` must be positive")?;
+    check(self.export_interval > 0.0, "`export_interval` must be positive")?;
+    check(self.contact_stiffness > 0.0, "`contact_stiffness` must be positive")?;
+    check(self.contact_restitution > 0.0 && self.contact_restitution <= 1.0, "`contact_restitution` must be above 0 and at most 1")?;
+    check(self.contact_friction >= 0.0, "`contact_friction` can't be negative")?;
+    check(self.barnes_hut_theta > 0.0 && self.barnes_hut_theta < 0.7, "`barnes_hut_theta` must be between 0 and 0.7")?;
+    Ok(())
+  }
+
+  pub fn describe(&self, setting: Setting) -> String {
+    let value = match setting {
+      Setting::GravitationalConstant => format!("{:.3e}", self.gravitational_constant),
+      Setting::PlanetDensity => format!("{:.1}", self.planet_density),
+      Setting::SpawnRadius => format!("{:.2}", self.spawn_radius),
+      Setting::TeleportOnEdges => self.teleport_on_edges.to_string(),
+      Setting::TrailNodePeriod => format!("{:.3}", self.trail_node_period),
+      Setting::TrailNodeSpacing => format!("{:.1}", self.trail_node_spacing),
+      Setting::TrailNodeLifetime => format!("{:.2}", self.trail_node_lifetime),
+      Setting::TrailMaxNodes => self.trail_max_nodes.to_string(),
+      Setting::PreviewHorizon => format!("{:.2}", self.preview_horizon),
+      Setting::ExportInterval => format!("{:.3}", self.export_interval),
+      Setting::ContactStiffness => format!("{:.3e}", self.contact_stiffness),
+      Setting::ContactRestitution => format!("{:.2}", self.contact_restitution),
+      Setting::ContactFriction => format!("{:.2}", self.contact_friction),
+      Setting::BarnesHutTheta => format!("{:.2}", self.barnes_hut_theta),
+    };
+    format!("{} = {}", setting.name(), value)
+  }
+
+  // Nudges a setting up or down a step, keeping it valid. Scales are multiplied, fractions are stepped.
+  pub fn adjust(&mut self, setting: Setting, up: bool) {
+    const FACTOR: f32 = 1.25;
+    // Kept finite and, unless it was already zero, above zero
+    let scale = |value: &mut f32| {
+      *value = if up { (*value * FACTOR).min(f32::MAX) } else { (*value/FACTOR).max(f32::MIN_POSITIVE.min(*value)) };
+    };
+    let step = |value: &mut f32, step: f32, min: f32, max: f32| {
+      *value = (*value + if up { step } else { -step }).clamp(min, max);
+    };
+
+    match setting {
+      Setting::GravitationalConstant => scale(&mut self.gravitational_constant),
+      Setting::PlanetDensity => scale(&mut self.planet_density),
+      Setting::SpawnRadius => scale(&mut self.spawn_radius),
+      Setting::TeleportOnEdges => self.teleport_on_edges = !self.teleport_on_edges,
+      Setting::TrailNodePeriod => scale(&mut self.trail_node_period),
+      Setting::TrailNodeSpacing => scale(&mut self.trail_node_spacing),
+      Setting::TrailNodeLifetime => step(&mut self.trail_node_lifetime, 0.1, 0.0, f32::MAX),
+      // In u64 so the top of the range neither overflows nor wraps round
+      Setting::TrailMaxNodes => self.trail_max_nodes = if up {
+        (self.trail_max_nodes as u64 * 5/4 + 1).min(u32::MAX as u64) as u32
+      } else {
+        (self.trail_max_nodes as u64 * 4/5).max(2) as u32
+      },
+      Setting::PreviewHorizon => scale(&mut self.preview_horizon),
+      Setting::ExportInterval => scale(&mut self.export_interval),
+      Setting::ContactStiffness => scale(&mut self.contact_stiffness),
+      Setting::ContactRestitution => step(&mut self.contact_restitution, 0.05, 0.05, 1.0),
+      Setting::ContactFriction => step(&mut self.contact_friction, 0.1, 0.0, f32::MAX),
+      Setting::BarnesHutTheta => step(&mut self.barnes_hut_theta, 0.05, 0.05, 0.65),
+    }
+  }
+}
+
+// A single adjustable field of SimConfig
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+  GravitationalConstant,
+  PlanetDensity,
+  SpawnRadius,
+  TeleportOnEdges,
+  TrailNodePeriod,
+  TrailNodeSpacing,
+  TrailNodeLifetime,
+  TrailMaxNodes,
+  PreviewHorizon,
+  ExportInterval,
+  ContactStiffness,
+  ContactRestitution,
+  ContactFriction,
+  BarnesHutTheta,
+}
+
+impl Setting {
+  pub const ALL: [Setting; 14] = [
+    Setting::GravitationalConstant,
+    Setting::PlanetDensity,
+    Setting::SpawnRadius,
+    Setting::TeleportOnEdges,
+    Setting::TrailNodePeriod,
+    Setting::TrailNodeSpacing,
+    Setting::TrailNodeLifetime,
+    Setting::TrailMaxNodes,
+    Setting::PreviewHorizon,
+    Setting::ExportInterval,
+    Setting::ContactStiffness,
+    Setting::ContactRestitution,
+    Setting::ContactFriction,
+    Setting::BarnesHutTheta,
+  ];
+
+  // As written in a scenario's [config] table
+  pub fn name(self) -> &'static str {
+    match self {
+      Setting::GravitationalConstant => "gravitational_constant",
+      Setting::PlanetDensity => "planet_density",
+      Setting::SpawnRadius => "spawn_radius",
+      Setting::TeleportOnEdges => "teleport_on_edges",
+      Setting::TrailNodePeriod => "trail_node_period",
+      Setting::TrailNodeSpacing => "trail_node_spacing",
+      Setting::TrailNodeLifetime => "trail_node_lifetime",
+      Setting::TrailMaxNodes => "trail_max_nodes",
+      Setting::PreviewHorizon => "preview_horizon",
+      Setting::ExportInterval => "export_interval",
+      Setting::ContactStiffness => "contact_stiffness",
+      Setting::ContactRestitution => "contact_restitution",
+      Setting::ContactFriction => "contact_friction",
+      Setting::BarnesHutTheta => "barnes_hut_theta",
+    }
+  }
+
+  pub fn next(self) -> Self {
+    let index = Self::ALL.iter().position(|setting| *setting == self).unwrap();
+    Self::ALL[(index + 1) % Self::ALL.len()]
+  }
+}
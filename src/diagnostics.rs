@@ -0,0 +1,123 @@
+use nalgebra::{Point2, Vector2};
+use serde::{Serialize, Deserialize};
+
+use crate::planet::Planet;
+use crate::tools;
+
+// Conserved totals of the whole system, used to judge how well the integration is behaving.
+// Accumulated in f64 since the totals are sums of many large, partly cancelling terms.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostics {
+  pub kinetic_energy: f64,    // Translational plus rotational
+  pub potential_energy: f64,
+  pub momentum: Vector2<f64>,
+  pub angular_momentum: f64,  // About the origin, including spin
+  pub centre_of_mass: Point2<f64>,
+  pub total_mass: f64,
+
+  // Sums of magnitudes, so drift can be made relative even when a total is close to zero
+  momentum_scale: f64,
+  angular_momentum_scale: f64,
+}
+
+// Change since a reference measurement, relative to the size of the quantity
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Drift {
+  pub energy: f64,
+  pub momentum: f64,
+  pub angular_momentum: f64,
+  pub centre_of_mass: f64,    // Distance moved by the centre of mass beyond what the momentum accounts for
+}
+
+impl Default for Diagnostics {
+  fn default() -> Self {
+    Self {
+      kinetic_energy: 0.0,
+      potential_energy: 0.0,
+      momentum: Vector2::new(0.0, 0.0),
+      angular_momentum: 0.0,
+      centre_of_mass: Point2::new(0.0, 0.0),
+      total_mass: 0.0,
+
+      momentum_scale: 0.0,
+      angular_momentum_scale: 0.0,
+    }
+  }
+}
+
+impl Diagnostics {
+  // Potential energy summed over every pair, which is O(n^2)
+  pub fn measure<'a>(planets: impl Iterator<Item = &'a Planet>, g: f32) -> Self {
+    let planets: Vec<&Planet> = planets.collect();
+    let mut potential_energy = 0.0;
+    for (i, pl1) in planets.iter().enumerate() {
+      for pl2 in planets[i+1..].iter() {
+        potential_energy += tools::gravitational_potential_energy(g, pl1, pl2);
+      }
+    }
+    Self::measure_with_potential(planets.into_iter(), potential_energy)
+  }
+
+  // Everything else, with the potential energy found some cheaper way
+  pub fn measure_with_potential<'a>(planets: impl Iterator<Item = &'a Planet>, potential_energy: f64) -> Self {
+    let mut d = Self { potential_energy, ..Self::default() };
+
+    let mut weighted_position = Vector2::new(0.0, 0.0);
+    for pl in planets {
+      let mass = pl.total_mass() as f64;
+      let position = pl.position.cast::<f64>().coords;
+      let velocity = pl.velocity.cast::<f64>();
+      let momentum = velocity * mass;
+      let moment_of_inertia = pl.moment_of_inertia() as f64;
+      let spin = pl.spin as f64;
+      let angular_momentum = position.x * momentum.y - position.y * momentum.x + moment_of_inertia * spin;
+
+      d.kinetic_energy += 0.5 * mass * velocity.norm_squared() + 0.5 * moment_of_inertia * spin.powi(2);
+      d.momentum += momentum;
+      d.angular_momentum += angular_momentum;
+      d.total_mass += mass;
+      weighted_position += position * mass;
+
+      d.momentum_scale += momentum.norm();
+      d.angular_momentum_scale += angular_momentum.abs();
+    }
+
+    if d.total_mass > 0.0 {
+      d.centre_of_mass = Point2::from(weighted_position/d.total_mass);
+    }
+
+    d
+  }
+
+  pub fn total_energy(&self) -> f64 {
+    self.kinetic_energy + self.potential_energy
+  }
+
+  pub fn drift_from(&self, initial: &Diagnostics, elapsed_time: f64) -> Drift {
+    let relative = |change: f64, scale: f64| if scale > 0.0 { change/scale } else { 0.0 };
+
+    // With no external forces the centre of mass moves in a straight line at P/M
+    let expected_centre_of_mass = if initial.total_mass > 0.0 {
+      initial.centre_of_mass + initial.momentum/initial.total_mass * elapsed_time
+    } else {
+      initial.centre_of_mass
+    };
+
+    Drift {
+      energy: relative(
+        self.total_energy() - initial.total_energy(),
+        initial.kinetic_energy + initial.potential_energy.abs(),
+      ),
+      // A system starting at rest has no momentum scale of its own, so fall back on the current one
+      momentum: relative(
+        (self.momentum - initial.momentum).norm(),
+        initial.momentum_scale.max(self.momentum_scale),
+      ),
+      angular_momentum: relative(
+        self.angular_momentum - initial.angular_momentum,
+        initial.angular_momentum_scale.max(self.angular_momentum_scale),
+      ),
+      centre_of_mass: (self.centre_of_mass - expected_centre_of_mass).norm(),
+    }
+  }
+}
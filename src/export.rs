@@ -0,0 +1,198 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::simulation::Simulation;
+
+// Writes per-body time series while a simulation runs, one row per body per sample:
+//   time, id, x, y, vx, vy, mass, radius
+// either as a CSV file or as a directory holding one NumPy .npy file per column, which loads straight into pandas:
+//   pd.DataFrame({c: np.load(f"out/{c}.npy") for c in ["time", "id", "x", "y", "vx", "vy", "mass", "radius"]})
+// Rows are written as they are sampled, so long runs don't build up in memory.
+
+pub const EXPORT_COLUMNS: [&str; 8] = ["time", "id", "x", "y", "vx", "vy", "mass", "radius"];
+const NPY_HEADER_LEN: usize = 128;   // Fixed, so the row count can be filled in once the export finishes
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+  Csv,
+  Columnar,
+}
+
+#[derive(Debug)]
+pub struct ExportError {
+  pub path: PathBuf,
+  pub source: io::Error,
+}
+
+impl fmt::Display for ExportError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "couldn't write {}: {}", self.path.display(), self.source)
+  }
+}
+
+impl std::error::Error for ExportError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    Some(&self.source)
+  }
+}
+
+struct Column {
+  path: PathBuf,
+  dtype: &'static str,
+  file: BufWriter<File>,
+}
+
+enum Sink {
+  Csv { path: PathBuf, file: BufWriter<File> },
+  Columnar(Vec<Column>),
+}
+
+impl Sink {
+  // The file, or for the columnar format the directory, being written
+  fn path(&self) -> PathBuf {
+    match self {
+      Sink::Csv { path, .. } => path.clone(),
+      Sink::Columnar(columns) => columns[0].path.parent().unwrap_or(Path::new("")).to_path_buf(),
+    }
+  }
+}
+
+pub struct TrajectoryExporter {
+  sink: Sink,
+  bodies: Option<HashSet<usize>>,   // Every body if None
+  interval: f64,                    // Simulated seconds between samples, 0 for every sample call
+  next_sample: Option<f64>,
+  last_sample: Option<f64>,         // Time of the last rows written
+  rows: u64,
+}
+
+impl TrajectoryExporter {
+  // Exports `bodies`, or all of them if None, to `path`. For the columnar format `path` is a directory,
+  // created if needed.
+  pub fn new(format: ExportFormat, path: impl AsRef<Path>, bodies: Option<&[usize]>, interval: f64) -> Result<Self, ExportError> {
+    let path = path.as_ref();
+    let error = |path: &Path| { let path = path.to_path_buf(); move |source| ExportError { path, source } };
+
+    let sink = match format {
+      ExportFormat::Csv => {
+        let mut file = BufWriter::new(File::create(path).map_err(error(path))?);
+        writeln!(file, "{}", EXPORT_COLUMNS.join(",")).map_err(error(path))?;
+        Sink::Csv { path: path.to_path_buf(), file }
+      },
+      ExportFormat::Columnar => {
+        fs::create_dir_all(path).map_err(error(path))?;
+        let mut columns = Vec::with_capacity(EXPORT_COLUMNS.len());
+        for name in EXPORT_COLUMNS {
+          let column_path = path.join(format!("{}.npy", name));
+          let dtype = match name {
+            "time" => "<f8",
+            "id" => "<u8",
+            _ => "<f4",
+          };
+          let mut file = BufWriter::new(File::create(&column_path).map_err(error(&column_path))?);
+          write_npy_header(&mut file, dtype, 0).map_err(error(&column_path))?;
+          columns.push(Column { path: column_path, dtype, file });
+        }
+        Sink::Columnar(columns)
+      },
+    };
+
+    Ok(Self {
+      sink,
+      bodies: bodies.map(|bodies| bodies.iter().copied().collect()),
+      interval: interval.max(0.0),
+      next_sample: None,
+      last_sample: None,
+      rows: 0,
+    })
+  }
+
+  pub fn rows(&self) -> u64 {
+    self.rows
+  }
+
+  // Call after every step. Writes a row for each exported body if a sample is due.
+  // Errors if the simulation's time has gone back since the last sample, as when the scene is replaced, rather
+  // than writing a second run into the same export.
+  pub fn sample(&mut self, simulation: &Simulation) -> Result<(), ExportError> {
+    if self.last_sample.is_some_and(|last| simulation.time < last) {
+      let source = io::Error::new(io::ErrorKind::InvalidInput, "simulation time went back, so the scene was replaced");
+      return Err(ExportError { path: self.sink.path(), source });
+    }
+
+    // Time is a running sum of steps, so allow it to fall just short of a sample
+    let tolerance = self.interval * 1.0e-6;
+    if self.next_sample.is_some_and(|next| simulation.time < next - tolerance) {
+      return Ok(());
+    }
+    let mut next = self.next_sample.unwrap_or(simulation.time) + self.interval;
+    while next <= simulation.time + tolerance && self.interval > 0.0 {
+      next += self.interval;
+    }
+    self.next_sample = Some(next);
+    self.last_sample = Some(simulation.time);
+
+    let mut ids: Vec<usize> = simulation.planets.keys()
+      .filter(|id| self.bodies.as_ref().is_none_or(|bodies| bodies.contains(id)))
+      .copied()
+      .collect();
+    ids.sort_unstable();
+
+    for id in ids {
+      let planet = simulation.planets[&id].borrow();
+      let values = [
+        planet.position.x, planet.position.y, planet.velocity.x, planet.velocity.y, planet.mass, planet.radius,
+      ];
+      match &mut self.sink {
+        Sink::Csv { path, file } => {
+          let row = values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(",");
+          writeln!(file, "{},{},{}", simulation.time, id, row)
+            .map_err(|source| ExportError { path: path.clone(), source })?;
+        },
+        Sink::Columnar(columns) => {
+          for (i, column) in columns.iter_mut().enumerate() {
+            let result = match i {
+              0 => column.file.write_all(&simulation.time.to_le_bytes()),
+              1 => column.file.write_all(&(id as u64).to_le_bytes()),
+              _ => column.file.write_all(&values[i - 2].to_le_bytes()),
+            };
+            result.map_err(|source| ExportError { path: column.path.clone(), source })?;
+          }
+        },
+      }
+      self.rows += 1;
+    }
+    Ok(())
+  }
+
+  // Flushes everything to disk, returning the number of rows written
+  pub fn finish(self) -> Result<u64, ExportError> {
+    match self.sink {
+      Sink::Csv { path, mut file } => file.flush().map_err(|source| ExportError { path, source })?,
+      Sink::Columnar(columns) => {
+        for mut column in columns {
+          let rows = self.rows;
+          let result = (|| {
+            column.file.seek(SeekFrom::Start(0))?;
+            write_npy_header(&mut column.file, column.dtype, rows)?;
+            column.file.flush()
+          })();
+          result.map_err(|source| ExportError { path: column.path, source })?;
+        }
+      },
+    }
+    Ok(self.rows)
+  }
+}
+
+// NumPy format 1.0 header for a one dimensional array, padded with spaces to a fixed length
+fn write_npy_header(out: &mut impl Write, dtype: &str, rows: u64) -> io::Result<()> {
+  let dict = format!("{{'descr': '{}', 'fortran_order': False, 'shape': ({},), }}", dtype, rows);
+  let header_len = NPY_HEADER_LEN - 10;
+  out.write_all(b"\x93NUMPY\x01\x00")?;
+  out.write_all(&(header_len as u16).to_le_bytes())?;
+  out.write_all(format!("{:<width$}\n", dict, width = header_len - 1).as_bytes())
+}
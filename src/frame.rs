@@ -0,0 +1,115 @@
+use nalgebra::{Point2, Rotation2, Vector2};
+
+use std::collections::VecDeque;
+
+use crate::simulation::Simulation;
+
+// A view co-rotating with a pair of bodies, which shows horseshoe and tadpole orbits for what they are. The
+// physics stays inertial; positions are only carried into the frame when drawn. The pair's barycentre is pinned
+// where it was when the frame was set up, and the line from the primary to the secondary keeps the direction
+// it had then, so turning the frame on doesn't move anything on screen.
+
+// How the frame sits at one moment
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+  pub barycentre: Point2<f32>,    // Of the pair, in the simulation
+  pub velocity: Vector2<f32>,     // Of the barycentre
+  pub angle: f32,                 // Radians the pair has turned since the frame was set up
+  pub angular_velocity: f32,
+  anchor: Point2<f32>,            // Where the barycentre is drawn
+}
+
+impl Pose {
+  pub fn to_frame(&self, world: Point2<f32>) -> Point2<f32> {
+    self.anchor + Rotation2::new(-self.angle) * (world - self.barycentre)
+  }
+
+  pub fn from_frame(&self, frame: Point2<f32>) -> Point2<f32> {
+    self.barycentre + Rotation2::new(self.angle) * (frame - self.anchor)
+  }
+
+  // Velocity in the simulation of something at `frame` moving at `velocity` as seen in the frame
+  pub fn velocity_from_frame(&self, frame: Point2<f32>, velocity: Vector2<f32>) -> Vector2<f32> {
+    let rotation = Rotation2::new(self.angle);
+    let offset = rotation * (frame - self.anchor);
+    self.velocity + rotation * velocity + Vector2::new(-offset.y, offset.x) * self.angular_velocity
+  }
+}
+
+pub struct RotatingFrame {
+  pub primary: usize,
+  pub secondary: usize,
+  anchor: Point2<f32>,
+  start_angle: f32,
+  history: VecDeque<(f64, Pose)>,   // Simulated time of each step and the pose after it, oldest first
+}
+
+impl RotatingFrame {
+  // None if either body is missing, or they are in the same place so there is no line between them
+  pub fn new(simulation: &Simulation, primary: usize, secondary: usize) -> Option<Self> {
+    assert!(primary != secondary, "A rotating frame needs two different bodies, got {} twice", primary);
+    // Measured from nothing, to find where the frame starts
+    let start = Self::measure(simulation, primary, secondary, Point2::origin(), 0.0)?;
+
+    let mut frame = Self {
+      primary,
+      secondary,
+      anchor: start.barycentre,
+      start_angle: start.angle,
+      history: VecDeque::new(),
+    };
+    frame.record(simulation, 0.0);
+    Some(frame)
+  }
+
+  fn measure(simulation: &Simulation, primary: usize, secondary: usize, anchor: Point2<f32>, start_angle: f32) -> Option<Pose> {
+    let (primary, secondary) = (simulation.planets.get(&primary)?.borrow(), simulation.planets.get(&secondary)?.borrow());
+    let (m1, m2) = (primary.total_mass(), secondary.total_mass());
+    let separation = secondary.position - primary.position;
+    if separation.magnitude_squared() == 0.0 || m1 + m2 <= 0.0 {
+      return None;
+    }
+
+    let relative_velocity = secondary.velocity - primary.velocity;
+    Some(Pose {
+      barycentre: Point2::from((primary.position.coords * m1 + secondary.position.coords * m2)/(m1 + m2)),
+      velocity: (primary.velocity * m1 + secondary.velocity * m2)/(m1 + m2),
+      angle: separation.y.atan2(separation.x) - start_angle,
+      angular_velocity: separation.perp(&relative_velocity)/separation.magnitude_squared(),
+      anchor,
+    })
+  }
+
+  // Notes the pose after a step, keeping `keep` simulated seconds of them for drawing trails. Returns false once
+  // either body has gone, after which the frame is no use.
+  pub fn record(&mut self, simulation: &Simulation, keep: f64) -> bool {
+    let Some(pose) = Self::measure(simulation, self.primary, self.secondary, self.anchor, self.start_angle) else {
+      return false
+    };
+
+    let time = simulation.time;
+    self.history.push_back((time, pose));
+    while self.history.front().is_some_and(|(first, _)| time - first > keep) {
+      self.history.pop_front();
+    }
+    true
+  }
+
+  pub fn current(&self) -> Pose {
+    self.history.back().unwrap().1
+  }
+
+  // The pose at the first recorded step at or after `time`, or None if that is from before the oldest kept
+  pub fn pose_at(&self, time: f64) -> Option<Pose> {
+    const TIME_TOLERANCE: f64 = 1.0e-9;
+    if self.history.front().is_none_or(|(first, _)| time < first - TIME_TOLERANCE) {
+      return None;
+    }
+    let index = self.history.partition_point(|(recorded, _)| *recorded < time - TIME_TOLERANCE);
+    Some(self.history.get(index).unwrap_or(self.history.back().unwrap()).1)
+  }
+
+  pub fn describe(&self) -> String {
+    format!("rotating with bodies {} and {}", self.primary, self.secondary)
+  }
+}
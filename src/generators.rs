@@ -0,0 +1,698 @@
+use nalgebra::{Point2, Vector2, Vector3};
+
+use rand::prelude::*;
+use rand::distributions::Uniform;
+use rand_distr::StandardNormal;
+
+use serde::Deserialize;
+
+use std::f32::consts::PI;
+
+use crate::tools;
+use crate::TWO_PI;
+use crate::planet::{Planet, Halo};
+use crate::simulation::Simulation;
+
+// Deepest King potential allowed. Past this the tidal radius runs out to thousands of core radii, and the
+// profile is too steep to sample from a few hundred bodies.
+pub const KING_MAX_W0: f32 = 15.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HaloKind {
+  None,
+  Static,             // A rigid Plummer halo carried by the nucleus
+  Particles(usize),   // A Plummer sphere made of this many bodies
+}
+
+// A disk galaxy: a central nucleus, a Plummer bulge, an exponential disk and an optional halo
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GalaxySpec {
+  pub nucleus_mass: f32,
+  pub nucleus_radius: f32,
+  pub bulge_mass: f32,
+  pub bulge_particles: usize,
+  pub bulge_scale_radius: f32,
+  pub disk_mass: f32,
+  pub disk_particles: usize,
+  pub disk_scale_length: f32,
+  pub disk_radius: f32,           // Disk is truncated here
+  pub disk_toomre_q: f32,         // Stability of the disk against clumping, 0 for a cold disk
+  pub halo: HaloKind,
+  pub halo_mass: f32,
+  pub halo_scale_radius: f32,
+  pub particle_radius: f32,       // Size of every bulge, disk and halo body
+  pub clockwise: bool,            // Direction the disk rotates
+}
+
+impl Default for GalaxySpec {
+  fn default() -> Self {
+    Self {
+      nucleus_mass: 5.0e8,
+      nucleus_radius: 3.0,
+      bulge_mass: 1.0e8,
+      bulge_particles: 60,
+      bulge_scale_radius: 15.0,
+      disk_mass: 2.0e8,
+      disk_particles: 300,
+      disk_scale_length: 40.0,
+      disk_radius: 150.0,
+      disk_toomre_q: 1.5,
+      halo: HaloKind::Static,
+      halo_mass: 2.0e9,
+      halo_scale_radius: 80.0,
+      particle_radius: 0.4,
+      clockwise: true,
+    }
+  }
+}
+
+impl GalaxySpec {
+  pub fn total_mass(&self) -> f32 {
+    let halo_mass = if self.halo == HaloKind::None { 0.0 } else { self.halo_mass };
+    self.nucleus_mass + self.placed_bulge_mass() + self.placed_disk_mass() + halo_mass
+  }
+
+  // A bulge or disk without particles isn't there, so none of its mass counts
+  fn placed_bulge_mass(&self) -> f32 {
+    if self.bulge_particles > 0 { self.bulge_mass } else { 0.0 }
+  }
+
+  fn placed_disk_mass(&self) -> f32 {
+    if self.disk_particles > 0 { self.disk_mass } else { 0.0 }
+  }
+}
+
+// Two-body orbit the centres of two galaxies start on
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncounterSpec {
+  pub eccentricity: f32,    // 1 for parabolic, above 1 for hyperbolic
+  pub pericentre: f32,      // Closest approach of the two centres
+  pub separation: f32,      // Starting distance between the centres
+  pub clockwise: bool,      // Direction the galaxies orbit each other
+}
+
+const PLUMMER_MAX_RADIUS: f32 = 10.0;   // In scale radii, to avoid a handful of bodies starting far away
+
+// Helpers for filling a simulation with groups of bodies
+impl Simulation {
+  // Spawns a planet surrounded by a disk of moons.
+  // Each moon orbits the mass enclosed by its orbit (the main planet plus every moon further in) rather
+  // than the main planet alone, so a heavy disk starts close to equilibrium instead of heating up.
+  // With a Toomre Q target the moons also get random velocities matching the dispersion that gives the disk
+  // that stability: sigma_R = 3.36 Q G Sigma/kappa radially, and sigma_R kappa/(2 Omega) tangentially.
+  #[allow(clippy::too_many_arguments)]
+  pub fn add_planet_with_moons(
+    &mut self,
+    position: Point2<f32>,
+    velocity: Option<Vector2<f32>>,
+    main_planet_mass: Option<f32>,
+    main_planet_radius: f32,
+    moon_num: usize,
+    moon_orbit_radius_range: (f32, f32),    // Starting from surface of planet
+    moon_body_radius_range: (f32, f32),
+    moon_eccentricity_range: (f32, f32),
+    orbit_direction_clockwise: bool,  // anticlockwise = false, clockwise = true
+    toomre_q: Option<f32>,
+  ) {
+    assert!(moon_eccentricity_range.0 >= 0.0 && moon_eccentricity_range.1 < 1.0);
+
+    let main_planet_id = self.add_planet(position, velocity, main_planet_mass, main_planet_radius, None);  // Add main planet
+    let (main_planet_mass, frame_velocity) = {
+      let p = self.planets.get(&main_planet_id).unwrap().borrow();
+      (p.mass, p.velocity)
+    };
+    
+    let g = self.config().gravitational_constant;
+    let mut rng = self.generator_rng();
+
+    let orbit_rad_range = Uniform::from(moon_orbit_radius_range.0..moon_orbit_radius_range.1);
+    let angle_range = Uniform::from(0.0..TWO_PI);
+    let size_rad_range = Uniform::from(moon_body_radius_range.0..moon_body_radius_range.1);
+    let eccentricity_range = Uniform::new_inclusive(moon_eccentricity_range.0, moon_eccentricity_range.1);
+
+    // Moons with their orbit radius, innermost first
+    let mut moons: Vec<(Planet, f32)> = (0..moon_num)
+      .map(|_| {
+        let moon_radius = size_rad_range.sample(&mut rng);
+        let orbit_radius = main_planet_radius + orbit_rad_range.sample(&mut rng);
+        (self.new_planet(position, None, None, moon_radius, None), orbit_radius)
+      })
+      .collect();
+    moons.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut enclosed_mass = Vec::with_capacity(moons.len());
+    let mut total = main_planet_mass;
+    for (moon, _) in moons.iter() {
+      enclosed_mass.push(total);
+      total += moon.mass;
+    }
+
+    let surface_density = toomre_q.map(|_| {
+      let rings: Vec<(f32, f32)> = moons.iter().map(|(moon, r)| (*r, moon.mass)).collect();
+      Self::disk_surface_density(&rings)
+    });
+    let sense = if orbit_direction_clockwise { 1.0 } else { -1.0 };
+
+    for (i, (mut moon, orbit_radius)) in moons.into_iter().enumerate() {
+      let mu = g * (enclosed_mass[i] + moon.mass);
+      let start_angle = angle_range.sample(&mut rng);      // Angle from main planet to moon
+      let eccentricity = eccentricity_range.sample(&mut rng);
+      let true_anomaly = angle_range.sample(&mut rng);      // Where along its orbit the moon starts
+
+      // Choose the orbit that passes through the starting position at this true anomaly
+      let (start_pos, mut start_velocity) = tools::orbit_state(
+        mu,
+        orbit_radius * (1.0 + eccentricity * true_anomaly.cos()),
+        eccentricity,
+        start_angle - sense * true_anomaly,
+        true_anomaly,
+        orbit_direction_clockwise,
+      ).expect("Closed orbits reach every true anomaly");
+
+      if let (Some(q), Some(surface_density)) = (toomre_q, surface_density.as_ref()) {
+        let omega_squared = mu/orbit_radius.powi(3);
+        // kappa^2 = R dOmega^2/dR + 4 Omega^2, with dM/dR = 2 pi R Sigma
+        let kappa = (omega_squared + TWO_PI * g * surface_density[i]/orbit_radius).sqrt();
+        start_velocity += Self::toomre_dispersion(&mut rng, g, q, surface_density[i], omega_squared, kappa, start_pos);
+      }
+
+      moon.position += start_pos;
+      moon.velocity = start_velocity + frame_velocity;  // Add velocity of main planet
+      self.add_planet_raw(moon);
+    }
+  }
+
+  // Surface density at each ring radius, estimated from the mass of its neighbours in radius divided by
+  // the area of the annulus they span. Expects (radius, mass) pairs sorted by radius.
+  fn disk_surface_density(rings: &[(f32, f32)]) -> Vec<f32> {
+    const NEIGHBOURS: usize = 8;    // Either side
+
+    (0..rings.len())
+      .map(|i| {
+        let lo = i.saturating_sub(NEIGHBOURS);
+        let hi = (i + NEIGHBOURS).min(rings.len() - 1);
+        let mass: f32 = rings[lo..=hi].iter().map(|(_, mass)| mass).sum();
+        let area = PI * (rings[hi].0.powi(2) - rings[lo].0.powi(2));
+        if area > 0.0 { mass/area } else { 0.0 }
+      })
+      .collect()
+  }
+
+  // Random velocity for a disk body at `offset` from the centre, drawn from the dispersions that give a
+  // disk the Toomre stability Q:  sigma_R = 3.36 Q G Sigma/kappa,  sigma_phi = sigma_R kappa/(2 Omega)
+  fn toomre_dispersion(rng: &mut impl Rng, g: f32, q: f32, surface_density: f32, omega_squared: f32, kappa: f32, offset: Vector2<f32>) -> Vector2<f32> {
+    let sigma_r = 3.36 * q * g * surface_density/kappa;
+    let sigma_t = sigma_r * kappa/(2.0 * omega_squared.sqrt());
+
+    let radial = offset.normalize();
+    let tangential = Vector2::new(-radial.y, radial.x);
+    let normal_r: f32 = rng.sample(StandardNormal);
+    let normal_t: f32 = rng.sample(StandardNormal);
+    radial * sigma_r * normal_r + tangential * sigma_t * normal_t
+  }
+
+  pub fn add_random_planets(&mut self, n: usize, x_range: (f32, f32), y_range: (f32, f32), radius_range: (f32, f32), speed_range: Option<(f32, f32)>) {
+    assert!(x_range.1 > x_range.0);
+    assert!(y_range.1 > y_range.0);
+    assert!(radius_range.1 > radius_range.0);
+    assert!(n > 0);
+
+    let mut rng = self.generator_rng();
+    
+    for _ in 0..n {
+      let x_pos = rng.gen_range(x_range.0..x_range.1);
+      let y_pos = rng.gen_range(y_range.0..y_range.1);
+      let radius = rng.gen_range(radius_range.0..radius_range.1);
+
+      let velocity = if let Some(speed_range) = speed_range {
+        assert!(speed_range.1 > speed_range.0);
+
+        let speed = rng.gen_range(speed_range.0..speed_range.1);
+        let angle = rng.gen_range(0.0..TWO_PI);
+        Some(tools::get_components(speed, angle))
+      } else {
+        None
+      };
+
+      self.add_planet(
+        Point2::new(x_pos, y_pos),
+        velocity,
+        None,
+        radius,
+        None,
+      );
+    }
+  }
+
+  pub fn spawn_square_of_planets(
+    &mut self,
+    top_left: Point2<f32>,
+    w: u16,
+    h: u16,
+    gap: f32,
+    rad: f32,
+  ) {
+    for i in 0..w {
+      for j in 0..h {
+        self.add_planet(
+          Point2::new(top_left.x + i as f32 * gap, top_left.y + j as f32 * gap),
+          None,
+          None,
+          rad,
+          None,
+        );
+      }
+    }
+  }
+
+  // Plummer sphere, sampled in 3D and projected onto the plane (Aarseth, Henon & Wielen 1974).
+  // Radii come from inverting the cumulative mass M(<r) = M r^3/(r^2 + a^2)^(3/2), and speeds from the
+  // isotropic distribution function g(q) = q^2 (1 - q^2)^(7/2), where q is the fraction of the local escape speed.
+  // Returns the IDs of the new bodies.
+  #[allow(clippy::too_many_arguments)]
+  pub fn add_plummer_cluster(
+    &mut self,
+    centre: Point2<f32>,
+    velocity: Option<Vector2<f32>>,
+    n: usize,
+    total_mass: f32,
+    scale_radius: f32,
+    body_radius: f32,
+    virialise: bool,
+  ) -> Vec<usize> {
+    assert!(n > 0);
+
+    let g = self.config().gravitational_constant;
+    let mut rng = self.generator_rng();
+    let samples = (0..n)
+      .map(|_| {
+        let radius = Self::plummer_radius(&mut rng);
+
+        // Von Neumann rejection, g(q) peaks at about 0.092
+        let q = loop {
+          let q: f32 = rng.gen();
+          if rng.gen::<f32>() * 0.1 < q.powi(2) * (1.0 - q.powi(2)).powf(3.5) {
+            break q;
+          }
+        };
+        let escape_speed = (2.0 * g * total_mass/scale_radius).sqrt() * (1.0 + radius.powi(2)).powf(-0.25);
+
+        (
+          Self::random_direction(&mut rng) * radius * scale_radius,
+          Self::random_direction(&mut rng) * q * escape_speed,
+        )
+      })
+      .collect();
+
+    self.add_cluster(centre, velocity, total_mass/n as f32, body_radius, samples, virialise)
+  }
+
+  // King (1966) model with dimensionless central potential w0, sampled in 3D and projected onto the plane.
+  // The density profile comes from integrating Poisson's equation outwards from the centre until the
+  // potential reaches zero at the tidal radius; speeds then follow the lowered isothermal distribution
+  // f(v) ~ v^2 (e^(W - v^2/2) - 1) for the local potential W (in units of the velocity dispersion).
+  // Returns the IDs of the new bodies.
+  #[allow(clippy::too_many_arguments)]
+  pub fn add_king_cluster(
+    &mut self,
+    centre: Point2<f32>,
+    velocity: Option<Vector2<f32>>,
+    n: usize,
+    total_mass: f32,
+    core_radius: f32,
+    w0: f32,
+    body_radius: f32,
+    virialise: bool,
+  ) -> Vec<usize> {
+    assert!(n > 0);
+    assert!(w0 > 0.0 && w0 <= KING_MAX_W0, "w0 must be in (0, {}], got {}", KING_MAX_W0, w0);
+
+    let g = self.config().gravitational_constant;
+    let profile = KingProfile::solve(w0);
+    // r0^2 = 9 sigma^2/(4 pi G rho0), with rho0 = M/(r0^3 M~) for the dimensionless mass M~
+    let sigma = (4.0 * PI * g * total_mass/(9.0 * core_radius * profile.total_mass())).sqrt();
+
+    let mut rng = self.generator_rng();
+    let samples = (0..n)
+      .map(|_| {
+        let (radius, w) = profile.sample_radius(rng.gen());
+
+        // Rejection sample the speed, bounding f(v) by its peak over a coarse grid
+        let max_speed = (2.0 * w).sqrt();
+        let f = |v: f32| v.powi(2) * ((w - v.powi(2)/2.0).exp() - 1.0);
+        let f_max = (1..=50).map(|i| f(max_speed * i as f32/50.0)).fold(0.0, f32::max) * 1.1;
+        let speed = loop {
+          let v = rng.gen::<f32>() * max_speed;
+          if rng.gen::<f32>() * f_max < f(v) {
+            break v;
+          }
+        };
+
+        (
+          Self::random_direction(&mut rng) * radius * core_radius,
+          Self::random_direction(&mut rng) * speed * sigma,
+        )
+      })
+      .collect();
+
+    self.add_cluster(centre, velocity, total_mass/n as f32, body_radius, samples, virialise)
+  }
+
+  // Builds a disk galaxy around `centre`. Every component moves in the potential of the whole galaxy, with
+  // each treated as spherical: the bulge and halo bodies get isotropic Jeans dispersions, and the disk bodies
+  // circular orbits plus the random motion that gives the disk a Toomre Q of `disk_toomre_q`.
+  // Particles still merge on close passes in `CollisionMode::Merge`, so use soft-sphere contacts to keep a
+  // galaxy close to collisionless over long runs.
+  // Returns the IDs of the new bodies, nucleus first.
+  pub fn add_galaxy(&mut self, centre: Point2<f32>, velocity: Option<Vector2<f32>>, spec: &GalaxySpec) -> Vec<usize> {
+    assert!(spec.disk_radius > 0.0 && spec.disk_scale_length > 0.0);
+    assert!(spec.disk_radius > 3.0 * spec.nucleus_radius, "The disk must reach past 3 nucleus radii");
+
+    let frame_velocity = velocity.unwrap_or_else(|| Vector2::new(0.0, 0.0));
+    let mut nucleus = Planet::new(0, centre, Some(frame_velocity), spec.nucleus_mass, spec.nucleus_radius, None);
+    if spec.halo == HaloKind::Static {
+      nucleus.halo = Some(Halo { mass: spec.halo_mass, scale_radius: spec.halo_scale_radius });
+    }
+    let mut ids = vec![self.add_planet_raw(nucleus)];
+
+    // Exponential disk, Sigma ~ e^(-R/Rd), truncated at the disk radius
+    let disk_truncation = spec.disk_radius/spec.disk_scale_length;
+    let disk_norm = 1.0 - (1.0 + disk_truncation) * (-disk_truncation).exp();
+    let disk_enclosed = |r: f32| {
+      let x = (r/spec.disk_scale_length).min(disk_truncation);
+      spec.placed_disk_mass() * (1.0 - (1.0 + x) * (-x).exp())/disk_norm
+    };
+    let plummer_enclosed = |mass: f32, a: f32, r: f32| mass * r.powi(3)/(r.powi(2) + a.powi(2)).powf(1.5);
+    let halo_mass = if spec.halo == HaloKind::None { 0.0 } else { spec.halo_mass };
+    let enclosed_mass = |r: f32| spec.nucleus_mass + disk_enclosed(r) +
+      plummer_enclosed(spec.placed_bulge_mass(), spec.bulge_scale_radius, r) +
+      plummer_enclosed(halo_mass, spec.halo_scale_radius, r);
+
+    if spec.bulge_particles > 0 {
+      ids.extend(self.add_jeans_plummer_sphere(
+        centre, velocity, spec.bulge_particles, spec.bulge_mass, spec.bulge_scale_radius, spec.particle_radius, enclosed_mass,
+      ));
+    }
+    if let HaloKind::Particles(n) = spec.halo {
+      if n > 0 {
+        ids.extend(self.add_jeans_plummer_sphere(
+          centre, velocity, n, spec.halo_mass, spec.halo_scale_radius, spec.particle_radius, enclosed_mass,
+        ));
+      }
+    }
+
+    // The radius of an exponential disk body follows a gamma(2) distribution. Keep clear of the nucleus,
+    // where orbits are too tight to follow.
+    let inner_radius = 3.0 * spec.nucleus_radius;
+    let g = self.config().gravitational_constant;
+    let mut rng = self.generator_rng();
+    let particle_mass = spec.disk_mass/spec.disk_particles.max(1) as f32;
+    let sense = if spec.clockwise { 1.0 } else { -1.0 };
+    let omega_squared = |r: f32| g * enclosed_mass(r)/r.powi(3);
+
+    for _ in 0..spec.disk_particles {
+      let r = Self::exponential_disk_radius(&mut rng, spec.disk_scale_length, inner_radius, spec.disk_radius);
+      let angle = rng.gen_range(0.0..TWO_PI);
+      let offset = tools::get_components(r, angle);
+      let mut disk_velocity = tools::get_components(tools::circular_orbit_speed(g, enclosed_mass(r), r), angle + sense * PI/2.0);
+
+      if spec.disk_toomre_q > 0.0 {
+        // kappa^2 = R dOmega^2/dR + 4 Omega^2
+        let h = 0.01 * r;
+        let kappa_squared = r * (omega_squared(r + h) - omega_squared(r - h))/(2.0 * h) + 4.0 * omega_squared(r);
+        let surface_density = spec.disk_mass/(TWO_PI * spec.disk_scale_length.powi(2) * disk_norm) * (-r/spec.disk_scale_length).exp();
+        disk_velocity += Self::toomre_dispersion(
+          &mut rng, g, spec.disk_toomre_q, surface_density, omega_squared(r), kappa_squared.max(0.0).sqrt(), offset,
+        );
+      }
+
+      ids.push(self.add_planet(
+        centre + offset,
+        Some(frame_velocity + disk_velocity),
+        Some(particle_mass),
+        spec.particle_radius,
+        None,
+      ));
+    }
+
+    ids
+  }
+
+  // Two disk galaxies on a parabolic or hyperbolic encounter, with their barycentre at `centre` and the
+  // second galaxy starting to the right of the first. Returns the IDs of each galaxy's bodies.
+  pub fn add_galaxy_merger(
+    &mut self,
+    centre: Point2<f32>,
+    galaxy1: &GalaxySpec,
+    galaxy2: &GalaxySpec,
+    encounter: &EncounterSpec,
+  ) -> (Vec<usize>, Vec<usize>) {
+    assert!(encounter.eccentricity >= 1.0);
+    assert!(encounter.pericentre > 0.0 && encounter.separation >= encounter.pericentre);
+
+    let g = self.config().gravitational_constant;
+    let (m1, m2) = (galaxy1.total_mass(), galaxy2.total_mass());
+    let e = encounter.eccentricity;
+    let semi_latus_rectum = encounter.pericentre * (1.0 + e);
+    // Inbound, at the true anomaly where the orbit is `separation` apart
+    let true_anomaly = -((semi_latus_rectum/encounter.separation - 1.0)/e).clamp(-1.0, 1.0).acos();
+    let sense = if encounter.clockwise { 1.0 } else { -1.0 };
+
+    let (separation, relative_velocity) = tools::orbit_state(
+      g * (m1 + m2),
+      semi_latus_rectum,
+      e,
+      -sense * true_anomaly,    // Puts the starting separation along the x axis
+      true_anomaly,
+      encounter.clockwise,
+    ).expect("Starting separation is reached on the way in");
+
+    let mass_fraction = m2/(m1 + m2);
+    (
+      self.add_galaxy(centre - separation * mass_fraction, Some(-relative_velocity * mass_fraction), galaxy1),
+      self.add_galaxy(centre + separation * (1.0 - mass_fraction), Some(relative_velocity * (1.0 - mass_fraction)), galaxy2),
+    )
+  }
+
+  // Plummer sphere whose velocities balance the potential of everything else around it too, given as the
+  // mass enclosed within each radius. Speeds are Gaussian with the isotropic Jeans dispersion
+  //   sigma^2(r) = 1/rho(r) * integral from r to infinity of rho(r') G M(r')/r'^2 dr'
+  // Returns the IDs of the new bodies.
+  #[allow(clippy::too_many_arguments)]
+  fn add_jeans_plummer_sphere(
+    &mut self,
+    centre: Point2<f32>,
+    velocity: Option<Vector2<f32>>,
+    n: usize,
+    total_mass: f32,
+    scale_radius: f32,
+    body_radius: f32,
+    enclosed_mass: impl Fn(f32) -> f32,
+  ) -> Vec<usize> {
+    const STEPS: usize = 500;
+    let g = self.config().gravitational_constant;
+    const INNER_RADIUS: f32 = 0.01;   // In scale radii, inside this the dispersion is held constant
+
+    let density = |r: f32| (1.0 + (r/scale_radius).powi(2)).powf(-2.5);
+    let radii: Vec<f32> = (0..=STEPS)
+      .map(|i| scale_radius * (INNER_RADIUS + (PLUMMER_MAX_RADIUS - INNER_RADIUS) * i as f32/STEPS as f32))
+      .collect();
+    let integrand: Vec<f32> = radii.iter().map(|r| density(*r) * g * enclosed_mass(*r)/r.powi(2)).collect();
+
+    // Integrate inwards from the truncation radius
+    let mut sigma_squared = vec![0.0; radii.len()];
+    let mut integral = 0.0;
+    for i in (0..STEPS).rev() {
+      integral += 0.5 * (integrand[i] + integrand[i + 1]) * (radii[i + 1] - radii[i]);
+      sigma_squared[i] = integral/density(radii[i]);
+    }
+    let dispersion = |r: f32| {
+      let t = ((r/scale_radius - INNER_RADIUS)/(PLUMMER_MAX_RADIUS - INNER_RADIUS) * STEPS as f32).clamp(0.0, STEPS as f32);
+      let i = (t as usize).min(STEPS - 1);
+      (sigma_squared[i] + (t - i as f32) * (sigma_squared[i + 1] - sigma_squared[i])).sqrt()
+    };
+
+    let mut rng = self.generator_rng();
+    let samples = (0..n)
+      .map(|_| {
+        let radius = Self::plummer_radius(&mut rng) * scale_radius;
+        let sigma = dispersion(radius);
+        let gaussian = Vector3::from_fn(|_, _| rng.sample::<f32, _>(StandardNormal));
+        (Self::random_direction(&mut rng) * radius, gaussian * sigma)
+      })
+      .collect();
+
+    self.add_cluster(centre, velocity, total_mass/n as f32, body_radius, samples, false)
+  }
+
+  // Radius of a random body in a Plummer sphere, in scale radii, from inverting M(<r) = M r^3/(r^2 + 1)^(3/2)
+  fn plummer_radius(rng: &mut impl Rng) -> f32 {
+    loop {
+      let x: f32 = rng.gen_range(f32::EPSILON..1.0);
+      let r = 1.0/(x.powf(-2.0/3.0) - 1.0).sqrt();
+      if r < PLUMMER_MAX_RADIUS {
+        return r;
+      }
+    }
+  }
+
+  // Radius of a random body in an exponential disk with scale length `h`, limited to [min, max]. Radii follow
+  // a gamma(2) distribution, with survival function S(x) = (1 + x) e^-x for x = r/h. That is inverted by
+  // bisection on ln S, which stays finite however many scale lengths out the range is.
+  fn exponential_disk_radius(rng: &mut impl Rng, h: f32, min: f32, max: f32) -> f32 {
+    let ln_survival = |x: f64| x.ln_1p() - x;
+    let (mut low, mut high) = (min as f64/h as f64, max as f64/h as f64);
+    let (ln_low, ln_high) = (ln_survival(low), ln_survival(high));
+    // S(r) is uniform between S(max) and S(min): S = S(min) (1 - u (1 - S(max)/S(min)))
+    let span = -(ln_high - ln_low).exp_m1();
+    let target = ln_low + (-rng.gen::<f64>() * span).ln_1p();
+
+    for _ in 0..60 {
+      let middle = (low + high)/2.0;
+      if ln_survival(middle) > target {
+        low = middle;
+      } else {
+        high = middle;
+      }
+    }
+    (((low + high)/2.0 * h as f64) as f32).clamp(min, max)
+  }
+
+  fn random_direction(rng: &mut impl Rng) -> Vector3<f32> {
+    let z: f32 = rng.gen_range(-1.0..1.0);
+    let angle = rng.gen_range(0.0..TWO_PI);
+    let xy = (1.0 - z.powi(2)).sqrt();
+    Vector3::new(xy * angle.cos(), xy * angle.sin(), z)
+  }
+
+  // Projects 3D positions and velocities onto the plane and adds them as bodies around `centre`.
+  // The sample's own centre of mass drift is removed so the cluster as a whole moves at `velocity`.
+  // Projection leaves a 3D equilibrium out of balance in the plane, so `virialise` rescales the internal
+  // velocities until 2K = -W for the projected positions.
+  fn add_cluster(
+    &mut self,
+    centre: Point2<f32>,
+    velocity: Option<Vector2<f32>>,
+    body_mass: f32,
+    body_radius: f32,
+    samples: Vec<(Vector3<f32>, Vector3<f32>)>,
+    virialise: bool,
+  ) -> Vec<usize> {
+    let n = samples.len() as f32;
+    let mut positions: Vec<Vector2<f32>> = samples.iter().map(|(r, _)| r.xy()).collect();
+    let mut velocities: Vec<Vector2<f32>> = samples.iter().map(|(_, v)| v.xy()).collect();
+
+    let mean_position = positions.iter().sum::<Vector2<f32>>()/n;
+    let mean_velocity = velocities.iter().sum::<Vector2<f32>>()/n;
+    positions.iter_mut().for_each(|r| *r -= mean_position);
+    velocities.iter_mut().for_each(|v| *v -= mean_velocity);
+
+    if virialise {
+      let g = self.config().gravitational_constant;
+      let kinetic: f64 = velocities.iter().map(|v| 0.5 * body_mass as f64 * v.norm_squared() as f64).sum();
+      let mut potential = 0.0_f64;
+      for (i, r1) in positions.iter().enumerate() {
+        for r2 in positions[i+1..].iter() {
+          // Softened by the body size, since bodies closer than that collide rather than orbit
+          let dist = (r2 - r1).norm().max(2.0 * body_radius) as f64;
+          potential -= (g * body_mass * body_mass) as f64/dist;
+        }
+      }
+      if kinetic > 0.0 {
+        let scale = (-potential/(2.0 * kinetic)).sqrt() as f32;
+        velocities.iter_mut().for_each(|v| *v *= scale);
+      }
+    }
+
+    let frame_velocity = velocity.unwrap_or_else(|| Vector2::new(0.0, 0.0));
+    positions.into_iter().zip(velocities)
+      .map(|(r, v)| self.add_planet(centre + r, Some(v + frame_velocity), Some(body_mass), body_radius, None))
+      .collect()
+  }
+}
+
+// Dimensionless King model, in units of the King radius r0 and central density rho0
+struct KingProfile {
+  radii: Vec<f32>,
+  potentials: Vec<f32>,
+  cumulative_mass: Vec<f32>,
+}
+
+impl KingProfile {
+  // Density relative to the centre is rho(W) ~ e^W erf(sqrt W) - sqrt(4W/pi) (1 + 2W/3)
+  fn density(w: f64) -> f64 {
+    if w <= 0.0 {
+      return 0.0;
+    } else if w < 0.05 {
+      // The two terms nearly cancel here, so use the leading term of the series instead
+      return 8.0/(15.0 * std::f64::consts::PI.sqrt()) * w.powf(2.5);
+    }
+    w.exp() * erf(w.sqrt()) - (4.0 * w/std::f64::consts::PI).sqrt() * (1.0 + 2.0 * w/3.0)
+  }
+
+  // Integrates W'' + 2W'/r = -9 rho(W)/rho(W0) with RK4 until W reaches zero at the tidal radius
+  fn solve(w0: f32) -> Self {
+    const STEP: f64 = 1.0e-3;
+    let w0 = w0 as f64;
+    let rho0 = Self::density(w0);
+    let derivs = |r: f64, w: f64, dw: f64| -> (f64, f64) {
+      (dw, -9.0 * Self::density(w)/rho0 - 2.0 * dw/r)
+    };
+
+    // Start slightly off centre using the series W = W0 - 3/2 r^2 to avoid the 1/r singularity
+    let mut r = STEP;
+    let mut w = w0 - 1.5 * r * r;
+    let mut dw = -3.0 * r;
+    let mut mass = 4.0/3.0 * std::f64::consts::PI * r.powi(3);
+
+    let mut profile = Self {
+      radii: vec![0.0, r as f32],
+      potentials: vec![w0 as f32, w as f32],
+      cumulative_mass: vec![0.0, mass as f32],
+    };
+
+    while w > 0.0 {
+      let (k1w, k1d) = derivs(r, w, dw);
+      let (k2w, k2d) = derivs(r + STEP/2.0, w + k1w * STEP/2.0, dw + k1d * STEP/2.0);
+      let (k3w, k3d) = derivs(r + STEP/2.0, w + k2w * STEP/2.0, dw + k2d * STEP/2.0);
+      let (k4w, k4d) = derivs(r + STEP, w + k3w * STEP, dw + k3d * STEP);
+      w += STEP/6.0 * (k1w + 2.0 * k2w + 2.0 * k3w + k4w);
+      dw += STEP/6.0 * (k1d + 2.0 * k2d + 2.0 * k3d + k4d);
+      r += STEP;
+      mass += 4.0 * std::f64::consts::PI * r.powi(2) * Self::density(w.max(0.0))/rho0 * STEP;
+
+      profile.radii.push(r as f32);
+      profile.potentials.push(w.max(0.0) as f32);
+      profile.cumulative_mass.push(mass as f32);
+    }
+
+    profile
+  }
+
+  fn total_mass(&self) -> f32 {
+    *self.cumulative_mass.last().unwrap()
+  }
+
+  // Radius and potential enclosing the given fraction of the mass
+  fn sample_radius(&self, fraction: f32) -> (f32, f32) {
+    let target = fraction * self.total_mass();
+    let i = self.cumulative_mass.partition_point(|m| *m < target).clamp(1, self.radii.len() - 1);
+    let (m0, m1) = (self.cumulative_mass[i - 1], self.cumulative_mass[i]);
+    let t = if m1 > m0 { (target - m0)/(m1 - m0) } else { 0.0 };
+    (
+      self.radii[i - 1] + t * (self.radii[i] - self.radii[i - 1]),
+      self.potentials[i - 1] + t * (self.potentials[i] - self.potentials[i - 1]),
+    )
+  }
+}
+
+// Error function (Abramowitz & Stegun 7.1.26, accurate to about 1e-7)
+fn erf(x: f64) -> f64 {
+  let t = 1.0/(1.0 + 0.3275911 * x.abs());
+  let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+  let y = 1.0 - poly * (-x * x).exp();
+  if x >= 0.0 { y } else { -y }
+}
@@ -0,0 +1,258 @@
+use nalgebra::{Vector2, Vector3};
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Reads state vectors saved from JPL Horizons (https://ssd.jpl.nasa.gov/horizons/) as a vector table, either in
+// the default layout or with CSV output turned on. Several tables can be pasted into one file, one per target.
+// Each table needs its header, which gives the target, the output units and the reference plane:
+//
+//   Target body name: Earth (399)                     {source: DE441}
+//   Output units    : KM-S
+//   Reference frame : Ecliptic of J2000.0
+//   $$SOE
+//   2451545.000000000 = A.D. 2000-Jan-01 12:00:00.0000 TDB
+//    X =-2.521092855899356E+07 Y = 1.449279195838006E+08 Z =-6.164165719002485E+02
+//    VX=-2.983983333677879E+01 VY=-5.207633902410673E+00 VZ= 6.168441184239981E-05
+//   $$EOE
+//
+// States are kept in kilometres and kilometres per second. Tables in the equatorial (ICRF) frame are rotated
+// onto the ecliptic; either way the simulation only sees the ecliptic plane, so Z is dropped when projecting.
+
+const OBLIQUITY_J2000: f64 = 84_381.448/3600.0;   // Degrees between the ICRF equator and the ecliptic of J2000
+const SECONDS_PER_DAY: f64 = 86_400.0;
+const KM_PER_AU: f64 = 1.495_978_707e8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StateVector {
+  pub epoch: f64,               // Julian date (TDB)
+  pub position: Vector3<f64>,   // km, ecliptic of J2000
+  pub velocity: Vector3<f64>,   // km/s
+}
+
+impl StateVector {
+  // Position and velocity in the ecliptic plane
+  pub fn projected(&self) -> (Vector2<f64>, Vector2<f64>) {
+    (self.position.xy(), self.velocity.xy())
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+  pub name: String,       // As Horizons names it, such as "Earth"
+  pub id: Option<i64>,    // Horizons ID, such as 399, if it has a numeric one
+  pub states: Vec<StateVector>,
+}
+
+impl Target {
+  // The state at `epoch`, or the first if none is asked for
+  pub fn state_at(&self, epoch: Option<f64>) -> Option<&StateVector> {
+    const EPOCH_TOLERANCE: f64 = 1.0e-6;   // Days, well under the output's precision
+    match epoch {
+      Some(epoch) => self.states.iter().find(|state| (state.epoch - epoch).abs() < EPOCH_TOLERANCE),
+      None => self.states.first(),
+    }
+  }
+
+  // Mass in kilograms, for the Sun, the planets, the Moon and Pluto
+  pub fn known_mass(&self) -> Option<f64> {
+    // GM in km^3/s^2, from the DE440 ephemeris
+    let gm = match self.id? {
+      10 => 1.327_124_400_419_4e11,
+      199 => 2.203_186_855_140e4,
+      299 => 3.248_585_920_790e5,
+      399 => 3.986_004_355_070e5,
+      301 => 4.902_800_118_457e3,
+      499 => 4.282_837_581_575e4,
+      599 => 1.266_865_319_000e8,
+      699 => 3.793_120_623_436e7,
+      799 => 5.793_951_256_528e6,
+      899 => 6.835_099_970_000e6,
+      999 => 8.696_138_177_608e2,
+      _ => return None,
+    };
+    Some(gm * 1.0e9/6.674_30e-11)
+  }
+}
+
+#[derive(Debug)]
+pub enum HorizonsError {
+  Io { path: PathBuf, source: io::Error },
+  Format { line: usize, reason: String },
+}
+
+impl fmt::Display for HorizonsError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      HorizonsError::Io { path, source } => write!(f, "couldn't read {}: {}", path.display(), source),
+      HorizonsError::Format { line, reason } => write!(f, "line {}: {}", line, reason),
+    }
+  }
+}
+
+impl std::error::Error for HorizonsError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      HorizonsError::Io { source, .. } => Some(source),
+      HorizonsError::Format { .. } => None,
+    }
+  }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Target>, HorizonsError> {
+  let path = path.as_ref();
+  let text = fs::read_to_string(path)
+    .map_err(|source| HorizonsError::Io { path: path.to_path_buf(), source })?;
+  parse(&text)
+}
+
+// What the header before each table says
+#[derive(Default)]
+struct Header {
+  target: Option<(String, Option<i64>)>,
+  units: Option<(f64, f64)>,    // km per unit of length, seconds per unit of time
+  equatorial: bool,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Target>, HorizonsError> {
+  let mut targets = Vec::new();
+  let mut header = Header::default();
+  let mut table: Option<(usize, Vec<f64>)> = None;   // Line the table started on, and the numbers read so far
+  let mut epoch_line = 0;
+
+  for (i, line) in text.lines().enumerate() {
+    let line_number = i + 1;
+    let error = |reason: String| HorizonsError::Format { line: line_number, reason };
+    let trimmed = line.trim();
+
+    if let Some((start, numbers)) = table.as_mut() {
+      if trimmed == "$$EOE" {
+        let (start, numbers) = (*start, std::mem::take(numbers));
+        table = None;
+        targets.push(finish_table(&header, start, &numbers, epoch_line)?);
+        continue;
+      }
+      if trimmed.is_empty() {
+        continue;
+      }
+      if trimmed.contains(',') {
+        // CSV: JD, calendar date, X, Y, Z, VX, VY, VZ, and maybe more
+        let fields: Vec<&str> = trimmed.split(',').map(str::trim).collect();
+        if fields.len() < 8 {
+          return Err(error(format!("expected a Julian date, calendar date and six state values, found {} columns", fields.len())));
+        }
+        for (column, field) in fields.iter().enumerate().filter(|(column, _)| *column != 1).take(7) {
+          numbers.push(field.parse().map_err(|_| error(format!("column {} isn't a number: {:?}", column + 1, field)))?);
+        }
+        continue;
+      }
+      // Default layout: a Julian date line, then lines of `LABEL = value` pairs
+      let spaced = trimmed.replace('=', " = ");
+      let tokens: Vec<&str> = spaced.split_whitespace().collect();
+      if tokens.get(1) == Some(&"=") && tokens.first().is_some_and(|token| token.parse::<f64>().is_ok()) {
+        if !numbers.len().is_multiple_of(7) {
+          return Err(HorizonsError::Format { line: epoch_line, reason: "state is missing some of X, Y, Z, VX, VY and VZ".to_string() });
+        }
+        numbers.push(tokens[0].parse().unwrap());
+        epoch_line = line_number;
+        continue;
+      }
+      for pair in tokens.chunks(3) {
+        match pair {
+          [label, "=", value] if ["X", "Y", "Z", "VX", "VY", "VZ"].contains(label) => {
+            if numbers.is_empty() {
+              return Err(error("state values before any Julian date".to_string()));
+            }
+            numbers.push(value.parse().map_err(|_| error(format!("{} isn't a number: {:?}", label, value)))?);
+          },
+          [_, "=", _] => (),    // LT, RG, RR and the like
+          _ => return Err(error(format!("can't read {:?}", trimmed))),
+        }
+      }
+      continue;
+    }
+
+    if trimmed == "$$SOE" {
+      if header.target.is_none() {
+        return Err(error("table has no `Target body name` above it".to_string()));
+      }
+      if header.units.is_none() {
+        return Err(error("table has no `Output units` above it".to_string()));
+      }
+      table = Some((line_number, Vec::new()));
+      continue;
+    }
+
+    let Some((key, value)) = line.split_once(':') else { continue };
+    let value = value.trim();
+    match key.trim() {
+      "Target body name" => {
+        // "Earth (399)   {source: DE441}", or a designation in place of the ID: "433 Eros (A898 PA)"
+        let value = value.split('{').next().unwrap().trim();
+        header.target = Some(match value.rsplit_once('(') {
+          Some((name, id)) => {
+            let id = id.trim_end_matches(')').trim();
+            match id.parse() {
+              Ok(id) => (name.trim().to_string(), Some(id)),
+              Err(_) => (value.to_string(), None),
+            }
+          },
+          None => (value.to_string(), None),
+        });
+      },
+      "Output units" => {
+        header.units = Some(match value.split_whitespace().next().unwrap_or("") {
+          "KM-S" => (1.0, 1.0),
+          "KM-D" => (1.0, SECONDS_PER_DAY),
+          "AU-D" => (KM_PER_AU, SECONDS_PER_DAY),
+          other => return Err(error(format!("unknown output units {:?}, expected KM-S, KM-D or AU-D", other))),
+        });
+      },
+      "Reference frame" | "Reference plane" | "Coordinate systm" => {
+        let value = value.to_lowercase();
+        if value.contains("ecliptic") {
+          header.equatorial = false;
+        } else if value.contains("icrf") || value.contains("frame") || value.contains("equator") {
+          header.equatorial = true;
+        }
+      },
+      _ => (),
+    }
+  }
+
+  if let Some((start, _)) = table {
+    return Err(HorizonsError::Format { line: start, reason: "table has no $$EOE".to_string() });
+  }
+  if targets.is_empty() {
+    return Err(HorizonsError::Format { line: 1, reason: "no $$SOE table found; is this a vector table?".to_string() });
+  }
+  Ok(targets)
+}
+
+// Turns a table's numbers, seven to a state, into a target in km, km/s and the ecliptic plane
+fn finish_table(header: &Header, start: usize, numbers: &[f64], epoch_line: usize) -> Result<Target, HorizonsError> {
+  if numbers.is_empty() {
+    return Err(HorizonsError::Format { line: start, reason: "table is empty".to_string() });
+  }
+  if !numbers.len().is_multiple_of(7) {
+    return Err(HorizonsError::Format { line: epoch_line, reason: "state is missing some of X, Y, Z, VX, VY and VZ".to_string() });
+  }
+
+  let (name, id) = header.target.clone().unwrap();
+  let (length, time) = header.units.unwrap();
+  let (sin, cos) = OBLIQUITY_J2000.to_radians().sin_cos();
+  let to_ecliptic = |v: Vector3<f64>| if header.equatorial {
+    Vector3::new(v.x, cos * v.y + sin * v.z, -sin * v.y + cos * v.z)
+  } else {
+    v
+  };
+
+  let states = numbers.chunks(7).map(|state| StateVector {
+    epoch: state[0],
+    position: to_ecliptic(Vector3::new(state[1], state[2], state[3]) * length),
+    velocity: to_ecliptic(Vector3::new(state[4], state[5], state[6]) * length/time),
+  }).collect();
+  Ok(Target { name, id, states })
+}
@@ -0,0 +1,24 @@
+pub mod tools;
+pub mod planet;
+pub mod config;
+pub mod units;
+pub mod frame;
+pub mod camera;
+pub mod simulation;
+pub mod barnes_hut;
+pub mod preview;
+pub mod generators;
+pub mod diagnostics;
+pub mod presets;
+pub mod horizons;
+pub mod scenario;
+pub mod snapshot;
+pub mod replay;
+pub mod export;
+pub mod cli;
+
+use std::f32::consts::PI;
+
+pub const TWO_PI: f32 = PI * 2.0;
+pub const ACC_DEBUG_VECTOR_MULTIPLIER: f32 = 5.0;
+pub const SCREEN_DIMS: (f32, f32) = (1280.0, 860.0);
@@ -1,48 +1,157 @@
-mod tools;
-mod planet;
-
 use ggez::event::{self};
 use ggez::graphics::{self, DrawParam, Mesh, MeshBuilder, Color, Canvas, DrawMode};
 use ggez::{Context, GameResult};
-use ggez::timer;
-use ggez::input::{mouse::MouseButton, keyboard::{KeyCode, KeyMods, KeyInput}};
+use ggez::input::{mouse::MouseButton, keyboard::{KeyCode, KeyInput, KeyMods}};
 
 use nalgebra::{Point2, Vector2};
 
-use rand::prelude::*;
-use rand::rngs::ThreadRng;
-use rand::distributions::Uniform;
-
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::cell::RefCell;
-use std::time::Duration;
-use std::f32::consts::PI;
+use std::path::PathBuf;
+
+use orbits::planet::PlanetTrail;
+use orbits::camera::{Camera, Follow};
+use orbits::frame::RotatingFrame;
+use orbits::preview::{self, Outcome};
+use orbits::presets::PeriodicOrbit;
+use orbits::simulation::{Simulation, Integrator, GravitySolver};
+use orbits::config::Setting;
+use orbits::units::{Conversion, Quantity, Units};
+use orbits::scenario::Scenario;
+use orbits::snapshot;
+use orbits::replay::{Replay, ReplayCursor, ReplayError, ReplayRecorder};
+use orbits::export::{ExportFormat, TrajectoryExporter};
+use orbits::cli::Args;
+use orbits::SCREEN_DIMS;
+
+// Loaded when no scenario file is given on the command line
+const DEFAULT_SCENARIO: &str = include_str!("../scenarios/default.toml");
+const QUICK_SAVE_PATH: &str = "quicksave.json";
+const RECORDING_PATH: &str = "recording.orbrec";
+const EXPORT_CSV_PATH: &str = "trajectories.csv";
+const EXPORT_COLUMNAR_PATH: &str = "trajectories";
+const PLAYBACK_SCRUB_SECONDS: f64 = 2.0;    // How far the arrow keys move through a recording
+// Preset orbits are scaled so one published unit of length is this many pixels, and unit mass is this heavy
+const PRESET_LENGTH_SCALE: f32 = 150.0;
+const PRESET_UNIT_MASS: f32 = 4.0e9;
+const PRESET_BODY_RADIUS: f32 = 2.0;
+// With a fixed timestep, simulated time falls behind rather than taking more than this many steps in a frame
+const MAX_STEPS_PER_FRAME: u32 = 1000;
+const DEFAULT_STEP: f32 = 1.0/60.0;       // Length of a single step taken while paused, without a fixed timestep
+const TIME_SCALE_RANGE: (f32, f32) = (1.0/64.0, 64.0);
+const SIM_RATE_SMOOTHING: f32 = 0.05;     // Weight of each new frame in the displayed sim/wall time ratio
+const ZOOM_PER_SCROLL: f32 = 1.1;         // Zoom factor for each step of the mouse wheel
+const EDIT_FACTOR: f32 = 1.25;            // How much + and - change the selected body's mass or radius
+const MIN_EDIT_MASS: f32 = 1.0;           // Smallest mass - takes the selected body down to, keeping its spin finite
+const MIN_EDIT_RADIUS: f32 = 0.1;         // Likewise for radius with Shift+-
+const VELOCITY_NUDGE: f32 = 2.0;          // Change in the selected body's velocity per arrow key, in pixels/s on screen
+const SELECTION_PANEL_WIDTH: f32 = 330.0;
+
+const USAGE: &str = "\
+Usage: orbits [scenario.toml] [options]
+
+Options:
+  --scenario <path>     Scenario file to load instead of the default scene
+  --width <pixels>      Window width (default 1280)
+  --height <pixels>     Window height (default 860)
+  --seed <n>            Seed for randomly generated bodies, so every restart builds the same scene
+  --dt <seconds>        Fixed simulation timestep (default: the time since the last frame)
+  --integrator <name>   euler or leapfrog (default euler)
+  --gravity <solver>    direct or barnes-hut (default direct)
+  --info                Start with the body info overlay shown (I)
+  --vectors             Start with the velocity and force vectors shown (D)
+";
+
+struct Options {
+  scenario_path: Option<PathBuf>,
+  window_dims: (f32, f32),
+  seed: Option<u64>,
+  fixed_dt: Option<f32>,
+  integrator: Integrator,
+  gravity_solver: GravitySolver,
+  show_planet_info_debug: bool,
+  show_vector_debug: bool,
+}
 
-use planet::{Planet, PlanetTrail, PLANET_DENSITY};
+impl Options {
+  fn parse(mut args: Args) -> Result<Options, String> {
+    let mut options = Options {
+      scenario_path: None,
+      window_dims: SCREEN_DIMS,
+      seed: None,
+      fixed_dt: None,
+      integrator: Integrator::default(),
+      gravity_solver: GravitySolver::default(),
+      show_planet_info_debug: false,
+      show_vector_debug: false,
+    };
 
-pub const G: f32 = 0.0001;    // Gravitational constant
-pub const TWO_PI: f32 = PI * 2.0;
-const SPAWN_PLANET_RADIUS: f32 = 5.0;
-const ACC_DEBUG_VECTOR_MULTIPLIER: f32 = 5.0;
-pub const SCREEN_DIMS: (f32, f32) = (1280.0, 860.0);
-const TELEPORT_ON_EDGES: bool = false;       // When edge of window is reached, teleport to other side.
+    while let Some(arg) = args.next_arg() {
+      match arg.as_str() {
+        "--scenario" => options.scenario_path = Some(args.value(&arg)?),
+        "--width" => options.window_dims.0 = args.value(&arg)?,
+        "--height" => options.window_dims.1 = args.value(&arg)?,
+        "--seed" => options.seed = Some(args.value(&arg)?),
+        "--dt" => options.fixed_dt = Some(args.value(&arg)?),
+        "--integrator" => options.integrator = args.value(&arg)?,
+        "--gravity" => options.gravity_solver = args.value(&arg)?,
+        "--info" => options.show_planet_info_debug = true,
+        "--vectors" => options.show_vector_debug = true,
+        "--help" | "-h" => {
+          print!("{}", USAGE);
+          std::process::exit(0);
+        },
+        _ if arg.starts_with("--") => return Err(format!("unknown option {:?}", arg)),
+        _ if options.scenario_path.is_none() => options.scenario_path = Some(PathBuf::from(arg)),
+        _ => return Err(format!("unexpected argument {:?}", arg)),
+      }
+    }
+
+    let (width, height) = options.window_dims;
+    if !(width.is_finite() && width >= 1.0 && height.is_finite() && height >= 1.0) {
+      return Err("--width and --height must be at least 1".to_string());
+    }
+    if options.fixed_dt.is_some_and(|dt| !(dt.is_finite() && dt > 0.0)) {
+      return Err("--dt must be a positive number".to_string());
+    }
+    Ok(options)
+  }
+}
 
 struct MainState {
-  planet_id_count: usize,
-  planets: HashMap<usize, RefCell<Planet>>,
+  simulation: Simulation,
   planet_trails: HashMap<usize, RefCell<PlanetTrail>>,
   mouse_info: MouseInfo,
+  camera: Camera,
+  frame: Option<RotatingFrame>,     // Co-rotating frame the scene is drawn in, if any
+  selected: Option<usize>,          // Body shown in the inspector and changed by the edit keys
+  scenario_path: Option<PathBuf>,   // Scenario file to restart into, or the built in default if None
+  seed: Option<u64>,                // Reseeds the generators on every restart if given
+  preset: Option<PeriodicOrbit>,    // Overrides the scenario while selected
+  scene_name: String,
+  load_error: Option<String>,       // Why the last scenario, snapshot or recording couldn't be loaded
+  recorder: Option<ReplayRecorder>,
+  playback: Option<Playback>,       // Shown instead of the simulation, which waits until playback ends
+  exporter: Option<TrajectoryExporter>,
 
   show_planet_info_debug: bool,
   show_vector_debug: bool,
-  dt: f32,
+  setting: Setting,                 // Config setting changed by Page Up/Down
+  screen_dims: (f32, f32),
+  dt: f32,                          // Time since the last frame
+  fixed_dt: Option<f32>,            // Simulation timestep, if not stepping once per frame
+  step_backlog: f32,                // Frame time not yet simulated with the fixed timestep
+  paused: bool,
+  time_scale: f32,                  // Simulated seconds per real second asked for
+  sim_rate: f32,                    // Simulated seconds per real second actually managed, smoothed
 
   // Mesh objects
   body_mesh: Mesh,
+  marker_mesh: Mesh,
 }
 
 impl MainState {
-  fn new(ctx: &mut Context) -> GameResult<MainState> {
+  fn new(ctx: &mut Context, options: Options) -> GameResult<MainState> {
     let body_mesh = Mesh::new_circle(
       ctx,
       DrawMode::fill(),
@@ -51,18 +160,46 @@ impl MainState {
       0.001,
       Color::WHITE,
     )?;
+    let marker_mesh = Mesh::new_line(
+      ctx,
+      &[Point2::new(0.0, 0.0), Point2::new(1.0, 0.0)],
+      0.2,
+      [0.0, 0.0, 0.0, 0.6].into(),
+    )?;
+
+    let mut simulation = Simulation::new();
+    simulation.integrator = options.integrator;
+    simulation.gravity_solver = options.gravity_solver;
 
     let mut s = MainState {
-      planet_id_count: 0,
-      planets: HashMap::new(),
+      simulation,
       planet_trails: HashMap::new(),
       mouse_info: MouseInfo::default(),
-
-      show_planet_info_debug: false,
-      show_vector_debug: false,
+      camera: Camera::new(options.window_dims),
+      frame: None,
+      selected: None,
+      scenario_path: options.scenario_path,
+      seed: options.seed,
+      preset: None,
+      scene_name: String::new(),
+      load_error: None,
+      recorder: None,
+      playback: None,
+      exporter: None,
+
+      show_planet_info_debug: options.show_planet_info_debug,
+      show_vector_debug: options.show_vector_debug,
+      setting: Setting::ALL[0],
+      screen_dims: options.window_dims,
       dt: 1.0/60.0,
+      fixed_dt: options.fixed_dt,
+      step_backlog: 0.0,
+      paused: false,
+      time_scale: 1.0,
+      sim_rate: 1.0,
 
       body_mesh,
+      marker_mesh,
     };
 
     s.restart();
@@ -70,193 +207,396 @@ impl MainState {
     Ok(s)
   }
 
+  // Rebuilds the scene from the selected preset, or else from the scenario. A scenario file is read again each
+  // time so edits show up on restart. If it can't be loaded the current scene carries on and the error is shown.
   fn restart(&mut self) {
-    self.clear();
-    // const GAP: f32 = 100.0;
-    // self.spawn_square_of_planets(
-    //     Point2::new(GAP/2.0, GAP/2.0),
-    //     (SCREEN_DIMS.0/GAP).ceil() as u16,
-    //     (SCREEN_DIMS.1/GAP).ceil() as u16,
-    //     GAP,
-    //     10.0,
-    // );
-
-    // self.add_planet_with_moons(
-    //     [(SCREEN_DIMS.0/2) as f32, (SCREEN_DIMS.1/2) as f32].into(),
-    //     None,
-    //     None,
-    //     50.0,
-    //     500,
-    //     (15.0, 200.0),
-    //     (0.5, 1.5),
-    //     true,
-    // );
-
-    self.add_planet_with_moons(
-      Point2::new(SCREEN_DIMS.0 * 1.0/3.0, SCREEN_DIMS.1/2.0),
-      None,
-      None,
-      50.0,
-      350,
-      (15.0, 100.0),
-      (0.5, 1.5),
-      true,
-    );
-    self.add_planet_with_moons(
-      Point2::new(SCREEN_DIMS.0 * 2.0/3.0, SCREEN_DIMS.1/2.0),
-      None,
-      None,
-      50.0,
-      350,
-      (15.0, 100.0),
-      (0.5, 1.5),
-      true,
-    );
+    if let Some(preset) = self.preset {
+      self.clear();
+      self.simulation.units = Units::default();
+      self.simulation.add_periodic_orbit(
+        preset,
+        Point2::new(self.screen_dims.0/2.0, self.screen_dims.1/2.0),
+        PRESET_LENGTH_SCALE,
+        PRESET_UNIT_MASS,
+        PRESET_BODY_RADIUS,
+      );
+      self.scene_name = preset.name().to_string();
+      self.load_error = None;
+      return;
+    }
 
-    // const DIV: f32 = 100.0;
-    // self.add_random_planets(
-    //     1000,
-    //     (SCREEN_DIMS.0/DIV, SCREEN_DIMS.0 - SCREEN_DIMS.0/DIV),
-    //     (SCREEN_DIMS.1/DIV, SCREEN_DIMS.1 - SCREEN_DIMS.1/DIV),
-    //     (2.0, 10.0),
-    //     Some((0.0, 1.0)),
-    // );
+    let scenario = match &self.scenario_path {
+      Some(path) => Scenario::load(path),
+      None => Scenario::parse(DEFAULT_SCENARIO),
+    };
+    // Built on the side and only swapped in once it has loaded, so a failure part way leaves nothing behind
+    let result = scenario.and_then(|scenario| {
+      let mut simulation = Simulation::new();
+      simulation.integrator = self.simulation.integrator;
+      simulation.gravity_solver = self.simulation.gravity_solver;
+      if let Some(seed) = self.seed {
+        simulation.seed(seed);
+      }
+      scenario.apply(&mut simulation)?;
+      Ok((scenario, simulation))
+    });
+
+    match result {
+      Ok((scenario, simulation)) => {
+        self.clear();
+        self.simulation = simulation;
+        self.scene_name = scenario.name.unwrap_or_else(|| "Untitled".to_string());
+        self.load_error = None;
+      },
+      Err(error) => {
+        let source = self.scenario_path.as_ref().map_or("default scenario".to_string(), |path| path.display().to_string());
+        let message = format!("Couldn't load {}:\n{}", source, error);
+        println!("{}", message);
+        self.load_error = Some(message);
+      },
+    }
   }
 
-  fn clear(&mut self) {
-    self.planets = HashMap::new();
+  // Steps through the preset orbits, then back round to the scenario
+  fn cycle_preset(&mut self) {
+    self.preset = match self.preset {
+      None => Some(PeriodicOrbit::ALL[0]),
+      Some(preset) if preset == *PeriodicOrbit::ALL.last().unwrap() => None,
+      Some(preset) => Some(preset.next()),
+    };
+    self.restart();
   }
 
-  fn add_planet(&mut self, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: Option<f32>, radius: f32, spawn_protection: Option<Duration>) {
-    self.add_planet_raw(Planet::new(
-      self.planet_id_count,
-      position,
-      velocity,
-      mass,
-      radius,
-      spawn_protection,
-    ));
+  fn quick_save(&self) {
+    match snapshot::save(QUICK_SAVE_PATH, &self.simulation, Some(&self.planet_trails)) {
+      Ok(()) => println!("Saved snapshot to {}", QUICK_SAVE_PATH),
+      Err(error) => println!("Couldn't save snapshot: {}", error),
+    }
   }
 
-  // Spawns a planet with other 
-  fn add_planet_with_moons(
-    &mut self,
-    position: Point2<f32>,
-    velocity: Option<Vector2<f32>>,
-    main_planet_mass: Option<f32>,
-    main_planet_radius: f32,
-    moon_num: usize,
-    moon_orbit_radius_range: (f32, f32),    // Starting from surface of planet
-    moon_body_radius_range: (f32, f32),
-    orbit_direction_clockwise: bool,  // anticlockwise = false, clockwise = true
-  ) {
-    self.add_planet(position, velocity, main_planet_mass, main_planet_radius, None);  // Add main planet
-    let (main_planet_mass, frame_velocity) = {
-      let p = self.planets.get(&(self.planet_id_count - 1)).unwrap().borrow();
-      (p.mass, p.velocity)
-    };
-    
-    let mut rng = rand::thread_rng();
-
-    let orbit_rad_range = Uniform::from(moon_orbit_radius_range.0..moon_orbit_radius_range.1);
-    let angle_range = Uniform::from(0.0..TWO_PI);
-    let size_rad_range = Uniform::from(moon_body_radius_range.0..moon_body_radius_range.1);
-    
-    for _ in 0..moon_num {
-      let orbit_radius = main_planet_radius + orbit_rad_range.sample(&mut rng);
-      let orbit_speed = tools::circular_orbit_speed(main_planet_mass, orbit_radius);
-      let start_angle = angle_range.sample(&mut rng);      // Angle from main planet to moon
-      let start_pos = tools::get_components(orbit_radius, start_angle);   // Position on circle orbit where planet will start
-      let start_velocity = tools::get_components(
-        orbit_speed,
-        if orbit_direction_clockwise {
-          start_angle + PI/2.0
-        } else {
-          start_angle - PI/2.0
-        }
-      );  // 90 degrees to angle with planet
-      let moon_radius = size_rad_range.sample(&mut rng);
+  // Replaces the whole scene with the quick save, leaving the current one alone if it can't be loaded
+  fn quick_load(&mut self) {
+    match snapshot::load(QUICK_SAVE_PATH) {
+      Ok(snapshot) => {
+        self.finish_recording();
+        self.finish_export();
+        self.simulation = snapshot.simulation;
+        self.selected = None;
+        self.frame = None;
+        self.planet_trails = snapshot.trails.unwrap_or_default();
+        self.scene_name = format!("{} (loaded)", QUICK_SAVE_PATH);
+        self.load_error = None;
+      },
+      Err(error) => {
+        let message = format!("Couldn't load snapshot:\n{}", error);
+        println!("{}", message);
+        self.load_error = Some(message);
+      },
+    }
+  }
 
-      self.add_planet(
-        position + start_pos,
-        Some(start_velocity + frame_velocity),  // Add velocity of main planet
-        None,
-        moon_radius,
-        None,
-      );
+  fn toggle_recording(&mut self) {
+    if self.recorder.is_some() {
+      self.finish_recording();
+    } else {
+      let mut recorder = ReplayRecorder::new();
+      recorder.record(&self.simulation);
+      self.recorder = Some(recorder);
     }
   }
 
-  fn add_planet_raw(&mut self, mut planet: Planet) {
-    planet.id = self.planet_id_count;
+  // Saves the recording in progress, if any. Called whenever the scene is replaced, as a recording can't carry on
+  // into a new scene whose time starts again.
+  fn finish_recording(&mut self) {
+    if let Some(recorder) = self.recorder.take() {
+      let frames = recorder.frame_count();
+      match recorder.finish().save(RECORDING_PATH) {
+        Ok(()) => println!("Saved {} frames to {}", frames, RECORDING_PATH),
+        Err(error) => println!("Couldn't save recording: {}", error),
+      }
+    }
+  }
 
-    self.planet_trails.insert(
-      self.planet_id_count,
-      RefCell::new(PlanetTrail::new(planet.position))
-    );
+  // Starts writing every body's trajectory to disk, or finishes the export in progress
+  fn toggle_export(&mut self, format: ExportFormat) {
+    if self.exporter.is_some() {
+      self.finish_export();
+      return;
+    }
 
-    self.planets.insert(
-      self.planet_id_count,
-      RefCell::new(planet)
-    );
+    let path = match format {
+      ExportFormat::Csv => EXPORT_CSV_PATH,
+      ExportFormat::Columnar => EXPORT_COLUMNAR_PATH,
+    };
+    // Just the selected body if there is one
+    let bodies = self.selected.map(|id| [id]);
+    let interval = self.simulation.config().export_interval as f64;
+    match TrajectoryExporter::new(format, path, bodies.as_ref().map(|ids| &ids[..]), interval) {
+      Ok(exporter) => {
+        match self.selected {
+          Some(id) => println!("Exporting the trajectory of body {} to {}", id, path),
+          None => println!("Exporting trajectories to {}", path),
+        }
+        self.exporter = Some(exporter);
+      },
+      Err(error) => println!("Couldn't start export: {}", error),
+    }
+  }
 
-    self.planet_id_count += 1;
+  // Like finish_recording, for the export in progress
+  fn finish_export(&mut self) {
+    if let Some(exporter) = self.exporter.take() {
+      match exporter.finish() {
+        Ok(rows) => println!("Exported {} rows", rows),
+        Err(error) => println!("Couldn't finish export: {}", error),
+      }
+    }
   }
 
-  fn add_random_planets(&mut self, n: usize, x_range: (f32, f32), y_range: (f32, f32), radius_range: (f32, f32), speed_range: Option<(f32, f32)>) {
-    assert!(x_range.1 > x_range.0);
-    assert!(y_range.1 > y_range.0);
-    assert!(radius_range.1 > radius_range.0);
-    assert!(n > 0);
+  fn toggle_playback(&mut self) {
+    if self.playback.take().is_some() {
+      return;
+    }
 
-    let mut rng = rand::thread_rng();
-    
-    for _ in 0..n {
-      let x_pos = rng.gen_range(x_range.0..x_range.1);
-      let y_pos = rng.gen_range(y_range.0..y_range.1);
-      let radius = rng.gen_range(radius_range.0..radius_range.1);
+    match Replay::load(RECORDING_PATH).and_then(Playback::new) {
+      Ok(playback) => {
+        self.playback = Some(playback);
+        self.load_error = None;
+      },
+      Err(error) => {
+        let message = format!("Couldn't play {}:\n{}", RECORDING_PATH, error);
+        println!("{}", message);
+        self.load_error = Some(message);
+      },
+    }
+  }
 
-      let velocity = if let Some(speed_range) = speed_range {
-        assert!(speed_range.1 > speed_range.0);
+  fn adjust_setting(&mut self, up: bool) {
+    let mut config = *self.simulation.config();
+    config.adjust(self.setting, up);
+    self.simulation.set_config(config);
+  }
 
-        let speed = rng.gen_range(speed_range.0..speed_range.1);
-        let angle = rng.gen_range(0.0..TWO_PI);
-        Some(tools::get_components(speed, angle))
-      } else {
-        None
-      };
+  // Follows the selected body, or else the one under the cursor
+  fn follow_body(&mut self, ctx: &Context) {
+    let cursor = ctx.mouse.position();
+    let body = self.selected.or_else(|| self.simulation.body_at(self.camera.to_world(Point2::new(cursor.x, cursor.y))));
+    if let Some(id) = body {
+      self.camera.follow = Follow::Body(id);
+    }
+  }
 
-      self.add_planet(
-        Point2::new(x_pos, y_pos),
-        velocity,
-        None,
-        radius,
-        None,
-      );
+  // Returns whether the key was used to change the selected body
+  fn edit_selected(&mut self, keycode: KeyCode, mods: KeyMods) -> bool {
+    let Some((id, planet)) = self.selected.and_then(|id| Some((id, self.simulation.planets.get(&id)?))) else { return false };
+    let (mass, radius, velocity) = {
+      let planet = planet.borrow();
+      (planet.mass, planet.radius, planet.velocity)
+    };
+    let nudge = VELOCITY_NUDGE/self.camera.zoom;
+
+    match keycode {
+      KeyCode::Equals | KeyCode::NumpadAdd if mods.contains(KeyMods::SHIFT) => {
+        self.simulation.set_radius(id, (radius * EDIT_FACTOR).min(f32::MAX));
+      },
+      KeyCode::Minus | KeyCode::NumpadSubtract if mods.contains(KeyMods::SHIFT) => {
+        self.simulation.set_radius(id, (radius/EDIT_FACTOR).max(MIN_EDIT_RADIUS.min(radius)));
+      },
+      KeyCode::Equals | KeyCode::NumpadAdd => {
+        self.simulation.set_mass(id, (mass * EDIT_FACTOR).min(f32::MAX));
+      },
+      KeyCode::Minus | KeyCode::NumpadSubtract => {
+        self.simulation.set_mass(id, (mass/EDIT_FACTOR).max(MIN_EDIT_MASS.min(mass)));
+      },
+      KeyCode::Up => { self.simulation.set_velocity(id, velocity + Vector2::new(0.0, -nudge)); },
+      KeyCode::Down => { self.simulation.set_velocity(id, velocity + Vector2::new(0.0, nudge)); },
+      KeyCode::Left => { self.simulation.set_velocity(id, velocity + Vector2::new(-nudge, 0.0)); },
+      KeyCode::Right => { self.simulation.set_velocity(id, velocity + Vector2::new(nudge, 0.0)); },
+      KeyCode::Back => { self.simulation.set_velocity(id, Vector2::zeros()); },
+      KeyCode::Delete => {
+        self.simulation.remove_planet(id);
+        self.selected = None;
+      },
+      KeyCode::Escape => self.selected = None,
+      _ => return false,
     }
+    true
+  }
+
+  // Moves everything into the centre of momentum frame, so the scene as a whole stops drifting
+  fn reframe(&mut self) {
+    let velocity = self.simulation.to_centre_of_momentum_frame();
+    println!("Took ({:.3}, {:.3}) off every body's velocity", velocity.x, velocity.y);
   }
 
-  fn remove_planet(&mut self, id: usize) {
-    if self.planets.remove(&id).is_none() {
-      println!("WARNING: Tried to remove planet {} but it wasn't in the hashmap.", id);
+  // Views the scene rotating with the heaviest body and the selected one, or the next heaviest, or goes back to
+  // the inertial view
+  fn toggle_rotating_frame(&mut self) {
+    if self.frame.take().is_some() {
+      self.camera.frame = None;
+      return;
+    }
+
+    let Some(primary) = self.simulation.heaviest_body() else { return };
+    let secondary = self.selected.filter(|id| *id != primary).or_else(|| {
+      self.simulation.planets.iter()
+        .filter(|(id, _)| **id != primary)
+        .max_by(|(_, a), (_, b)| a.borrow().total_mass().total_cmp(&b.borrow().total_mass()))
+        .map(|(id, _)| *id)
+    });
+    match secondary.and_then(|secondary| RotatingFrame::new(&self.simulation, primary, secondary)) {
+      Some(frame) => {
+        self.camera.frame = Some(frame.current());
+        self.frame = Some(frame);
+      },
+      None => println!("A rotating frame needs two bodies apart from each other"),
     }
   }
 
+  // Pauses if need be, then moves on by one timestep
+  fn single_step(&mut self) {
+    self.paused = true;
+    self.step_simulation(self.fixed_dt.unwrap_or(DEFAULT_STEP));
+  }
+
+  fn scale_time(&mut self, factor: f32) {
+    self.time_scale = (self.time_scale * factor).clamp(TIME_SCALE_RANGE.0, TIME_SCALE_RANGE.1);
+  }
+
+  fn clear(&mut self) {
+    self.finish_recording();
+    self.finish_export();
+    self.simulation.clear();
+    self.planet_trails.clear();
+    self.frame = None;
+    self.camera.frame = None;
+    self.selected = None;
+  }
+
+  // For showing simulation values in the scene's units
+  fn conversion(&self) -> Conversion {
+    self.simulation.units.conversion(self.simulation.config().gravitational_constant)
+  }
+
   fn draw_debug_info(&self, canvas: &mut Canvas) {
+    let diagnostics = self.simulation.diagnostics();
+    let drift = self.simulation.drift();
+    let units = self.conversion();
+    let centre_of_mass = units.position_from_sim(diagnostics.centre_of_mass);
     let text = graphics::Text::new(
       format!(
-        "{:.3}\nBodies: {}\nPlanet Trails: {}\nTrail Node Count: {}",
+        "{:.3}\nScene: {}\nBodies: {}\nTime: {} (step {}){}\nSpeed: x{} asked, x{:.2} managed (Space: pause, .: step, [ ]: change)\nView: {:.3}x zoom, {}{} (F: follow, Shift+F: follow selected body, V: rotating frame, H: reset)\nCollisions: {:?}\nIntegrator: {:?}, Gravity: {:?}\nPlanet Trails: {}\nTrail Node Count: {}\n\
+        Energy: {} (K {}, U {}) drift {:+.2e}\n\
+        Momentum: ({:.3e}, {:.3e}) {} drift {:.2e}\n\
+        Angular Momentum: {} drift {:+.2e}\n\
+        Centre of Mass: ({:.4}, {:.4}) {} drift {:.2e}\n\
+        Setting: {} (Tab: next, Page Up/Down: change)",
         1.0/self.dt,
-        self.planets.len(),
+        self.scene_name,
+        self.simulation.planets.len(),
+        units.format(Quantity::Time, self.simulation.time),
+        self.simulation.steps,
+        if self.paused { " PAUSED" } else { "" },
+        self.time_scale,
+        self.sim_rate,
+        self.camera.zoom,
+        self.camera.follow.describe(),
+        self.frame.as_ref().map_or(String::new(), |frame| format!(", {}", frame.describe())),
+        self.simulation.collision_mode,
+        self.simulation.integrator,
+        self.simulation.gravity_solver,
         self.planet_trails.len(),
         self.node_count(),
+        units.format(Quantity::Energy, diagnostics.total_energy()),
+        units.format(Quantity::Energy, diagnostics.kinetic_energy),
+        units.format(Quantity::Energy, diagnostics.potential_energy),
+        drift.energy,
+        units.from_sim(Quantity::Momentum, diagnostics.momentum.x),
+        units.from_sim(Quantity::Momentum, diagnostics.momentum.y),
+        units.symbol(Quantity::Momentum),
+        drift.momentum,
+        units.format(Quantity::AngularMomentum, diagnostics.angular_momentum),
+        drift.angular_momentum,
+        centre_of_mass.x,
+        centre_of_mass.y,
+        units.symbol(Quantity::Length),
+        drift.centre_of_mass,
+        self.simulation.config().describe(self.setting),
       )
     );
     
     canvas.draw(&text, DrawParam::new().dest([10.0, 10.0]));
+
+    if let Some(recorder) = &self.recorder {
+      let text = graphics::Text::new(format!("REC {} frames", recorder.frame_count()));
+      canvas.draw(&text, DrawParam::new().dest([self.screen_dims.0 - 150.0, 10.0]).color(Color::RED));
+    }
+    if let Some(exporter) = &self.exporter {
+      let text = graphics::Text::new(format!("EXPORT {} rows", exporter.rows()));
+      canvas.draw(&text, DrawParam::new().dest([self.screen_dims.0 - 150.0, 30.0]).color(Color::YELLOW));
+    }
+
+    if let Some(error) = &self.load_error {
+      let text = graphics::Text::new(error.as_str());
+      let height = 20.0 * (error.lines().count() + 1) as f32;
+      canvas.draw(&text, DrawParam::new().dest([10.0, self.screen_dims.1 - height]).color(Color::RED));
+    }
+  }
+
+  // Live state of the selected body, with a ring around it
+  fn draw_selection(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+    let Some(planet) = self.selected.and_then(|id| self.simulation.planets.get(&id)) else { return Ok(()) };
+    let planet = planet.borrow();
+    let units = self.conversion();
+
+    let ring = Mesh::new_circle(
+      ctx,
+      DrawMode::stroke(1.0),
+      self.camera.to_screen(planet.position),
+      planet.radius * self.camera.zoom + 4.0,
+      0.1,
+      Color::YELLOW,
+    )?;
+    canvas.draw(&ring, DrawParam::default());
+
+    let position = units.position_from_sim(planet.position.cast::<f64>());
+    let mut text = format!(
+      "Body {}\nPosition: ({:.4}, {:.4}) {}\nVelocity: ({:.4}, {:.4}) {}\nSpeed: {}\nMass: {}\nRadius: {}\nSpin: {}",
+      planet.id,
+      position.x,
+      position.y,
+      units.symbol(Quantity::Length),
+      units.from_sim(Quantity::Velocity, planet.velocity.x as f64),
+      units.from_sim(Quantity::Velocity, planet.velocity.y as f64),
+      units.symbol(Quantity::Velocity),
+      units.format(Quantity::Velocity, planet.velocity.magnitude() as f64),
+      units.format(Quantity::Mass, planet.mass as f64),
+      units.format(Quantity::Length, planet.radius as f64),
+      units.format(Quantity::AngularVelocity, planet.spin as f64),
+    );
+    match self.simulation.orbital_elements(planet.id, None) {
+      Some((primary, elements)) => text += &format!("\nOrbiting: {}\na: {}\ne: {:.4}\nT: {}",
+        primary,
+        units.format(Quantity::Length, elements.semi_major_axis as f64),
+        elements.eccentricity,
+        match elements.period {
+          Some(period) => units.format(Quantity::Time, period as f64),
+          None => "unbound".to_string(),
+        },
+      ),
+      None => text += "\nNot orbiting anything",
+    }
+    text += "\n\n+/-: mass, Shift +/-: radius\nArrows: velocity, Backspace: stop\nDelete: remove, Esc: deselect";
+
+    canvas.draw(
+      &graphics::Text::new(text),
+      DrawParam::new().dest([self.screen_dims.0 - SELECTION_PANEL_WIDTH, 60.0]).color(Color::YELLOW),
+    );
+    Ok(())
   }
 
-  pub fn draw_mouse_drag(ctx: &mut Context, canvas: &mut Canvas, mouse_info: &MouseInfo) -> GameResult {
+  // The drag is drawn on screen, with the body as big as it will be once the camera's zoom is applied
+  pub fn draw_mouse_drag(ctx: &mut Context, canvas: &mut Canvas, mouse_info: &MouseInfo, spawn_radius: f32) -> GameResult {
     let line = Mesh::new_line(
       ctx,
       &[mouse_info.down_pos, mouse_info.current_drag_position],
@@ -269,7 +609,7 @@ impl MainState {
       ctx,
       DrawMode::fill(),
       mouse_info.down_pos,
-      SPAWN_PLANET_RADIUS,
+      spawn_radius,
       0.1,
       [1.0, 1.0, 1.0, 0.4].into()
     )?;
@@ -278,55 +618,97 @@ impl MainState {
     Ok(())
   }
 
-  fn collide_planets(pl1: &mut Planet, pl2: &Planet) {  // Makes pl1 the new planet
-    // Conservation of momentum
-    let total_mass = pl1.mass + pl2.mass;
-    let total_momentum = pl1.mass * pl1.velocity + pl2.mass * pl2.velocity;
-    pl1.radius = tools::inverse_volume_of_sphere(total_mass/PLANET_DENSITY);
-    // Use centre of mass as new position
-    pl1.position = Point2::new(
-      (pl1.position.x * pl1.mass + pl2.position.x * pl2.mass)/total_mass,
-      (pl1.position.y * pl1.mass + pl2.position.y * pl2.mass)/total_mass
+  // Where the body being dragged out would go if let go now, and how its path ends
+  fn draw_trajectory_preview(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+    let config = self.simulation.config();
+    let prediction = preview::predict(
+      &self.simulation,
+      self.camera.to_world(self.mouse_info.down_pos),
+      self.camera.velocity_to_world(self.mouse_info.down_pos, self.mouse_info.down_pos - self.mouse_info.current_drag_position),
+      config.spawn_radius,
+      config.preview_horizon,
+      self.fixed_dt.unwrap_or(DEFAULT_STEP),
     );
-    pl1.velocity = total_momentum/total_mass;   // Inelastic collision
-    pl1.mass = total_mass;
-    pl1.update_color(); // Will have changed colour due to increase in mass
-  }
+    let units = self.conversion();
+    let (color, label) = match prediction.outcome {
+      Outcome::Survives => (Color::new(0.5, 0.8, 1.0, 0.6), None),
+      Outcome::Collides { with, time } => (Color::new(1.0, 0.3, 0.3, 0.8), Some(format!("hits body {} in {}", with, units.format(Quantity::Time, time as f64)))),
+      Outcome::Escapes { time } => (Color::new(1.0, 1.0, 0.3, 0.8), Some(format!("escapes in {}", units.format(Quantity::Time, time as f64)))),
+    };
 
-  fn spawn_square_of_planets(
-    &mut self,
-    top_left: Point2<f32>,
-    w: u16,
-    h: u16,
-    gap: f32,
-    rad: f32,
-  ) {
-    for i in 0..w {
-      for j in 0..h {
-        self.add_planet(
-          Point2::new(top_left.x + i as f32 * gap, top_left.y + j as f32 * gap),
-          None,
-          None,
-          rad,
-          None,
-        );
+    // Broken wherever the body wraps round the edges, and skipping points too close together to draw
+    let max_jump = (self.screen_dims.0.min(self.screen_dims.1)/2.0).powi(2);
+    let mut builder = MeshBuilder::new();
+    let mut can_draw = false;
+    let mut line: Vec<Point2<f32>> = Vec::new();
+    for point in prediction.path.iter().map(|point| self.camera.to_screen(*point)) {
+      match line.last() {
+        Some(last) if (point - last).magnitude_squared() > max_jump => {
+          if line.len() > 1 {
+            builder.line(&line, 1.0, color)?;
+            can_draw = true;
+          }
+          line = vec![point];
+        },
+        Some(last) if (point - last).magnitude_squared() < 1.0 => (),
+        _ => line.push(point),
       }
     }
+    if line.len() > 1 {
+      builder.line(&line, 1.0, color)?;
+      can_draw = true;
+    }
+    if can_draw {
+      canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+    }
+
+    if let (Some(label), Some(end)) = (label, prediction.path.last()) {
+      let end = self.camera.to_screen(*end);
+      let marker = Mesh::new_circle(ctx, DrawMode::stroke(1.0), end, config.spawn_radius * self.camera.zoom + 4.0, 0.1, color)?;
+      canvas.draw(&marker, DrawParam::default());
+      canvas.draw(&graphics::Text::new(label), DrawParam::new().dest(end + Vector2::new(8.0, 8.0)).color(color));
+    }
+    Ok(())
   }
 
-  fn update_planet_trails(&mut self, dt_duration: &Duration) {
+  // Run after every step, so trails follow simulated time rather than the frame rate
+  fn update_planet_trails(&mut self) {
+    let time = self.simulation.time;
+    // Bodies added since the last update get a trail starting where they are
+    for (id, planet) in self.simulation.planets.iter() {
+      self.planet_trails.entry(*id)
+        .or_insert_with(|| {
+          let planet = planet.borrow();
+          RefCell::new(PlanetTrail::new(planet.position, time, planet.color()))
+        });
+    }
+
     for (id, trail) in self.planet_trails.iter_mut() {
       trail.borrow_mut().update(
-        dt_duration,
-        if let Some(planet) = self.planets.get(&id) {
-          Some(planet.borrow().position)
-        } else {
-          None
-        },
+        time,
+        self.simulation.planets.get(id).map(|planet| planet.borrow()).as_deref(),
+        self.simulation.config(),
       );
     }
   }
 
+  fn step_simulation(&mut self, dt: f32) {
+    self.simulation.step(dt);
+    self.update_planet_trails();
+    let keep = self.simulation.config().trail_node_lifetime as f64;
+    if self.frame.as_mut().is_some_and(|frame| !frame.record(&self.simulation, keep)) {
+      println!("Left the rotating frame, as one of its bodies has gone");
+      self.frame = None;
+    }
+    if self.recorder.as_mut().is_some_and(|recorder| !recorder.record(&self.simulation)) {
+      self.finish_recording();
+    }
+    if let Some(Err(error)) = self.exporter.as_mut().map(|exporter| exporter.sample(&self.simulation)) {
+      println!("Stopped exporting: {}", error);
+      self.finish_export();
+    }
+  }
+
   fn node_count(&self) -> usize {
     let mut total = 0;
     for (_, trail) in self.planet_trails.iter() {
@@ -339,71 +721,51 @@ impl MainState {
 
 impl event::EventHandler for MainState {
   fn update(&mut self, ctx: &mut Context) -> GameResult {
-    let dt_duration = ctx.time.delta();
-    self.dt = dt_duration.as_secs_f32();
+    self.dt = ctx.time.delta().as_secs_f32();
+
+    if let Some(playback) = self.playback.as_mut() {
+      playback.update(self.dt as f64);
+      self.camera.frame = None;
+      return Ok(());
+    }
 
-    // For holding planets that have collided
-    let mut collided_planets: Vec<usize> = Vec::with_capacity(self.planets.len()/2);
-    let mut planets_to_remove: Vec<usize> = Vec::with_capacity(self.planets.len()/2);
-    
     // Remove dead particle emitters
     self.planet_trails.retain(|_, trail| !trail.borrow().is_dead());
 
-    let keys: Vec<&usize> = self.planets.keys().collect();
-    let len = self.planets.len();
-
-    if len > 0 {
-      // Update planets
-      for (_, pl) in self.planets.iter() {
-        pl.borrow_mut().update(self.dt, &dt_duration);
-      }
-
-      for i in 0..len-1 {
-        let already_collided = collided_planets.contains(&i);
-        if !already_collided {
-          let pl1 = self.planets.get(keys[i]).expect("Couldn't get planet 1");
-          for j in i+1..len {
-            let already_collided = collided_planets.contains(&j);
-            if !already_collided {
-              let pl2 = self.planets.get(keys[j]).expect("Couldn't get planet 2");
-  
-              let (colliding, dist_vec, square_distance, protection) = {
-                let bpl1 = pl1.borrow();
-                let bpl2 = pl2.borrow();
-                let dist_vec = bpl2.position - bpl1.position;
-                let min_dist = bpl1.radius + bpl2.radius;
-                let square_dist = dist_vec.x.powi(2) + dist_vec.y.powi(2);
-                (
-                  // AABB then circle collision
-                  dist_vec.x.abs() <= min_dist && dist_vec.y.abs() <= min_dist && square_dist <= min_dist.powi(2),
-                  dist_vec,
-                  square_dist,
-                  bpl1.has_spawn_protection() || bpl2.has_spawn_protection()
-                )
-              };
-      
-              // Check for collision even if they have spawn protection, since I do not want to apply grav
-              // force when planets are inside of each other (as they become very speedy).
-              // protection is true if either planets have spawn protection
-              if colliding && !protection {
-                Self::collide_planets(&mut pl1.borrow_mut(), &pl2.borrow());
-                collided_planets.push(*keys[i]);
-                collided_planets.push(*keys[j]);
-                planets_to_remove.push(*keys[j])
-              } else if !colliding {
-                tools::newtonian_grav(&mut pl1.borrow_mut(), &mut pl2.borrow_mut(), square_distance, dist_vec);
-              }
-            }
-          }
+    let frame_time = if self.paused { 0.0 } else { self.dt * self.time_scale };
+    let time_before = self.simulation.time;
+    match self.fixed_dt {
+      Some(fixed_dt) => {
+        self.step_backlog += frame_time;
+        let steps = (self.step_backlog/fixed_dt).floor() as u32;
+        if steps > MAX_STEPS_PER_FRAME {
+          self.step_backlog = 0.0;
+        } else {
+          self.step_backlog -= steps as f32 * fixed_dt;
         }
-        
-      }
+        for _ in 0..steps.min(MAX_STEPS_PER_FRAME) {
+          self.step_simulation(fixed_dt);
+        }
+      },
+      // Faster than real time takes several steps a frame, so no step is longer than the frame
+      None if frame_time > 0.0 => {
+        let steps = self.time_scale.ceil() as u32;
+        for _ in 0..steps {
+          self.step_simulation(frame_time/steps as f32);
+        }
+      },
+      None => (),
+    }
+    if self.dt > 0.0 {
+      let rate = (self.simulation.time - time_before) as f32/self.dt;
+      self.sim_rate += (rate - self.sim_rate) * SIM_RATE_SMOOTHING;
     }
 
-    self.planets.retain(|id, _| !planets_to_remove.contains(id));
-
-    // Update trails
-    self.update_planet_trails(&dt_duration);
+    self.camera.frame = self.frame.as_ref().map(RotatingFrame::current);
+    self.camera.track(&self.simulation);
+    if self.selected.is_some_and(|id| !self.simulation.planets.contains_key(&id)) {
+      self.selected = None;
+    }
 
     Ok(())
   }
@@ -411,11 +773,17 @@ impl event::EventHandler for MainState {
   fn draw(&mut self, ctx: &mut Context) -> GameResult {
     let mut canvas = graphics::Canvas::from_frame(ctx, Color::BLACK);
 
-    if self.mouse_info.down && self.mouse_info.button_down == MouseButton::Left &&
+    if let Some(playback) = &self.playback {
+      playback.draw(&mut canvas, &self.camera, &self.body_mesh, &self.marker_mesh);
+      return canvas.finish(ctx);
+    }
+
+    if self.mouse_info.down && self.mouse_info.spawning &&
       (self.mouse_info.down_pos.x - self.mouse_info.current_drag_position.x).powi(2) +
       (self.mouse_info.down_pos.y - self.mouse_info.current_drag_position.y).powi(2) >= 4.0
     {
-      Self::draw_mouse_drag(ctx, &mut canvas, &self.mouse_info)?;
+      Self::draw_mouse_drag(ctx, &mut canvas, &self.mouse_info, self.simulation.config().spawn_radius * self.camera.zoom)?;
+      self.draw_trajectory_preview(ctx, &mut canvas)?;
     }
 
     // Draw particles
@@ -426,7 +794,7 @@ impl event::EventHandler for MainState {
       for (_, trail) in self.planet_trails.iter() {
         // Draw builds the mesh, returns a bool.
         // If this bool is true then there's something to draw.
-        if trail.borrow().draw(&mut lines_mesh_builder)? {
+        if trail.borrow().draw(&mut lines_mesh_builder, self.simulation.config(), &self.camera, self.simulation.time, self.frame.as_ref())? {
           can_draw = true;
         }
       }
@@ -437,59 +805,127 @@ impl event::EventHandler for MainState {
       }
     }
 
-    for (_, planet) in self.planets.iter() {
+    let units = self.conversion();
+    for (id, planet) in self.simulation.planets.iter() {
+      let orbit = if self.show_planet_info_debug {
+        self.simulation.orbital_elements(*id, None)
+      } else {
+        None
+      };
+
       planet.borrow().draw(
         ctx,
         &mut canvas,
+        &self.camera,
         &self.body_mesh,
-        self.show_planet_info_debug,
+        &self.marker_mesh,
+        self.show_planet_info_debug.then_some(&units),
         self.show_vector_debug,
+        orbit,
       )?;
     }
 
+    self.draw_selection(ctx, &mut canvas)?;
     self.draw_debug_info(&mut canvas);
     canvas.finish(ctx)
   }
 
+  // Left clicking a body selects it, anywhere else starts dragging out a new body
   fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
     self.mouse_info.down = true;
     self.mouse_info.button_down = button;
     self.mouse_info.down_pos = Point2::new(x, y);
+    self.mouse_info.spawning = false;
+
+    if button == MouseButton::Left && self.playback.is_none() {
+      match self.simulation.body_at(self.camera.to_world(Point2::new(x, y))) {
+        Some(id) => self.selected = Some(id),
+        None => self.mouse_info.spawning = true,
+      }
+    }
     Ok(())
   }
 
   fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
     self.mouse_info.down = false;
 
-    if button == MouseButton::Left {
-      self.add_planet(
-        self.mouse_info.down_pos,
-        Some(self.mouse_info.down_pos - Point2::new(x, y)),
+    // Dragging back from where the body goes sets its velocity, which is the same on screen at any zoom
+    if button == MouseButton::Left && self.mouse_info.spawning && self.playback.is_none() {
+      self.simulation.add_planet(
+        self.camera.to_world(self.mouse_info.down_pos),
+        Some(self.camera.velocity_to_world(self.mouse_info.down_pos, self.mouse_info.down_pos - Point2::new(x, y))),
         None,
-        SPAWN_PLANET_RADIUS,
+        self.simulation.config().spawn_radius,
         None,
       );
     }
     Ok(())
   }
 
-  fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) -> GameResult {
+  fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, dx: f32, dy: f32) -> GameResult {
     self.mouse_info.current_drag_position = Point2::new(x, y);
+    if self.mouse_info.down && matches!(self.mouse_info.button_down, MouseButton::Right | MouseButton::Middle) {
+      self.camera.pan(Vector2::new(dx, dy));
+    }
+    Ok(())
+  }
+
+  fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) -> GameResult {
+    let cursor = ctx.mouse.position();
+    self.camera.zoom_at(Point2::new(cursor.x, cursor.y), ZOOM_PER_SCROLL.powf(y));
     Ok(())
   }
 
   fn key_down_event(
     &mut self,
-    _ctx: &mut Context,
+    ctx: &mut Context,
     input: KeyInput,
     _repeat: bool,
   ) -> GameResult {
+    if let (Some(playback), Some(keycode)) = (self.playback.as_mut(), input.keycode) {
+      if playback.key_down(keycode) {
+        return Ok(());
+      }
+    }
+
     if let Some(keycode) = input.keycode {
+      // The simulation waits during playback, so only keys that don't touch it get through
+      if self.playback.is_some() && !matches!(keycode, KeyCode::P | KeyCode::H | KeyCode::D | KeyCode::I) {
+        return Ok(());
+      }
+      if self.playback.is_none() && self.edit_selected(keycode, input.mods) {
+        return Ok(());
+      }
+
       match keycode {
         KeyCode::D => self.show_vector_debug = !self.show_vector_debug,
         KeyCode::I => self.show_planet_info_debug = !self.show_planet_info_debug,
         KeyCode::R => self.restart(),
         KeyCode::C => self.clear(),
+        KeyCode::O => self.cycle_preset(),
+        KeyCode::L => {
+          self.preset = None;
+          self.restart();
+        },
+        KeyCode::F5 => self.quick_save(),
+        KeyCode::F9 => self.quick_load(),
+        KeyCode::Z => self.toggle_recording(),
+        KeyCode::P => self.toggle_playback(),
+        KeyCode::E if input.mods.contains(KeyMods::SHIFT) => self.toggle_export(ExportFormat::Columnar),
+        KeyCode::E => self.toggle_export(ExportFormat::Csv),
+        KeyCode::Space => self.paused = !self.paused,
+        KeyCode::Period => self.single_step(),
+        KeyCode::LBracket => self.scale_time(0.5),
+        KeyCode::RBracket => self.scale_time(2.0),
+        KeyCode::H => self.camera.reset(),
+        KeyCode::F if input.mods.contains(KeyMods::SHIFT) => self.follow_body(ctx),
+        KeyCode::F => self.camera.follow = self.camera.follow.next(),
+        KeyCode::B => self.reframe(),
+        KeyCode::V => self.toggle_rotating_frame(),
+        KeyCode::M => self.simulation.collision_mode = self.simulation.collision_mode.toggled(),
+        KeyCode::Tab => self.setting = self.setting.next(),
+        KeyCode::PageUp => self.adjust_setting(true),
+        KeyCode::PageDown => self.adjust_setting(false),
         _ => (),
       }
     }
@@ -498,9 +934,94 @@ impl event::EventHandler for MainState {
 }
 
 
+// Plays a recording back at any speed, and jumps around it, without simulating anything
+struct Playback {
+  replay: Replay,
+  cursor: ReplayCursor,
+  time: f64,      // Simulation time being shown
+  playing: bool,
+  speed: f64,
+}
+
+impl Playback {
+  fn new(replay: Replay) -> Result<Playback, ReplayError> {
+    let mut cursor = ReplayCursor::new();
+    if !replay.is_empty() {
+      cursor.seek(&replay, 0)?;
+    }
+    let time = if replay.is_empty() { 0.0 } else { replay.frame_time(0) };
+    Ok(Playback { replay, cursor, time, playing: true, speed: 1.0 })
+  }
+
+  fn start_time(&self) -> f64 {
+    if self.replay.is_empty() { 0.0 } else { self.replay.frame_time(0) }
+  }
+
+  fn end_time(&self) -> f64 {
+    self.start_time() + self.replay.duration()
+  }
+
+  fn update(&mut self, dt: f64) {
+    if self.playing {
+      self.seek(self.time + dt * self.speed);
+      if self.time >= self.end_time() {
+        self.playing = false;
+      }
+    }
+  }
+
+  fn seek(&mut self, time: f64) {
+    self.time = time.clamp(self.start_time(), self.end_time());
+    if !self.replay.is_empty() {
+      // Every frame was decoded when the recording loaded, so this can't fail
+      self.cursor.seek(&self.replay, self.replay.frame_at(self.time)).expect("Recording was checked on load");
+    }
+  }
+
+  // Returns whether the key was used by playback
+  fn key_down(&mut self, keycode: KeyCode) -> bool {
+    match keycode {
+      KeyCode::Space => self.playing = !self.playing,
+      KeyCode::Left => self.seek(self.time - PLAYBACK_SCRUB_SECONDS * self.speed),
+      KeyCode::Right => self.seek(self.time + PLAYBACK_SCRUB_SECONDS * self.speed),
+      KeyCode::LBracket => self.speed /= 2.0,
+      KeyCode::RBracket => self.speed *= 2.0,
+      KeyCode::Home => self.seek(self.start_time()),
+      KeyCode::End => self.seek(self.end_time()),
+      _ => return false,
+    }
+    true
+  }
+
+  fn draw(&self, canvas: &mut Canvas, camera: &Camera, body_mesh: &Mesh, marker_mesh: &Mesh) {
+    let mut count = 0;
+    for body in self.cursor.bodies() {
+      let scale = Vector2::new(body.radius, body.radius) * camera.zoom;
+      let position = camera.to_screen(body.position);
+      canvas.draw(body_mesh, DrawParam::new().scale(scale).dest(position).color(body.color));
+      canvas.draw(marker_mesh, DrawParam::new().scale(scale).rotation(body.rotation).dest(position));
+      count += 1;
+    }
+
+    let text = graphics::Text::new(format!(
+      "PLAYBACK {}\nTime: {:.2} / {:.2}\nSpeed: x{}\nFrame: {} / {}\nBodies: {}\n\
+      Space: play/pause, Left/Right: scrub, [ ]: speed, Home/End: jump, P: leave",
+      if self.playing { "" } else { "(paused)" },
+      self.time - self.start_time(),
+      self.replay.duration(),
+      self.speed,
+      self.cursor.frame().map_or(0, |frame| frame + 1),
+      self.replay.frame_count(),
+      count,
+    ));
+    canvas.draw(&text, DrawParam::new().dest([10.0, 10.0]));
+  }
+}
+
 struct MouseInfo {
   down: bool,
   button_down: MouseButton,
+  spawning: bool,     // The left button went down away from any body, so letting go adds one
   down_pos: Point2<f32>,
   current_drag_position: Point2<f32>,
 }
@@ -510,6 +1031,7 @@ impl Default for MouseInfo {
     MouseInfo {
       down: false,
       button_down: MouseButton::Left,
+      spawning: false,
       down_pos: Point2::new(0.0, 0.0),
       current_drag_position: Point2::new(1.0, 0.0),
     }
@@ -529,11 +1051,19 @@ pub fn main() -> GameResult {
     path::PathBuf::from("./resources")
   };
 
+  let options = match Options::parse(Args::from_env()) {
+    Ok(options) => options,
+    Err(message) => {
+      eprintln!("error: {}\n\n{}", message, USAGE);
+      std::process::exit(1);
+    },
+  };
+
   let cb = ggez::ContextBuilder::new("Planets", "ggez")
     .add_resource_path(resource_dir)
     .window_mode(
       WindowMode::default()
-        .dimensions(SCREEN_DIMS.0, SCREEN_DIMS.1)
+        .dimensions(options.window_dims.0, options.window_dims.1)
     )
     .window_setup(
       WindowSetup::default()
@@ -541,6 +1071,6 @@ pub fn main() -> GameResult {
     );
 
   let (mut ctx, event_loop) = cb.build()?;
-  let state = MainState::new(&mut ctx)?;
+  let state = MainState::new(&mut ctx, options)?;
   event::run(ctx, event_loop, state)
 }
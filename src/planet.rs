@@ -1,19 +1,44 @@
-use ggez::graphics::{self, MeshBuilder, Mesh, DrawMode, Color, DrawParam, Canvas};
+use ggez::graphics::{self, MeshBuilder, Mesh, Color, DrawParam, Canvas};
 use ggez::{Context, GameResult};
-use ggez::timer;
 
 use nalgebra::{Vector2, Point2};
 use rgb_hsv::hsv_to_rgb;
+use serde::{Serialize, Deserialize};
 
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use std::collections::VecDeque;
 
-use crate::tools;
-use crate::{SCREEN_DIMS, TELEPORT_ON_EDGES, ACC_DEBUG_VECTOR_MULTIPLIER};
+use crate::tools::{self, OrbitalElements};
+use crate::config::SimConfig;
+use crate::camera::Camera;
+use crate::frame::RotatingFrame;
+use crate::units::{Conversion, Quantity};
+use crate::{SCREEN_DIMS, ACC_DEBUG_VECTOR_MULTIPLIER, TWO_PI};
 
-pub const PLANET_DENSITY: f32 = 5000.0;
 const PLANET_RADIUS_COLORING_LOOP: f32 = 5.0;  // Planets are rainbow and colour repeats every 10
 
+// Rigid dark matter halo carried along with a body: a Plummer sphere centred on it.
+// It adds to the body's gravity and inertia but never collides with anything.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Halo {
+  pub mass: f32,
+  pub scale_radius: f32,
+}
+
+impl Halo {
+  // Combined halo of two merging bodies, with the scale radius weighted by mass
+  pub fn merged(a: Option<Halo>, b: Option<Halo>) -> Option<Halo> {
+    match (a, b) {
+      (Some(a), Some(b)) => Some(Halo {
+        mass: a.mass + b.mass,
+        scale_radius: (a.scale_radius * a.mass + b.scale_radius * b.mass)/(a.mass + b.mass),
+      }),
+      (a, b) => a.or(b),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Planet {
   pub id: usize,
   pub position: Point2<f32>,
@@ -21,48 +46,53 @@ pub struct Planet {
   pub mass: f32,
   pub radius: f32,
   pub resultant_force: Vector2<f32>,
+  pub spin: f32,              // Angular velocity (rad/s), positive is clockwise on screen
+  pub rotation: f32,          // Angle of the rotation marker (rad)
+  pub resultant_torque: f32,
+  pub halo: Option<Halo>,
   color: Color,
   spawn_protection_timer: Option<Duration>,
 }
 
 impl Planet {
-  pub fn new(id: usize, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: Option<f32>, radius: f32, spawn_protection_timer: Option<Duration>) -> Planet {
-    let mut n = Planet {
+  pub fn new(id: usize, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: f32, radius: f32, spawn_protection_timer: Option<Duration>) -> Planet {
+    Planet {
       id,
       position,
       velocity: velocity.unwrap_or_else(|| Vector2::new(0.0, 0.0)),
-      mass: mass.unwrap_or_else(|| Self::mass_from_radius(radius, PLANET_DENSITY)),
+      mass,
       radius,
       resultant_force: Vector2::new(0.0, 0.0),
+      spin: 0.0,
+      rotation: 0.0,
+      resultant_torque: 0.0,
+      halo: None,
       color: Color::WHITE,
       spawn_protection_timer,
-    };
-
-    n
+    }
   }
 
+  // Semi-implicit Euler step: kick with the forces from the last step, drift, then clear them for the next
   pub fn update(&mut self, dt: f32, dt_duration: &Duration) {
-    let acceleration = self.resultant_force/self.mass;  // F = ma, F/m = a
+    self.kick(dt);
+    self.drift(dt, dt_duration);
+    self.clear_forces();
+  }
+
+  // Changes velocity and spin by the resultant force and torque acting for dt
+  pub fn kick(&mut self, dt: f32) {
+    let acceleration = self.resultant_force/self.total_mass();  // F = ma, F/m = a
     self.velocity += acceleration * dt;
-    self.position += self.velocity * dt;
+    self.spin += self.resultant_torque/self.moment_of_inertia() * dt;
+  }
 
-    if TELEPORT_ON_EDGES {
-      if self.position.x < -self.radius {
-        self.position.x = SCREEN_DIMS.0 + self.radius;
-      } else if self.position.x > SCREEN_DIMS.0 + self.radius {
-        self.position.x = -self.radius;
-      }
-      if self.position.y < -self.radius {
-        self.position.y = SCREEN_DIMS.1 + self.radius;
-      } else if self.position.y > SCREEN_DIMS.1 + self.radius {
-        self.position.y = -self.radius;
-      }
-    }
-    
-    self.resultant_force = Vector2::new(0.0, 0.0);
+  // Moves and turns the body at its current velocity and spin
+  pub fn drift(&mut self, dt: f32, dt_duration: &Duration) {
+    self.position += self.velocity * dt;
+    self.rotation = (self.rotation + self.spin * dt) % TWO_PI;
 
     if let Some(spawn_timer) = self.spawn_protection_timer.as_mut() {
-      if !(*spawn_timer < *dt_duration) {
+      if *spawn_timer >= *dt_duration {
         *spawn_timer -= *dt_duration;
       } else {        // Time is up
         self.spawn_protection_timer = None;
@@ -70,39 +100,87 @@ impl Planet {
     }
   }
 
-  pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas,
-  						body_mesh: &Mesh, text_debug: bool,
-  						vector_debug: bool) -> GameResult {
+  // Brings a body that has left the region [0, width] x [0, height] back in at the opposite edge
+  pub fn wrap_around(&mut self, edges: [f32; 2]) {
+    if self.position.x < -self.radius {
+      self.position.x = edges[0] + self.radius;
+    } else if self.position.x > edges[0] + self.radius {
+      self.position.x = -self.radius;
+    }
+    if self.position.y < -self.radius {
+      self.position.y = edges[1] + self.radius;
+    } else if self.position.y > edges[1] + self.radius {
+      self.position.y = -self.radius;
+    }
+  }
+
+  pub fn clear_forces(&mut self) {
+    self.resultant_force = Vector2::new(0.0, 0.0);
+    self.resultant_torque = 0.0;
+  }
+
+  // Info text is shown in the given units, if any
+  #[allow(clippy::too_many_arguments)]
+  pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas, camera: &Camera,
+  						body_mesh: &Mesh, marker_mesh: &Mesh, text_debug: Option<&Conversion>,
+  						vector_debug: bool, orbit: Option<(usize, OrbitalElements)>) -> GameResult {
+    let position = camera.to_screen(self.position);
+    let radius = self.radius * camera.zoom;
     canvas.draw(body_mesh, DrawParam::new()
-    												 .scale(Vector2::new(self.radius, self.radius))
-    												 .dest(self.position)
+    												 .scale(Vector2::new(radius, radius))
+    												 .dest(position)
     												 .color(self.color));
+    // Line from centre to surface showing how far the body has turned
+    canvas.draw(marker_mesh, DrawParam::new()
+    												 .scale(Vector2::new(radius, radius))
+    												 .rotation(self.rotation - camera.rotation())
+    												 .dest(position));
 
-    if text_debug {
+    if let Some(units) = text_debug {
       const DEBUG_TEXT_SCALE: f32 = 0.7;
 
-      let debug_text = graphics::Text::new(
-        format!("ID: {}\nMass: {}\nRad: {}",
-          self.id,
-          self.mass,
-          self.radius
-        )
+      let mut text = format!("ID: {}\nMass: {}\nRad: {}\nSpin: {}",
+        self.id,
+        units.format(Quantity::Mass, self.mass as f64),
+        units.format(Quantity::Length, self.radius as f64),
+        units.format(Quantity::AngularVelocity, self.spin as f64),
       );
+      if let Some(halo) = self.halo {
+        text += &format!("\nHalo: {} (a = {})",
+          units.format(Quantity::Mass, halo.mass as f64),
+          units.format(Quantity::Length, halo.scale_radius as f64),
+        );
+      }
+      if let Some((primary, elements)) = orbit {
+        text += &format!("\nOrbiting: {}\na: {}\ne: {:.4}\nw: {:.1} deg\nv: {:.1} deg\nT: {}",
+          primary,
+          units.format(Quantity::Length, elements.semi_major_axis as f64),
+          elements.eccentricity,
+          elements.argument_of_periapsis.to_degrees(),
+          elements.true_anomaly.to_degrees(),
+          match elements.period {
+            Some(period) => units.format(Quantity::Time, period as f64),
+            None => "unbound".to_string(),
+          },
+        );
+      }
+      let debug_text = graphics::Text::new(text);
 
       canvas.draw(
         &debug_text,
         DrawParam::new()
           .scale(Vector2::new(DEBUG_TEXT_SCALE, DEBUG_TEXT_SCALE))
-          .dest(Point2::new(self.position.x + self.radius, self.position.y - self.radius))
+          .dest(Point2::new(position.x + radius, position.y - radius))
       );
     }
 
     if vector_debug {
       // Draw velocity vector
-      if self.velocity.magnitude_squared() > 1.0 {    // Make sure larger than 1 pixel first
+      let velocity_end = camera.to_screen(self.position + self.velocity);
+      if (velocity_end - position).magnitude_squared() > 1.0 {    // Make sure larger than 1 pixel first
         let line_mesh = Mesh::new_line(
           ctx,
-          &[self.position, self.position + self.velocity],
+          &[position, velocity_end],
           1.0,
           [0.0, 1.0, 0.0, 1.0].into()
         )?;
@@ -110,11 +188,11 @@ impl Planet {
       }
 
       // Draw force vector
-      if self.resultant_force.magnitude_squared() > 1.0/ACC_DEBUG_VECTOR_MULTIPLIER {
+      let force_end = camera.to_screen(self.position + self.resultant_force * ACC_DEBUG_VECTOR_MULTIPLIER/self.total_mass());
+      if (force_end - position).magnitude_squared() > 1.0 {
         let line_mesh = Mesh::new_line(
           ctx,
-          &[self.position,
-            self.position + self.resultant_force * ACC_DEBUG_VECTOR_MULTIPLIER/self.mass],
+          &[position, force_end],
           1.0,
           [1.0, 0.0, 0.0, 1.0].into()
         )?;
@@ -125,19 +203,34 @@ impl Planet {
     Ok(())
   }
 
+  pub fn color(&self) -> Color {
+    self.color
+  }
+
   pub fn update_color(&mut self) {
     let (r, g, b) = hsv_to_rgb((self.radius/PLANET_RADIUS_COLORING_LOOP % 1.0, 1.0, 1.0));
     self.color = [r, g, b, 1.0].into();
   }
 
-  fn mass_from_radius(radius: f32, density: f32) -> f32 {
-    // m = vd
-    tools::volume_of_sphere(radius) * density
+  // Mass including any halo, which moves with the body
+  pub fn total_mass(&self) -> f32 {
+    self.mass + self.halo.map_or(0.0, |halo| halo.mass)
   }
 
-  fn radius_from_mass(mass: f32, density: f32) -> f32 {
-    // v = m/d, r = cube_root( 3v/4pi )
-    tools::inverse_volume_of_sphere(mass/density)
+  // Solid sphere: I = 2/5 mr^2. A halo does not spin with the body.
+  pub fn moment_of_inertia(&self) -> f32 {
+    0.4 * self.mass * self.radius.powi(2)
+  }
+
+  // Spin plus orbital angular momentum about the given point
+  pub fn angular_momentum(&self, origin: Point2<f32>, frame_velocity: Vector2<f32>) -> f32 {
+    self.moment_of_inertia() * self.spin +
+      self.total_mass() * tools::cross(self.position - origin, self.velocity - frame_velocity)
+  }
+
+  pub fn mass_from_radius(radius: f32, density: f32) -> f32 {
+    // m = vd
+    tools::volume_of_sphere(radius) * density
   }
 
   pub fn has_spawn_protection(&self) -> bool {
@@ -145,64 +238,79 @@ impl Planet {
   }
 }
 
-const PLANET_TRAIL_NODE_PLACEMENT_PERIOD: u64 = 20;
-const PLANET_TRAIL_NODE_LIFETIME: f32 = 0.7;
-
+// Line left behind a body, aged in simulated time so it stops fading while paused and keeps its length when
+// time is sped up
+#[derive(Serialize, Deserialize)]
 pub struct PlanetTrail {
-  nodes: VecDeque<PlanetTrailNode>,
-  node_placement_timer: Duration,
+  nodes: VecDeque<PlanetTrailNode>,   // Oldest first
+  color: [f32; 3],                    // Of the body leaving it
   has_parent: bool,
 }
 
 impl PlanetTrail {
-  pub fn new(start_pos: Point2<f32>) -> Self {
+  pub fn new(start_pos: Point2<f32>, time: f64, color: Color) -> Self {
     let mut nodes = VecDeque::with_capacity(36);
-    nodes.push_front(PlanetTrailNode::from(start_pos));
+    nodes.push_front(PlanetTrailNode { pos: start_pos, time });
 
     Self {
       nodes,
-      node_placement_timer: Duration::new(0, 0),
+      color: [color.r, color.g, color.b],
       has_parent: true,
     }
   }
 
-  pub fn update(&mut self, dt_duration: &Duration, parent_pos: Option<Point2<f32>>) {
-    self.kill_dead_nodes();
+  // Called after every step with the simulation's time, and the body leaving the trail until it's gone.
+  // A node goes down every `trail_node_period` simulated seconds, or sooner if the body has moved
+  // `trail_node_spacing` since the last one, so fast bodies still get smooth trails.
+  pub fn update(&mut self, time: f64, parent: Option<&Planet>, config: &SimConfig) {
+    self.kill_dead_nodes(time, config.trail_node_lifetime);
 
-    if let Some(parent_pos) = parent_pos {
+    if let Some(parent) = parent {
       self.has_parent = true;
-      self.node_placement_timer += *dt_duration;
-
-      let period = Duration::from_millis(PLANET_TRAIL_NODE_PLACEMENT_PERIOD);
-      if self.node_placement_timer > period {
-        // Place new node
-        self.add_node(parent_pos);
-        self.node_placement_timer -= period;
+      self.color = [parent.color.r, parent.color.g, parent.color.b];
+
+      let due = self.nodes.back().is_none_or(|last| {
+        time - last.time >= config.trail_node_period as f64 ||
+          (parent.position - last.pos).magnitude_squared() >= config.trail_node_spacing.powi(2)
+      });
+      if due {
+        self.add_node(parent.position, time);
+      }
+      while self.nodes.len() > config.trail_max_nodes as usize {
+        self.nodes.pop_front();
       }
     } else {
       self.has_parent = false;
     }
   }
 
-  pub fn draw(&self, mesh: &mut MeshBuilder) -> GameResult<bool> {    // Returns if any line segments drawn
+  // In a rotating frame each node is drawn where the frame had it when it was placed, so the trail shows the
+  // path in the frame. Nodes older than the frame's record of itself are left out.
+  pub fn draw(&self, mesh: &mut MeshBuilder, config: &SimConfig, camera: &Camera, time: f64, frame: Option<&RotatingFrame>) -> GameResult<bool> {    // Returns if any line segments drawn
+    let to_screen = |node: &PlanetTrailNode| match frame {
+      Some(frame) => frame.pose_at(node.time).map(|pose| camera.to_screen_posed(node.pos, &pose)),
+      None => Some(camera.to_screen(node.pos)),
+    };
+
     let len = self.node_count();
     let mut draw_segments = 0;
     if len > 1 {
       for i in 0..len-1 {
+        let (Some(start), Some(end)) = (to_screen(&self.nodes[i]), to_screen(&self.nodes[i + 1])) else { continue };
         if (self.nodes[i].pos.x - self.nodes[i + 1].pos.x).powi(2) +
           (self.nodes[i].pos.y - self.nodes[i + 1].pos.y).powi(2) <
-          (SCREEN_DIMS.0.min(SCREEN_DIMS.1)/2.0).powi(2)  // Make sure line length is less than half the minimum screen dimensions.
+          (SCREEN_DIMS.0.min(SCREEN_DIMS.1)/2.0).powi(2) &&  // Make sure line length is less than half the minimum screen dimensions.
+          (end - start).magnitude_squared() > 0.01          // and long enough on screen to draw when zoomed out
         {
           draw_segments += 1;
           // Change transpacency depending on how long the node has been alive.
-          let mut alpha = 1.0 - (Instant::now().duration_since(self.nodes[i].time_created).as_secs_f32() /
-                     PLANET_TRAIL_NODE_LIFETIME);
+          let mut alpha = 1.0 - ((time - self.nodes[i].time) as f32/config.trail_node_lifetime);
           alpha = alpha.max(0.0).powi(2);
   
           mesh.line(
-            &[self.nodes[i].pos, self.nodes[i + 1].pos],
+            &[start, end],
             1.0,
-            [0.1, 0.4, 1.0, alpha].into()
+            [self.color[0], self.color[1], self.color[2], alpha].into()
           )?;
         }
       }
@@ -211,9 +319,9 @@ impl PlanetTrail {
     Ok(draw_segments > 0)
   }
 
-  fn kill_dead_nodes(&mut self) {
+  fn kill_dead_nodes(&mut self, time: f64, lifetime: f32) {
     while let Some(node) = self.nodes.front() {
-      if Instant::now().duration_since(node.time_created).as_secs_f32() >= PLANET_TRAIL_NODE_LIFETIME {
+      if time - node.time >= lifetime as f64 {
         self.nodes.pop_front();
       } else {
         break
@@ -229,32 +337,20 @@ impl PlanetTrail {
     self.nodes.is_empty() && !self.has_parent
   }
 
-  pub fn add_node(&mut self, pos: Point2<f32>) {
+  pub fn add_node(&mut self, pos: Point2<f32>, time: f64) {
     // Make sure distance from last node is a sufficient distance so that line can be drawn without errors
-    let can_place = {
-      if let Some(last_node) = self.nodes.back() {
-        self.nodes.is_empty() || ((pos.x - last_node.pos.x).powi(2) + (pos.y - last_node.pos.y).powi(2)) > 0.1
-      } else {
-        false
-      }
-    };
+    let can_place = self.nodes.back().is_none_or(|last_node| {
+      (pos.x - last_node.pos.x).powi(2) + (pos.y - last_node.pos.y).powi(2) > 0.1
+    });
 
     if can_place {
-      self.nodes.push_back(PlanetTrailNode::from(pos));
+      self.nodes.push_back(PlanetTrailNode { pos, time });
     }
   }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 struct PlanetTrailNode {
   pos: Point2<f32>,
-  time_created: Instant,
+  time: f64,      // Simulated time it was placed
 }
-
-impl From<Point2<f32>> for PlanetTrailNode {
-  fn from(pos: Point2<f32>) -> Self {
-    Self {
-      pos,
-      time_created: Instant::now(),
-    }
-  }
-}
\ No newline at end of file
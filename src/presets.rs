@@ -0,0 +1,179 @@
+use nalgebra::{Point2, Vector2};
+
+use serde::Deserialize;
+
+use std::f64::consts::PI;
+
+use crate::simulation::Simulation;
+
+// Classic few-body orbits, with the published initial conditions in units where G = 1.
+// Every body has unit mass unless stated, the centre of mass sits at the origin and is at rest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodicOrbit {
+  FigureEight,      // Chenciner & Montgomery (2000), initial conditions from Simó
+  Lagrange,         // Equilateral triangle rotating rigidly, one unit a side
+  Pythagorean,      // Burrau's problem: masses 3, 4 and 5 at rest on a 3-4-5 triangle. Not periodic, ends in an ejection
+  BrouckeA1,        // Broucke (1975)
+  BrouckeA2,
+  BrouckeR1,
+  ButterflyI,       // Šuvakov & Dmitrašinović (2013)
+  #[serde(rename = "butterfly_ii")]
+  ButterflyII,
+  Bumblebee,
+  MothI,
+  Goggles,
+  YinYangIa,
+  Dragonfly,
+}
+
+impl PeriodicOrbit {
+  pub const ALL: [PeriodicOrbit; 13] = [
+    PeriodicOrbit::FigureEight,
+    PeriodicOrbit::Lagrange,
+    PeriodicOrbit::Pythagorean,
+    PeriodicOrbit::BrouckeA1,
+    PeriodicOrbit::BrouckeA2,
+    PeriodicOrbit::BrouckeR1,
+    PeriodicOrbit::ButterflyI,
+    PeriodicOrbit::ButterflyII,
+    PeriodicOrbit::Bumblebee,
+    PeriodicOrbit::MothI,
+    PeriodicOrbit::Goggles,
+    PeriodicOrbit::YinYangIa,
+    PeriodicOrbit::Dragonfly,
+  ];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      PeriodicOrbit::FigureEight => "Figure-eight",
+      PeriodicOrbit::Lagrange => "Lagrange triangle",
+      PeriodicOrbit::Pythagorean => "Pythagorean three-body",
+      PeriodicOrbit::BrouckeA1 => "Broucke A1",
+      PeriodicOrbit::BrouckeA2 => "Broucke A2",
+      PeriodicOrbit::BrouckeR1 => "Broucke R1",
+      PeriodicOrbit::ButterflyI => "Butterfly I",
+      PeriodicOrbit::ButterflyII => "Butterfly II",
+      PeriodicOrbit::Bumblebee => "Bumblebee",
+      PeriodicOrbit::MothI => "Moth I",
+      PeriodicOrbit::Goggles => "Goggles",
+      PeriodicOrbit::YinYangIa => "Yin-yang Ia",
+      PeriodicOrbit::Dragonfly => "Dragonfly",
+    }
+  }
+
+  // The preset after this one, wrapping round
+  pub fn next(&self) -> PeriodicOrbit {
+    let index = Self::ALL.iter().position(|orbit| orbit == self).unwrap();
+    Self::ALL[(index + 1) % Self::ALL.len()]
+  }
+
+  // Period in units where G = 1, or None if the orbit isn't periodic
+  pub fn period(&self) -> Option<f64> {
+    match self {
+      PeriodicOrbit::FigureEight => Some(6.32591398),
+      PeriodicOrbit::Lagrange => Some(2.0 * PI/3f64.sqrt()),
+      PeriodicOrbit::Pythagorean => None,
+      PeriodicOrbit::BrouckeA1 => Some(6.283213),
+      PeriodicOrbit::BrouckeA2 => Some(7.702408),
+      PeriodicOrbit::BrouckeR1 => Some(5.226525),
+      PeriodicOrbit::ButterflyI => Some(6.235641),
+      PeriodicOrbit::ButterflyII => Some(7.003505),
+      PeriodicOrbit::Bumblebee => Some(63.534541),
+      PeriodicOrbit::MothI => Some(14.893911),
+      PeriodicOrbit::Goggles => Some(10.466818),
+      PeriodicOrbit::YinYangIa => Some(17.328370),
+      PeriodicOrbit::Dragonfly => Some(21.270975),
+    }
+  }
+
+  // Mass, position and velocity of each body, in units where G = 1
+  pub fn initial_conditions(&self) -> Vec<(f64, [f64; 2], [f64; 2])> {
+    match self {
+      PeriodicOrbit::FigureEight => {
+        let (x, y) = (0.97000436, -0.24308753);
+        let (vx, vy) = (-0.93240737, -0.86473146);
+        vec![
+          (1.0, [x, y], [-vx/2.0, -vy/2.0]),
+          (1.0, [-x, -y], [-vx/2.0, -vy/2.0]),
+          (1.0, [0.0, 0.0], [vx, vy]),
+        ]
+      },
+      PeriodicOrbit::Lagrange => {
+        // Each body circles the centre at side/sqrt(3) with speed sqrt(G m/side)
+        (0..3).map(|i| {
+          let angle = PI/2.0 + i as f64 * 2.0 * PI/3.0;
+          let (sin, cos) = angle.sin_cos();
+          (1.0, [cos/3f64.sqrt(), sin/3f64.sqrt()], [-sin, cos])
+        }).collect()
+      },
+      PeriodicOrbit::Pythagorean => vec![
+        (3.0, [1.0, 3.0], [0.0, 0.0]),
+        (4.0, [-2.0, -1.0], [0.0, 0.0]),
+        (5.0, [1.0, -1.0], [0.0, 0.0]),
+      ],
+      // Broucke's orbits start with every body on the x axis moving along y
+      PeriodicOrbit::BrouckeA1 => Self::collinear([-0.9892620043, 2.2096177241, -1.2203557197], [1.9169244185, 0.1910268738, -2.1079512924]),
+      PeriodicOrbit::BrouckeA2 => Self::collinear([0.3361300950, 0.7699893804, -1.1061194753], [1.5324315370, -0.6287350978, -0.9036964391]),
+      PeriodicOrbit::BrouckeR1 => Self::collinear([0.8083106230, -0.4954148566, -0.3128957664], [0.9901979166, -2.7171431768, 1.7269452602]),
+      // Šuvakov's orbits start collinear, the outer bodies sharing velocity (p1, p2) and the middle one moving at -2(p1, p2)
+      PeriodicOrbit::ButterflyI => Self::isosceles(0.306893, 0.125507),
+      PeriodicOrbit::ButterflyII => Self::isosceles(0.392955, 0.097579),
+      PeriodicOrbit::Bumblebee => Self::isosceles(0.184279, 0.587188),
+      PeriodicOrbit::MothI => Self::isosceles(0.464445, 0.396060),
+      PeriodicOrbit::Goggles => Self::isosceles(0.083300, 0.127889),
+      PeriodicOrbit::YinYangIa => Self::isosceles(0.513938, 0.304736),
+      PeriodicOrbit::Dragonfly => Self::isosceles(0.080584, 0.588836),
+    }
+  }
+
+  fn collinear(x: [f64; 3], vy: [f64; 3]) -> Vec<(f64, [f64; 2], [f64; 2])> {
+    (0..3).map(|i| (1.0, [x[i], 0.0], [0.0, vy[i]])).collect()
+  }
+
+  fn isosceles(p1: f64, p2: f64) -> Vec<(f64, [f64; 2], [f64; 2])> {
+    vec![
+      (1.0, [-1.0, 0.0], [p1, p2]),
+      (1.0, [1.0, 0.0], [p1, p2]),
+      (1.0, [0.0, 0.0], [-2.0 * p1, -2.0 * p2]),
+    ]
+  }
+
+  // How long one period lasts in a simulation with gravitational constant `g`, once lengths are scaled by
+  // `length_scale` and masses by `unit_mass`
+  pub fn scaled_period(&self, g: f32, length_scale: f32, unit_mass: f32) -> Option<f32> {
+    self.period().map(|period| period as f32 * time_scale(g, length_scale, unit_mass))
+  }
+}
+
+// Time unit of a system where G, `length_scale` and `unit_mass` are all 1
+fn time_scale(g: f32, length_scale: f32, unit_mass: f32) -> f32 {
+  (length_scale.powi(3)/(g * unit_mass)).sqrt()
+}
+
+impl Simulation {
+  // Spawns one of the preset orbits around `centre`. A length of one in the published initial conditions becomes
+  // `length_scale` and a mass of one becomes `unit_mass`, with velocities scaled to match the simulation's G.
+  // Heavier bodies get radii growing with the cube root of their mass. Returns the IDs of the new bodies.
+  pub fn add_periodic_orbit(
+    &mut self,
+    orbit: PeriodicOrbit,
+    centre: Point2<f32>,
+    length_scale: f32,
+    unit_mass: f32,
+    body_radius: f32,
+  ) -> Vec<usize> {
+    assert!(length_scale > 0.0 && unit_mass > 0.0);
+
+    let velocity_scale = length_scale/time_scale(self.config().gravitational_constant, length_scale, unit_mass);
+    orbit.initial_conditions().into_iter().map(|(mass, position, velocity)| {
+      self.add_planet(
+        centre + Vector2::new(position[0] as f32, position[1] as f32) * length_scale,
+        Some(Vector2::new(velocity[0] as f32, velocity[1] as f32) * velocity_scale),
+        Some(mass as f32 * unit_mass),
+        body_radius * (mass as f32).cbrt(),
+        None,
+      )
+    }).collect()
+  }
+}
@@ -0,0 +1,108 @@
+use nalgebra::{Point2, Vector2};
+
+use std::collections::HashMap;
+
+use crate::simulation::Simulation;
+
+// Predicts where a body about to be added would go, by running a copy of the simulation ahead with the body in it.
+// Only the heaviest bodies are copied, as they set the path and the copy is run again every frame.
+
+pub const PREVIEW_MAX_BODIES: usize = 100;
+const MAX_PREVIEW_STEPS: usize = 2000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+  Survives,                             // Still going at the end of the horizon
+  Collides { with: usize, time: f32 },  // First body it touches, and the simulated seconds until it does
+  Escapes { time: f32 },                // Unbound, heading away, and further out than anything else
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prediction {
+  pub path: Vec<Point2<f32>>,   // One point per step, starting where the body is launched
+  pub outcome: Outcome,
+}
+
+// Runs ahead by `horizon` simulated seconds in steps of `dt`, stopping early at a collision or escape
+pub fn predict(simulation: &Simulation, position: Point2<f32>, velocity: Vector2<f32>, radius: f32, horizon: f32, dt: f32) -> Prediction {
+  assert!(dt > 0.0, "Preview step must be positive, got {}", dt);
+
+  let mut copy = Simulation::new();
+  copy.set_config(*simulation.config());
+  copy.collision_mode = simulation.collision_mode;
+  copy.integrator = simulation.integrator;
+  copy.gravity_solver = simulation.gravity_solver;
+
+  let mut heaviest: Vec<_> = simulation.planets.iter().map(|(id, planet)| (*id, planet.borrow().total_mass())).collect();
+  heaviest.sort_unstable_by(|(id_a, a), (id_b, b)| b.total_cmp(a).then(id_a.cmp(id_b)));
+  let mut original_ids = HashMap::new();    // Copy's ID to the simulation's
+  for (id, _) in heaviest.into_iter().take(PREVIEW_MAX_BODIES) {
+    let copy_id = copy.add_planet_raw(simulation.planets[&id].borrow().clone());
+    original_ids.insert(copy_id, id);
+  }
+  let body = copy.add_planet(position, Some(velocity), None, radius, None);
+
+  let mut path = vec![position];
+  let steps = ((horizon/dt).ceil() as usize).min(MAX_PREVIEW_STEPS);
+  for step in 1..=steps {
+    let time = step as f32 * dt;
+    let before: Vec<(usize, Point2<f32>)> = copy.planets.iter().map(|(id, planet)| (*id, planet.borrow().position)).collect();
+    let mass_before = copy.planets[&body].borrow().mass;
+    copy.step(dt);
+
+    // Merging either takes the body away, or grows it at the expense of whatever it hit
+    let last = *path.last().unwrap();
+    let absorbed = !copy.planets.contains_key(&body);
+    let grown = !absorbed && copy.planets[&body].borrow().mass > mass_before;
+    if absorbed || grown {
+      // What it went into, or what went into it, nearest where it was
+      let hit = before.iter()
+        .filter(|(id, _)| *id != body && copy.planets.contains_key(id) == absorbed)
+        .min_by(|(_, a), (_, b)| (a - last).magnitude_squared().total_cmp(&(b - last).magnitude_squared()));
+      if let Some((with, _)) = hit {
+        if grown {
+          path.push(copy.planets[&body].borrow().position);
+        }
+        return Prediction { path, outcome: Outcome::Collides { with: original_ids[with], time } };
+      }
+    }
+
+    let planet = copy.planets[&body].borrow();
+    path.push(planet.position);
+
+    // Bodies pushing against each other instead
+    let touching = copy.planets.iter()
+      .filter(|(id, _)| **id != body)
+      .find(|(_, other)| {
+        let other = other.borrow();
+        (other.position - planet.position).magnitude_squared() < (other.radius + planet.radius).powi(2)
+      });
+    if let Some((with, _)) = touching {
+      return Prediction { path, outcome: Outcome::Collides { with: original_ids[with], time } };
+    }
+
+    if escaping(&copy, body) {
+      return Prediction { path, outcome: Outcome::Escapes { time } };
+    }
+  }
+
+  Prediction { path, outcome: Outcome::Survives }
+}
+
+// Whether the body is unbound from everything else taken together, moving away from it, and beyond all of it
+fn escaping(simulation: &Simulation, id: usize) -> bool {
+  let body = simulation.planets[&id].borrow();
+  let others: Vec<_> = simulation.planets.iter().filter(|(other, _)| **other != id).map(|(_, planet)| planet.borrow()).collect();
+  let mass: f32 = others.iter().map(|planet| planet.total_mass()).sum();
+  if mass <= 0.0 {
+    return false;
+  }
+
+  let centre = Point2::from(others.iter().map(|planet| planet.position.coords * planet.total_mass()).sum::<Vector2<f32>>()/mass);
+  let velocity = others.iter().map(|planet| planet.velocity * planet.total_mass()).sum::<Vector2<f32>>()/mass;
+  let (offset, relative_velocity) = (body.position - centre, body.velocity - velocity);
+  let outermost = others.iter().map(|planet| (planet.position - centre).magnitude() + planet.radius).fold(0.0, f32::max);
+
+  let energy = relative_velocity.magnitude_squared()/2.0 - simulation.config().gravitational_constant * mass/offset.magnitude();
+  energy > 0.0 && offset.dot(&relative_velocity) > 0.0 && offset.magnitude() > outermost
+}
@@ -0,0 +1,481 @@
+use ggez::graphics::Color;
+use nalgebra::Point2;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::TWO_PI;
+use crate::simulation::Simulation;
+
+// Recordings of a run that can be played back and scrubbed through without simulating anything.
+//
+// Each frame stores every live body, in ID order, as a quantized position and rotation. Positions are zigzag
+// varints: absolute on keyframes and for newly created bodies, otherwise the change since the previous frame,
+// which for most bodies fits in a byte. Bodies appearing, disappearing or changing size are stored as events,
+// and keyframes list every live body so playback can jump to any frame by decoding from the keyframe before it.
+
+pub const REPLAY_QUANTUM: f32 = 1.0/32.0;   // Positions are rounded to this many pixels
+pub const KEYFRAME_INTERVAL: usize = 120;
+const REPLAY_MAGIC: &[u8; 8] = b"ORBREPLY";
+const REPLAY_VERSION: u8 = 1;
+
+// How a body looks, which only changes when it is created or merges
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Appearance {
+  pub radius: f32,
+  pub color: [u8; 4],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayEvent {
+  Created { id: usize, appearance: Appearance },
+  Changed { id: usize, appearance: Appearance },
+  Destroyed { id: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Frame {
+  time: f64,
+  keyframe: Option<Vec<(usize, Appearance)>>,   // Every live body, on keyframes only
+  events: Vec<ReplayEvent>,                     // Since the previous frame, on other frames
+  states: Vec<u8>,
+}
+
+// A body as drawn on a frame of playback
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplayBody {
+  pub id: usize,
+  pub position: Point2<f32>,
+  pub rotation: f32,
+  pub radius: f32,
+  pub color: Color,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct DecodedBody {
+  appearance: Appearance,
+  position: (i32, i32),
+  rotation: u8,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+  Io { path: PathBuf, source: io::Error },
+  Format(String),   // Not a recording, from another version, or cut short
+}
+
+impl fmt::Display for ReplayError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ReplayError::Io { path, source } => write!(f, "couldn't access {}: {}", path.display(), source),
+      ReplayError::Format(reason) => write!(f, "recording is unreadable: {}", reason),
+    }
+  }
+}
+
+impl std::error::Error for ReplayError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      ReplayError::Io { source, .. } => Some(source),
+      ReplayError::Format(_) => None,
+    }
+  }
+}
+
+fn format_error(reason: impl Into<String>) -> ReplayError {
+  ReplayError::Format(reason.into())
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+  frames: Vec<Frame>,
+}
+
+impl Replay {
+  pub fn frame_count(&self) -> usize {
+    self.frames.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.frames.is_empty()
+  }
+
+  // Simulation time of a frame
+  pub fn frame_time(&self, frame: usize) -> f64 {
+    self.frames[frame].time
+  }
+
+  pub fn duration(&self) -> f64 {
+    match (self.frames.first(), self.frames.last()) {
+      (Some(first), Some(last)) => last.time - first.time,
+      _ => 0.0,
+    }
+  }
+
+  // The last frame at or before simulation time `time`
+  pub fn frame_at(&self, time: f64) -> usize {
+    self.frames.partition_point(|frame| frame.time <= time).saturating_sub(1)
+  }
+
+  // Events since the previous frame. Keyframes report the bodies they start with as created.
+  pub fn events(&self, frame: usize) -> Vec<ReplayEvent> {
+    let frame = &self.frames[frame];
+    match &frame.keyframe {
+      Some(bodies) => bodies.iter().map(|&(id, appearance)| ReplayEvent::Created { id, appearance }).collect(),
+      None => frame.events.clone(),
+    }
+  }
+
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+    let path = path.as_ref();
+    let io_error = |source| ReplayError::Io { path: path.to_path_buf(), source };
+    let mut file = io::BufWriter::new(fs::File::create(path).map_err(io_error)?);
+    self.write_to(&mut file).and_then(|_| file.flush()).map_err(io_error)
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> Result<Replay, ReplayError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|source| ReplayError::Io { path: path.to_path_buf(), source })?;
+    Self::from_bytes(&bytes)
+  }
+
+  pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+    out.write_all(REPLAY_MAGIC)?;
+    out.write_all(&[REPLAY_VERSION])?;
+    out.write_all(&REPLAY_QUANTUM.to_le_bytes())?;
+    write_varint(out, KEYFRAME_INTERVAL as u64)?;
+    write_varint(out, self.frames.len() as u64)?;
+
+    for frame in self.frames.iter() {
+      out.write_all(&frame.time.to_le_bytes())?;
+      match &frame.keyframe {
+        Some(bodies) => {
+          out.write_all(&[1])?;
+          write_varint(out, bodies.len() as u64)?;
+          for (id, appearance) in bodies.iter() {
+            write_varint(out, *id as u64)?;
+            write_appearance(out, appearance)?;
+          }
+        },
+        None => {
+          out.write_all(&[0])?;
+          write_varint(out, frame.events.len() as u64)?;
+          for event in frame.events.iter() {
+            match event {
+              ReplayEvent::Created { id, appearance } | ReplayEvent::Changed { id, appearance } => {
+                out.write_all(&[if matches!(event, ReplayEvent::Created { .. }) { 0 } else { 1 }])?;
+                write_varint(out, *id as u64)?;
+                write_appearance(out, appearance)?;
+              },
+              ReplayEvent::Destroyed { id } => {
+                out.write_all(&[2])?;
+                write_varint(out, *id as u64)?;
+              },
+            }
+          }
+        },
+      }
+      write_varint(out, frame.states.len() as u64)?;
+      out.write_all(&frame.states)?;
+    }
+    Ok(())
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    self.write_to(&mut bytes).expect("Writing to a Vec can't fail");
+    bytes
+  }
+
+  pub fn from_bytes(mut bytes: &[u8]) -> Result<Replay, ReplayError> {
+    let input = &mut bytes;
+    let mut magic = [0; 8];
+    read_exact(input, &mut magic)?;
+    if &magic != REPLAY_MAGIC {
+      return Err(format_error("not a recording"));
+    }
+    let version = read_u8(input)?;
+    if version != REPLAY_VERSION {
+      return Err(format_error(format!("recording is version {}, but this build reads version {}", version, REPLAY_VERSION)));
+    }
+    let mut quantum = [0; 4];
+    read_exact(input, &mut quantum)?;
+    if f32::from_le_bytes(quantum) != REPLAY_QUANTUM || read_varint(input)? != KEYFRAME_INTERVAL as u64 {
+      return Err(format_error("recorded with different quantization or keyframe settings"));
+    }
+
+    // A frame takes at least its time, keyframe flag, body or event count and state length
+    let frame_count = read_count(input, 11)?;
+    let mut frames = Vec::with_capacity(frame_count);
+    for index in 0..frame_count {
+      let mut time = [0; 8];
+      read_exact(input, &mut time)?;
+      let is_keyframe = read_u8(input)? == 1;
+      if is_keyframe != index.is_multiple_of(KEYFRAME_INTERVAL) {
+        return Err(format_error(format!("frame {} is in the wrong place for a keyframe", index)));
+      }
+
+      let mut frame = Frame { time: f64::from_le_bytes(time), keyframe: None, events: Vec::new(), states: Vec::new() };
+      if is_keyframe {
+        let count = read_count(input, 9)?;
+        let bodies = (0..count)
+          .map(|_| Ok((read_varint(input)? as usize, read_appearance(input)?)))
+          .collect::<Result<Vec<_>, ReplayError>>()?;
+        frame.keyframe = Some(bodies);
+      } else {
+        for _ in 0..read_count(input, 2)? {
+          let tag = read_u8(input)?;
+          let id = read_varint(input)? as usize;
+          frame.events.push(match tag {
+            0 => ReplayEvent::Created { id, appearance: read_appearance(input)? },
+            1 => ReplayEvent::Changed { id, appearance: read_appearance(input)? },
+            2 => ReplayEvent::Destroyed { id },
+            _ => return Err(format_error(format!("unknown event {} on frame {}", tag, index))),
+          });
+        }
+      }
+      frame.states = vec![0; read_count(input, 1)?];
+      read_exact(input, &mut frame.states)?;
+      frames.push(frame);
+    }
+
+    let replay = Replay { frames };
+    // Decode everything once, so a damaged recording fails here rather than part way through playback
+    let mut cursor = ReplayCursor::new();
+    for frame in 0..replay.frame_count() {
+      cursor.advance(&replay, frame)?;
+    }
+    Ok(replay)
+  }
+}
+
+// Records a simulation's state after each step it's given
+#[derive(Default)]
+pub struct ReplayRecorder {
+  replay: Replay,
+  previous: BTreeMap<usize, (Appearance, (i32, i32))>,
+}
+
+impl ReplayRecorder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn frame_count(&self) -> usize {
+    self.replay.frame_count()
+  }
+
+  // Adds a frame, unless the simulation's time has gone back since the last one, as when the scene is replaced.
+  // Frames have to stay in time order for seeking, so that is refused and false returned.
+  pub fn record(&mut self, simulation: &Simulation) -> bool {
+    if self.replay.frames.last().is_some_and(|last| simulation.time < last.time) {
+      return false;
+    }
+
+    let mut current = BTreeMap::new();
+    let mut rotations = BTreeMap::new();
+    for (id, planet) in simulation.planets.iter() {
+      let planet = planet.borrow();
+      let (r, g, b, a) = planet.color().to_rgba();
+      let appearance = Appearance { radius: planet.radius, color: [r, g, b, a] };
+      current.insert(*id, (appearance, quantize(planet.position)));
+      rotations.insert(*id, (planet.rotation.rem_euclid(TWO_PI)/TWO_PI * 256.0) as u8);
+    }
+
+    let is_keyframe = self.replay.frames.len().is_multiple_of(KEYFRAME_INTERVAL);
+    let mut frame = Frame { time: simulation.time, keyframe: None, events: Vec::new(), states: Vec::new() };
+    if is_keyframe {
+      frame.keyframe = Some(current.iter().map(|(id, (appearance, _))| (*id, *appearance)).collect());
+    } else {
+      for id in self.previous.keys().filter(|id| !current.contains_key(id)) {
+        frame.events.push(ReplayEvent::Destroyed { id: *id });
+      }
+      for (id, (appearance, _)) in current.iter() {
+        match self.previous.get(id) {
+          None => frame.events.push(ReplayEvent::Created { id: *id, appearance: *appearance }),
+          Some((previous, _)) if previous != appearance => frame.events.push(ReplayEvent::Changed { id: *id, appearance: *appearance }),
+          Some(_) => (),
+        }
+      }
+    }
+
+    for (id, (_, position)) in current.iter() {
+      let base = match self.previous.get(id) {
+        Some((_, previous)) if !is_keyframe => *previous,
+        _ => (0, 0),
+      };
+      write_zigzag(&mut frame.states, position.0.wrapping_sub(base.0));
+      write_zigzag(&mut frame.states, position.1.wrapping_sub(base.1));
+      frame.states.push(rotations[id]);
+    }
+
+    self.replay.frames.push(frame);
+    self.previous = current;
+    true
+  }
+
+  pub fn finish(self) -> Replay {
+    self.replay
+  }
+}
+
+// Walks through a replay, holding the decoded bodies of one frame
+#[derive(Default)]
+pub struct ReplayCursor {
+  frame: Option<usize>,
+  bodies: BTreeMap<usize, DecodedBody>,
+}
+
+impl ReplayCursor {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn frame(&self) -> Option<usize> {
+    self.frame
+  }
+
+  // Moves to `frame`, decoding forward from where the cursor is if that's closer than the keyframe before it
+  pub fn seek(&mut self, replay: &Replay, frame: usize) -> Result<(), ReplayError> {
+    let keyframe = frame - frame % KEYFRAME_INTERVAL;
+    let start = match self.frame {
+      Some(current) if current <= frame && current >= keyframe => current + 1,
+      _ => keyframe,
+    };
+    for index in start..=frame {
+      self.advance(replay, index)?;
+    }
+    Ok(())
+  }
+
+  pub fn bodies(&self) -> impl Iterator<Item = ReplayBody> + '_ {
+    self.bodies.iter().map(|(id, body)| ReplayBody {
+      id: *id,
+      position: Point2::new(body.position.0 as f32 * REPLAY_QUANTUM, body.position.1 as f32 * REPLAY_QUANTUM),
+      rotation: body.rotation as f32/256.0 * TWO_PI,
+      radius: body.appearance.radius,
+      color: {
+        let [r, g, b, a] = body.appearance.color;
+        Color::from_rgba(r, g, b, a)
+      },
+    })
+  }
+
+  // Decodes `index`, which must be a keyframe or the frame after the current one
+  fn advance(&mut self, replay: &Replay, index: usize) -> Result<(), ReplayError> {
+    let frame = &replay.frames[index];
+    let mut new_bodies = Vec::new();
+    match &frame.keyframe {
+      Some(bodies) => {
+        self.bodies = bodies.iter()
+          .map(|&(id, appearance)| (id, DecodedBody { appearance, position: (0, 0), rotation: 0 }))
+          .collect();
+      },
+      None => {
+        for event in frame.events.iter() {
+          match *event {
+            ReplayEvent::Created { id, appearance } => {
+              self.bodies.insert(id, DecodedBody { appearance, position: (0, 0), rotation: 0 });
+              new_bodies.push(id);
+            },
+            ReplayEvent::Changed { id, appearance } => {
+              self.bodies.get_mut(&id)
+                .ok_or_else(|| format_error(format!("body {} changes on frame {} but doesn't exist", id, index)))?
+                .appearance = appearance;
+            },
+            ReplayEvent::Destroyed { id } => {
+              self.bodies.remove(&id)
+                .ok_or_else(|| format_error(format!("body {} is destroyed on frame {} but doesn't exist", id, index)))?;
+            },
+          }
+        }
+      },
+    }
+
+    let mut states = frame.states.as_slice();
+    for (id, body) in self.bodies.iter_mut() {
+      let (dx, dy) = (read_zigzag(&mut states)?, read_zigzag(&mut states)?);
+      if frame.keyframe.is_some() || new_bodies.contains(id) {
+        body.position = (dx, dy);
+      } else {
+        body.position = (body.position.0.wrapping_add(dx), body.position.1.wrapping_add(dy));
+      }
+      body.rotation = read_u8(&mut states)?;
+    }
+    if !states.is_empty() {
+      return Err(format_error(format!("frame {} has more bodies than it should", index)));
+    }
+
+    self.frame = Some(index);
+    Ok(())
+  }
+}
+
+fn quantize(position: Point2<f32>) -> (i32, i32) {
+  ((position.x/REPLAY_QUANTUM).round() as i32, (position.y/REPLAY_QUANTUM).round() as i32)
+}
+
+fn write_varint(out: &mut impl Write, mut value: u64) -> io::Result<()> {
+  while value >= 0x80 {
+    out.write_all(&[(value as u8) | 0x80])?;
+    value >>= 7;
+  }
+  out.write_all(&[value as u8])
+}
+
+fn write_zigzag(out: &mut Vec<u8>, value: i32) {
+  write_varint(out, ((value << 1) ^ (value >> 31)) as u32 as u64).expect("Writing to a Vec can't fail");
+}
+
+fn write_appearance(out: &mut impl Write, appearance: &Appearance) -> io::Result<()> {
+  out.write_all(&appearance.radius.to_le_bytes())?;
+  out.write_all(&appearance.color)
+}
+
+fn read_exact(input: &mut &[u8], buffer: &mut [u8]) -> Result<(), ReplayError> {
+  input.read_exact(buffer).map_err(|_| format_error("recording is cut short"))
+}
+
+fn read_u8(input: &mut &[u8]) -> Result<u8, ReplayError> {
+  let mut byte = [0];
+  read_exact(input, &mut byte)?;
+  Ok(byte[0])
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, ReplayError> {
+  let mut value = 0;
+  for shift in (0..64).step_by(7) {
+    let byte = read_u8(input)?;
+    value |= ((byte & 0x7f) as u64) << shift;
+    if byte & 0x80 == 0 {
+      return Ok(value);
+    }
+  }
+  Err(format_error("number is too long"))
+}
+
+// Reads how many items follow, each at least `item_size` bytes, refusing counts the rest of the input
+// can't hold so a damaged file can't ask for a huge allocation
+fn read_count(input: &mut &[u8], item_size: usize) -> Result<usize, ReplayError> {
+  let count = read_varint(input)?;
+  if count > (input.len() / item_size) as u64 {
+    return Err(format_error("recording is cut short"));
+  }
+  Ok(count as usize)
+}
+
+fn read_zigzag(input: &mut &[u8]) -> Result<i32, ReplayError> {
+  let value = read_varint(input)? as u32;
+  Ok(((value >> 1) as i32) ^ -((value & 1) as i32))
+}
+
+fn read_appearance(input: &mut &[u8]) -> Result<Appearance, ReplayError> {
+  let mut radius = [0; 4];
+  let mut color = [0; 4];
+  read_exact(input, &mut radius)?;
+  read_exact(input, &mut color)?;
+  Ok(Appearance { radius: f32::from_le_bytes(radius), color })
+}
@@ -0,0 +1,557 @@
+use nalgebra::{Point2, Vector2};
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::SimConfig;
+use crate::generators::{EncounterSpec, GalaxySpec, HaloKind, KING_MAX_W0};
+use crate::horizons::{self, HorizonsError};
+use crate::presets::PeriodicOrbit;
+use crate::simulation::{CollisionMode, OrbitError, Simulation};
+use crate::tools::OrbitSpec;
+use crate::units::{Conversion, Quantity, UnitSystem, Units};
+
+// A scene described in a TOML file: physics settings, explicit bodies, and generators that fill in groups of bodies.
+//
+//   name = "Binary star"
+//
+//   [physics]
+//   collision_mode = "soft_sphere"
+//
+//   [config]                      # Any SimConfig setting, see `config.rs`
+//   gravitational_constant = 2e-4
+//
+//   [units]                       # Units for every value below, see `units.rs`. Pixel units if left out.
+//
+//   [[bodies]]
+//   position = [640, 430]
+//   radius = 20
+//
+//   [[bodies]]
+//   primary = 0                   # Index of an earlier body to orbit
+//   orbit = { semi_major_axis = 150, eccentricity = 0.3 }
+//   radius = 5
+//
+//   [[generators]]
+//   kind = "plummer_cluster"
+//   centre = [300, 300]
+//   count = 200
+//   mass = 1e9
+//   scale_radius = 40
+//   body_radius = 1
+//
+//   [[horizons]]                  # Bodies from a JPL Horizons vector table, see `horizons.rs`. Needs [units].
+//   path = "data/inner_planets.txt"
+//   radius = 0.02
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+  #[serde(default)]
+  pub name: Option<String>,
+  #[serde(default)]
+  pub physics: Physics,
+  #[serde(default)]
+  pub config: SimConfig,
+  #[serde(default)]
+  pub units: Units,
+  #[serde(default)]
+  pub bodies: Vec<Body>,
+  #[serde(default)]
+  pub generators: Vec<Generator>,
+  #[serde(default)]
+  pub horizons: Vec<HorizonsImport>,
+
+  #[serde(skip)]
+  pub directory: Option<PathBuf>,   // Where the file was loaded from, for finding the files it refers to
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Physics {
+  #[serde(default)]
+  pub collision_mode: CollisionMode,
+}
+
+// A single body, placed either at a position or on an orbit around an earlier body
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Body {
+  pub position: Option<[f32; 2]>,
+  pub velocity: Option<[f32; 2]>,
+  pub primary: Option<usize>,
+  pub orbit: Option<OrbitSpec>,
+  pub mass: Option<f32>,      // Worked out from the radius if not given
+  pub radius: f32,
+}
+
+// One call to a generator in `generators.rs` or `presets.rs`, named by its `kind`
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Generator {
+  PlanetWithMoons {
+    position: [f32; 2],
+    velocity: Option<[f32; 2]>,
+    mass: Option<f32>,
+    radius: f32,
+    moons: usize,
+    orbit_radius: [f32; 2],     // Range, starting from the surface of the planet
+    moon_radius: [f32; 2],
+    #[serde(default)]
+    eccentricity: [f32; 2],
+    #[serde(default = "default_true")]
+    clockwise: bool,
+    toomre_q: Option<f32>,
+  },
+  RandomPlanets {
+    count: usize,
+    x: [f32; 2],
+    y: [f32; 2],
+    radius: [f32; 2],
+    speed: Option<[f32; 2]>,
+  },
+  SquareOfPlanets {
+    top_left: [f32; 2],
+    columns: u16,
+    rows: u16,
+    gap: f32,
+    radius: f32,
+  },
+  PlummerCluster {
+    centre: [f32; 2],
+    velocity: Option<[f32; 2]>,
+    count: usize,
+    mass: f32,
+    scale_radius: f32,
+    body_radius: f32,
+    #[serde(default = "default_true")]
+    virialise: bool,
+  },
+  KingCluster {
+    centre: [f32; 2],
+    velocity: Option<[f32; 2]>,
+    count: usize,
+    mass: f32,
+    core_radius: f32,
+    w0: f32,
+    body_radius: f32,
+    #[serde(default = "default_true")]
+    virialise: bool,
+  },
+  Galaxy {
+    centre: [f32; 2],
+    velocity: Option<[f32; 2]>,
+    #[serde(default)]
+    spec: GalaxySpec,
+  },
+  GalaxyMerger {
+    centre: [f32; 2],
+    #[serde(default)]
+    first: GalaxySpec,
+    #[serde(default)]
+    second: GalaxySpec,
+    encounter: EncounterSpec,
+  },
+  PeriodicOrbit {
+    orbit: PeriodicOrbit,
+    centre: [f32; 2],
+    length_scale: f32,
+    unit_mass: f32,
+    body_radius: f32,
+  },
+}
+
+// Every target in a JPL Horizons vector table, placed at its state relative to the table's centre body (the Sun,
+// usually), which goes at the origin of the scenario's units
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HorizonsImport {
+  pub path: PathBuf,                  // Relative to the scenario file
+  pub epoch: Option<f64>,             // Julian date (TDB) of the states to use, otherwise the first in each table
+  pub radius: f32,                    // Drawn radius of each body, as real ones would be too small to see
+  #[serde(default)]
+  pub radii: HashMap<String, f32>,    // By target name, in place of `radius`
+  #[serde(default)]
+  pub masses: HashMap<String, f32>,   // By target name. Needed for anything but the Sun, planets, Moon and Pluto.
+}
+
+fn default_true() -> bool {
+  true
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+  Io { path: PathBuf, source: io::Error },
+  Parse(toml::de::Error),
+  Invalid { item: String, reason: String },   // Parsed fine, but describes something that can't be built
+}
+
+impl fmt::Display for ScenarioError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ScenarioError::Io { path, source } => write!(f, "couldn't read {}: {}", path.display(), source),
+      ScenarioError::Parse(error) => write!(f, "{}", error),
+      ScenarioError::Invalid { item, reason } => write!(f, "{}: {}", item, reason),
+    }
+  }
+}
+
+impl std::error::Error for ScenarioError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      ScenarioError::Io { source, .. } => Some(source),
+      ScenarioError::Parse(error) => Some(error),
+      ScenarioError::Invalid { .. } => None,
+    }
+  }
+}
+
+// Fails with an `Invalid` error for `item` unless `condition` holds
+fn check(condition: bool, item: &str, reason: &str) -> Result<(), ScenarioError> {
+  if condition {
+    Ok(())
+  } else {
+    Err(ScenarioError::Invalid { item: item.to_string(), reason: reason.to_string() })
+  }
+}
+
+fn check_range(range: [f32; 2], item: &str, field: &str) -> Result<(), ScenarioError> {
+  check(range[0] < range[1], item, &format!("`{}` must be [min, max] with min below max, got {:?}", field, range))
+}
+
+fn check_galaxy(spec: &GalaxySpec, item: &str) -> Result<(), ScenarioError> {
+  check(spec.nucleus_mass > 0.0 && spec.nucleus_radius > 0.0, item, "the nucleus needs a positive mass and radius")?;
+  check(spec.disk_radius > 0.0 && spec.disk_scale_length > 0.0, item, "`disk_radius` and `disk_scale_length` must be positive")?;
+  check(spec.disk_radius > 3.0 * spec.nucleus_radius, item, "`disk_radius` must be more than 3 times `nucleus_radius`, where the disk starts")?;
+  check(spec.particle_radius > 0.0, item, "`particle_radius` must be positive")?;
+  check(spec.disk_toomre_q >= 0.0, item, "`disk_toomre_q` can't be negative")?;
+  check(spec.bulge_particles == 0 || spec.bulge_scale_radius > 0.0, item, "`bulge_scale_radius` must be positive")?;
+  check(spec.halo == HaloKind::None || spec.halo_scale_radius > 0.0, item, "`halo_scale_radius` must be positive")?;
+  check(spec.halo != HaloKind::Particles(0), item, "a particle halo needs at least one particle")
+}
+
+fn point(p: [f32; 2]) -> Point2<f32> {
+  Point2::new(p[0], p[1])
+}
+
+fn vector(v: Option<[f32; 2]>) -> Option<Vector2<f32>> {
+  v.map(|v| Vector2::new(v[0], v[1]))
+}
+
+impl Scenario {
+  pub fn load(path: impl AsRef<Path>) -> Result<Scenario, ScenarioError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+      .map_err(|source| ScenarioError::Io { path: path.to_path_buf(), source })?;
+    let mut scenario = Self::parse(&text)?;
+    scenario.directory = path.parent().map(Path::to_path_buf);
+    Ok(scenario)
+  }
+
+  pub fn parse(text: &str) -> Result<Scenario, ScenarioError> {
+    let scenario: Scenario = toml::from_str(text).map_err(ScenarioError::Parse)?;
+    scenario.validate()?;
+    Ok(scenario)
+  }
+
+  // Catches anything the generators would otherwise panic on, naming the offending entry
+  pub fn validate(&self) -> Result<(), ScenarioError> {
+    self.config.validate().map_err(|reason| ScenarioError::Invalid { item: "config".to_string(), reason })?;
+    self.units.validate().map_err(|reason| ScenarioError::Invalid { item: "units".to_string(), reason })?;
+
+    for (i, body) in self.bodies.iter().enumerate() {
+      let item = format!("bodies[{}]", i);
+      check(body.radius > 0.0, &item, "`radius` must be positive")?;
+      check(body.mass.is_none_or(|mass| mass > 0.0), &item, "`mass` must be positive")?;
+      match (body.position, body.primary, body.orbit) {
+        (Some(_), None, None) => (),
+        (None, Some(primary), Some(orbit)) => {
+          check(primary < i, &item, &format!("`primary` must be the index of an earlier body, got {}", primary))?;
+          check(body.velocity.is_none(), &item, "`velocity` comes from the orbit, so can't be given too")?;
+          let shape = OrbitError::InvalidShape { semi_major_axis: orbit.semi_major_axis, eccentricity: orbit.eccentricity };
+          check(orbit.semi_latus_rectum().is_some(), &item, &shape.to_string())?;
+          check(orbit.reaches_true_anomaly(), &item, &OrbitError::BeyondAsymptote { true_anomaly: orbit.true_anomaly }.to_string())?;
+        },
+        (None, None, Some(_)) => check(false, &item, "`orbit` needs a `primary` to orbit")?,
+        (None, Some(_), None) => check(false, &item, "`primary` needs an `orbit` to put the body on")?,
+        (None, None, None) => check(false, &item, "needs either a `position`, or a `primary` and `orbit`")?,
+        (Some(_), _, _) => check(false, &item, "has a `position` so can't be given a `primary` or `orbit` too")?,
+      }
+    }
+
+    for (i, generator) in self.generators.iter().enumerate() {
+      let item = format!("generators[{}]", i);
+      match *generator {
+        Generator::PlanetWithMoons { mass, radius, orbit_radius, moon_radius, eccentricity, toomre_q, .. } => {
+          check(radius > 0.0, &item, "`radius` must be positive")?;
+          check(mass.is_none_or(|mass| mass > 0.0), &item, "`mass` must be positive")?;
+          check_range(orbit_radius, &item, "orbit_radius")?;
+          check(orbit_radius[0] >= 0.0, &item, "`orbit_radius` can't be negative")?;
+          check_range(moon_radius, &item, "moon_radius")?;
+          check(moon_radius[0] > 0.0, &item, "`moon_radius` must be positive")?;
+          check(
+            eccentricity[0] >= 0.0 && eccentricity[0] <= eccentricity[1] && eccentricity[1] < 1.0,
+            &item,
+            &format!("`eccentricity` must be [min, max] within 0 to 1, got {:?}", eccentricity),
+          )?;
+          check(toomre_q.is_none_or(|q| q > 0.0), &item, "`toomre_q` must be positive")?;
+        },
+        Generator::RandomPlanets { count, x, y, radius, speed } => {
+          check(count > 0, &item, "`count` must be at least 1")?;
+          check_range(x, &item, "x")?;
+          check_range(y, &item, "y")?;
+          check_range(radius, &item, "radius")?;
+          check(radius[0] > 0.0, &item, "`radius` must be positive")?;
+          if let Some(speed) = speed {
+            check_range(speed, &item, "speed")?;
+          }
+        },
+        Generator::SquareOfPlanets { gap, radius, .. } => {
+          check(radius > 0.0, &item, "`radius` must be positive")?;
+          check(gap > 0.0, &item, "`gap` must be positive")?;
+        },
+        Generator::PlummerCluster { count, mass, scale_radius, body_radius, .. } => {
+          check(count > 0, &item, "`count` must be at least 1")?;
+          check(mass > 0.0 && scale_radius > 0.0 && body_radius > 0.0, &item, "`mass`, `scale_radius` and `body_radius` must be positive")?;
+        },
+        Generator::KingCluster { count, mass, core_radius, w0, body_radius, .. } => {
+          check(count > 0, &item, "`count` must be at least 1")?;
+          check(mass > 0.0 && core_radius > 0.0 && body_radius > 0.0, &item, "`mass`, `core_radius` and `body_radius` must be positive")?;
+          check(w0 > 0.0 && w0 <= KING_MAX_W0, &item, &format!("`w0` must be positive and at most {}", KING_MAX_W0))?;
+        },
+        Generator::Galaxy { spec, .. } => check_galaxy(&spec, &item)?,
+        Generator::GalaxyMerger { first, second, encounter, .. } => {
+          check_galaxy(&first, &format!("{}.first", item))?;
+          check_galaxy(&second, &format!("{}.second", item))?;
+          check(encounter.eccentricity >= 1.0, &item, "the encounter's `eccentricity` must be at least 1 (parabolic)")?;
+          check(encounter.pericentre > 0.0, &item, "the encounter's `pericentre` must be positive")?;
+          check(encounter.separation >= encounter.pericentre, &item, "the encounter's `separation` can't be less than its `pericentre`")?;
+        },
+        Generator::PeriodicOrbit { length_scale, unit_mass, body_radius, .. } => {
+          check(length_scale > 0.0 && unit_mass > 0.0 && body_radius > 0.0, &item, "`length_scale`, `unit_mass` and `body_radius` must be positive")?;
+        },
+      }
+    }
+
+    for (i, import) in self.horizons.iter().enumerate() {
+      let item = format!("horizons[{}]", i);
+      check(self.units.system != UnitSystem::Pixel, &item, "needs a `[units]` system to convert the table into")?;
+      check(import.radius > 0.0, &item, "`radius` must be positive")?;
+      check(import.radii.values().all(|radius| *radius > 0.0), &item, "`radii` must be positive")?;
+      check(import.masses.values().all(|mass| *mass > 0.0), &item, "`masses` must be positive")?;
+    }
+
+    Ok(())
+  }
+
+  // Adds everything in the scenario to `simulation`, bodies first so generators can surround them
+  pub fn apply(&self, simulation: &mut Simulation) -> Result<(), ScenarioError> {
+    self.validate()?;
+    simulation.collision_mode = self.physics.collision_mode;
+    simulation.set_config(self.config);
+    simulation.units = self.units;
+
+    let conversion = self.units.conversion(self.config.gravitational_constant);
+    let scenario = self.in_simulation_units(&conversion);
+
+    let mut body_ids = Vec::with_capacity(self.bodies.len());
+    for (i, body) in scenario.bodies.iter().enumerate() {
+      let id = match (body.position, body.primary, body.orbit) {
+        (Some(position), _, _) => simulation.add_planet(point(position), vector(body.velocity), body.mass, body.radius, None),
+        (None, Some(primary), Some(orbit)) => simulation.add_planet_in_orbit(body_ids[primary], &orbit, body.mass, body.radius)
+          .map_err(|error| ScenarioError::Invalid { item: format!("bodies[{}]", i), reason: error.to_string() })?,
+        _ => unreachable!("checked by validate"),
+      };
+      body_ids.push(id);
+    }
+
+    for (i, import) in scenario.horizons.iter().enumerate() {
+      self.import_horizons(simulation, import, &conversion)
+        .map_err(|reason| ScenarioError::Invalid { item: format!("horizons[{}]", i), reason })?;
+    }
+
+    for generator in scenario.generators.iter() {
+      match *generator {
+        Generator::PlanetWithMoons { position, velocity, mass, radius, moons, orbit_radius, moon_radius, eccentricity, clockwise, toomre_q } => {
+          simulation.add_planet_with_moons(
+            point(position),
+            vector(velocity),
+            mass,
+            radius,
+            moons,
+            (orbit_radius[0], orbit_radius[1]),
+            (moon_radius[0], moon_radius[1]),
+            (eccentricity[0], eccentricity[1]),
+            clockwise,
+            toomre_q,
+          );
+        },
+        Generator::RandomPlanets { count, x, y, radius, speed } => {
+          simulation.add_random_planets(count, (x[0], x[1]), (y[0], y[1]), (radius[0], radius[1]), speed.map(|s| (s[0], s[1])));
+        },
+        Generator::SquareOfPlanets { top_left, columns, rows, gap, radius } => {
+          simulation.spawn_square_of_planets(point(top_left), columns, rows, gap, radius);
+        },
+        Generator::PlummerCluster { centre, velocity, count, mass, scale_radius, body_radius, virialise } => {
+          simulation.add_plummer_cluster(point(centre), vector(velocity), count, mass, scale_radius, body_radius, virialise);
+        },
+        Generator::KingCluster { centre, velocity, count, mass, core_radius, w0, body_radius, virialise } => {
+          simulation.add_king_cluster(point(centre), vector(velocity), count, mass, core_radius, w0, body_radius, virialise);
+        },
+        Generator::Galaxy { centre, velocity, spec } => {
+          simulation.add_galaxy(point(centre), vector(velocity), &spec);
+        },
+        Generator::GalaxyMerger { centre, first, second, encounter } => {
+          simulation.add_galaxy_merger(point(centre), &first, &second, &encounter);
+        },
+        Generator::PeriodicOrbit { orbit, centre, length_scale, unit_mass, body_radius } => {
+          simulation.add_periodic_orbit(orbit, point(centre), length_scale, unit_mass, body_radius);
+        },
+      }
+    }
+
+    Ok(())
+  }
+
+  // Adds the bodies in a Horizons table, whose radii and masses are already in simulation units
+  fn import_horizons(&self, simulation: &mut Simulation, import: &HorizonsImport, conversion: &Conversion) -> Result<(), String> {
+    let path = match &self.directory {
+      Some(directory) => directory.join(&import.path),
+      None => import.path.clone(),
+    };
+    let targets = horizons::load(&path).map_err(|error| match error {
+      HorizonsError::Io { .. } => error.to_string(),
+      HorizonsError::Format { .. } => format!("{}: {}", path.display(), error),
+    })?;
+    for name in import.radii.keys().chain(import.masses.keys()) {
+      if !targets.iter().any(|target| &target.name == name) {
+        return Err(format!("{} has no target named {:?}", path.display(), name));
+      }
+    }
+
+    // Horizons gives km and km/s, the scenario's units might not
+    let system = self.units.system;
+    let km = 1.0e3/system.si_scale(Quantity::Length).unwrap();
+    let km_per_second = 1.0e3/system.si_scale(Quantity::Velocity).unwrap();
+    for target in targets.iter() {
+      let state = target.state_at(import.epoch)
+        .ok_or_else(|| format!("{} has no state at JD {}", target.name, import.epoch.unwrap()))?;
+      let mass = match import.masses.get(&target.name) {
+        Some(mass) => *mass,
+        None => {
+          let kilograms = target.known_mass()
+            .ok_or_else(|| format!("the mass of {} isn't known, so must be given in `masses`", target.name))?;
+          conversion.to_sim(Quantity::Mass, (kilograms/system.si_scale(Quantity::Mass).unwrap()) as f32)
+        },
+      };
+      let radius = import.radii.get(&target.name).copied().unwrap_or(import.radius);
+
+      let (position, velocity) = state.projected();
+      let position = conversion.position_to_sim([(position.x * km) as f32, (position.y * km) as f32]);
+      let velocity = Vector2::new(
+        conversion.to_sim(Quantity::Velocity, (velocity.x * km_per_second) as f32),
+        conversion.to_sim(Quantity::Velocity, (velocity.y * km_per_second) as f32),
+      );
+      simulation.add_planet(position, Some(velocity), Some(mass), radius, None);
+    }
+    Ok(())
+  }
+
+  // A copy with every value converted from the scenario's units into the simulation's own
+  fn in_simulation_units(&self, conversion: &Conversion) -> Scenario {
+    let length = |x: f32| conversion.to_sim(Quantity::Length, x);
+    let mass = |m: f32| conversion.to_sim(Quantity::Mass, m);
+    let speed = |v: f32| conversion.to_sim(Quantity::Velocity, v);
+    let position = |p: [f32; 2]| { let p = conversion.position_to_sim(p); [p.x, p.y] };
+    let galaxy = |spec: &mut GalaxySpec| {
+      spec.nucleus_mass = mass(spec.nucleus_mass);
+      spec.nucleus_radius = length(spec.nucleus_radius);
+      spec.bulge_mass = mass(spec.bulge_mass);
+      spec.bulge_scale_radius = length(spec.bulge_scale_radius);
+      spec.disk_mass = mass(spec.disk_mass);
+      spec.disk_scale_length = length(spec.disk_scale_length);
+      spec.disk_radius = length(spec.disk_radius);
+      spec.halo_mass = mass(spec.halo_mass);
+      spec.halo_scale_radius = length(spec.halo_scale_radius);
+      spec.particle_radius = length(spec.particle_radius);
+    };
+
+    let mut scenario = self.clone();
+    for body in scenario.bodies.iter_mut() {
+      body.position = body.position.map(position);
+      body.velocity = body.velocity.map(|v| v.map(speed));
+      body.mass = body.mass.map(mass);
+      body.radius = length(body.radius);
+      if let Some(orbit) = body.orbit.as_mut() {
+        orbit.semi_major_axis = length(orbit.semi_major_axis);
+      }
+    }
+
+    for generator in scenario.generators.iter_mut() {
+      match generator {
+        Generator::PlanetWithMoons { position: p, velocity, mass: m, radius, orbit_radius, moon_radius, .. } => {
+          *p = position(*p);
+          *velocity = velocity.map(|v| v.map(speed));
+          *m = m.map(mass);
+          *radius = length(*radius);
+          *orbit_radius = orbit_radius.map(length);
+          *moon_radius = moon_radius.map(length);
+        },
+        Generator::RandomPlanets { x, y, radius, speed: s, .. } => {
+          *x = x.map(|x| position([x, 0.0])[0]);
+          *y = y.map(|y| position([0.0, y])[1]);
+          *radius = radius.map(length);
+          *s = s.map(|s| s.map(speed));
+        },
+        Generator::SquareOfPlanets { top_left, gap, radius, .. } => {
+          *top_left = position(*top_left);
+          *gap = length(*gap);
+          *radius = length(*radius);
+        },
+        Generator::PlummerCluster { centre, velocity, mass: m, scale_radius, body_radius, .. } => {
+          *centre = position(*centre);
+          *velocity = velocity.map(|v| v.map(speed));
+          *m = mass(*m);
+          *scale_radius = length(*scale_radius);
+          *body_radius = length(*body_radius);
+        },
+        Generator::KingCluster { centre, velocity, mass: m, core_radius, body_radius, .. } => {
+          *centre = position(*centre);
+          *velocity = velocity.map(|v| v.map(speed));
+          *m = mass(*m);
+          *core_radius = length(*core_radius);
+          *body_radius = length(*body_radius);
+        },
+        Generator::Galaxy { centre, velocity, spec } => {
+          *centre = position(*centre);
+          *velocity = velocity.map(|v| v.map(speed));
+          galaxy(spec);
+        },
+        Generator::GalaxyMerger { centre, first, second, encounter } => {
+          *centre = position(*centre);
+          galaxy(first);
+          galaxy(second);
+          encounter.pericentre = length(encounter.pericentre);
+          encounter.separation = length(encounter.separation);
+        },
+        Generator::PeriodicOrbit { centre, length_scale, unit_mass, body_radius, .. } => {
+          *centre = position(*centre);
+          *length_scale = length(*length_scale);
+          *unit_mass = mass(*unit_mass);
+          *body_radius = length(*body_radius);
+        },
+      }
+    }
+
+    for import in scenario.horizons.iter_mut() {
+      import.radius = length(import.radius);
+      import.radii.values_mut().for_each(|radius| *radius = length(*radius));
+      import.masses.values_mut().for_each(|m| *m = mass(*m));
+    }
+
+    scenario
+  }
+}
@@ -0,0 +1,695 @@
+use nalgebra::{Point2, Vector2};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::time::Duration;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::tools::{self, OrbitalElements, OrbitSpec, OrbitDirection};
+use crate::config::SimConfig;
+use crate::units::Units;
+use crate::barnes_hut::{QuadTree, TreeBody};
+use crate::planet::{Planet, Halo};
+use crate::diagnostics::{Diagnostics, Drift};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionMode {
+  #[default]
+  Merge,        // Colliding bodies combine into one
+  SoftSphere,   // Colliding bodies push against each other and can rest in contact
+}
+
+impl CollisionMode {
+  pub fn toggled(self) -> Self {
+    match self {
+      CollisionMode::Merge => CollisionMode::SoftSphere,
+      CollisionMode::SoftSphere => CollisionMode::Merge,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+  #[default]
+  Euler,        // Semi-implicit Euler: kick with the last step's forces, then drift
+  Leapfrog,     // Kick-drift-kick, so velocities are in step with positions and energy is measured properly
+}
+
+impl FromStr for Integrator {
+  type Err = String;
+
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    match name {
+      "euler" => Ok(Integrator::Euler),
+      "leapfrog" => Ok(Integrator::Leapfrog),
+      _ => Err("expected euler or leapfrog".to_string()),
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GravitySolver {
+  #[default]
+  Direct,       // Every pair, exactly
+  BarnesHut,    // Distant groups approximated by a quadtree, for large scenes
+}
+
+impl FromStr for GravitySolver {
+  type Err = String;
+
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    match name {
+      "direct" => Ok(GravitySolver::Direct),
+      "barnes-hut" => Ok(GravitySolver::BarnesHut),
+      _ => Err("expected direct or barnes-hut".to_string()),
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OrbitError {
+  NoSuchPrimary(usize),
+  InvalidShape { semi_major_axis: f32, eccentricity: f32 },    // Closed orbits need a > 0, open ones a < 0
+  BeyondAsymptote { true_anomaly: f32 },    // An open orbit never reaches this true anomaly
+}
+
+impl fmt::Display for OrbitError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      OrbitError::NoSuchPrimary(id) => write!(f, "primary body {} does not exist", id),
+      OrbitError::InvalidShape { semi_major_axis, eccentricity } => write!(f,
+        "semi-major axis {} is invalid for eccentricity {} (it must be positive when e < 1 and negative when e > 1)",
+        semi_major_axis, eccentricity,
+      ),
+      OrbitError::BeyondAsymptote { true_anomaly } => write!(f,
+        "true anomaly {} deg lies beyond the asymptotes of the hyperbolic orbit",
+        true_anomaly.to_degrees(),
+      ),
+    }
+  }
+}
+
+impl std::error::Error for OrbitError {}
+
+// The simulation core. Holds every body and advances them through time, without needing a window.
+#[derive(Serialize, Deserialize)]
+pub struct Simulation {
+  pub planets: HashMap<usize, RefCell<Planet>>,
+  pub collision_mode: CollisionMode,
+  #[serde(default)]
+  pub integrator: Integrator,
+  #[serde(default)]
+  pub gravity_solver: GravitySolver,
+  #[serde(default)]
+  config: SimConfig,
+  #[serde(default)]
+  pub units: Units,   // Used to show values, the simulation itself works in pixel units
+  pub time: f64,      // Simulated seconds since the last clear
+  pub steps: u64,
+  planet_id_count: usize,
+
+  diagnostics: Diagnostics,
+  initial_diagnostics: Option<Diagnostics>,   // Reference for drift, retaken whenever bodies are added or removed
+  initial_time: f64,
+  #[serde(skip)]
+  forces_current: bool,   // Whether each body's resultant force is still the one for where the bodies are now
+
+  #[serde(skip, default = "StdRng::from_entropy")]
+  rng: StdRng,    // Feeds the generators, so a seeded simulation builds the same scenes every time
+}
+
+impl Default for Simulation {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Simulation {
+  pub fn new() -> Self {
+    Self {
+      planets: HashMap::new(),
+      collision_mode: CollisionMode::Merge,
+      integrator: Integrator::Euler,
+      gravity_solver: GravitySolver::Direct,
+      config: SimConfig::default(),
+      units: Units::default(),
+      time: 0.0,
+      steps: 0,
+      planet_id_count: 0,
+
+      diagnostics: Diagnostics::default(),
+      initial_diagnostics: None,
+      initial_time: 0.0,
+      forces_current: false,
+
+      rng: StdRng::from_entropy(),
+    }
+  }
+
+  pub fn config(&self) -> &SimConfig {
+    &self.config
+  }
+
+  // Changing G changes the potential energy, so drift is measured afresh from here
+  pub fn set_config(&mut self, config: SimConfig) {
+    self.config = config;
+    self.initial_diagnostics = None;
+    self.forces_current = false;
+  }
+
+  // A body not yet added to the simulation. Mass defaults to the configured density.
+  pub fn new_planet(&self, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: Option<f32>, radius: f32, spawn_protection: Option<Duration>) -> Planet {
+    let mass = mass.unwrap_or_else(|| Planet::mass_from_radius(radius, self.config.planet_density));
+    Planet::new(0, position, velocity, mass, radius, spawn_protection)
+  }
+
+  // Makes the generators repeatable: the same seed followed by the same calls gives the same bodies
+  pub fn seed(&mut self, seed: u64) {
+    self.rng = StdRng::seed_from_u64(seed);
+  }
+
+  // A random number generator for one generator call, drawn from the simulation's own
+  pub(crate) fn generator_rng(&mut self) -> StdRng {
+    StdRng::seed_from_u64(self.rng.gen())
+  }
+
+  pub fn clear(&mut self) {
+    self.planets = HashMap::new();
+    self.time = 0.0;
+    self.steps = 0;
+    self.diagnostics = Diagnostics::default();
+    self.initial_diagnostics = None;
+    self.forces_current = false;
+  }
+
+  // Returns the ID of the new planet
+  pub fn add_planet(&mut self, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: Option<f32>, radius: f32, spawn_protection: Option<Duration>) -> usize {
+    self.add_planet_raw(self.new_planet(position, velocity, mass, radius, spawn_protection))
+  }
+
+  pub fn add_planet_raw(&mut self, mut planet: Planet) -> usize {
+    let id = self.planet_id_count;
+    planet.id = id;
+
+    self.planets.insert(id, RefCell::new(planet));
+    self.planet_id_count += 1;
+    self.initial_diagnostics = None;
+    self.forces_current = false;
+
+    id
+  }
+
+  // Adds a body on the given orbit around `primary`. Mass defaults to the configured density, as with add_planet.
+  pub fn add_planet_in_orbit(&mut self, primary: usize, orbit: &OrbitSpec, mass: Option<f32>, radius: f32) -> Result<usize, OrbitError> {
+    let (primary_position, primary_velocity, primary_mass, primary_spin) = {
+      let p = self.planets.get(&primary).ok_or(OrbitError::NoSuchPrimary(primary))?.borrow();
+      (p.position, p.velocity, p.total_mass(), p.spin)
+    };
+
+    let e = orbit.eccentricity;
+    let semi_latus_rectum = orbit.semi_latus_rectum()
+      .ok_or(OrbitError::InvalidShape { semi_major_axis: orbit.semi_major_axis, eccentricity: e })?;
+
+    let mut planet = self.new_planet(primary_position, None, mass, radius, None);
+    let clockwise = (primary_spin >= 0.0) == (orbit.direction == OrbitDirection::Prograde);
+    let (position, velocity) = tools::orbit_state(
+      self.config.gravitational_constant * (primary_mass + planet.mass),
+      semi_latus_rectum,
+      e,
+      orbit.argument_of_periapsis,
+      orbit.true_anomaly,
+      clockwise,
+    ).ok_or(OrbitError::BeyondAsymptote { true_anomaly: orbit.true_anomaly })?;
+
+    planet.position += position;
+    planet.velocity = primary_velocity + velocity;
+    Ok(self.add_planet_raw(planet))
+  }
+
+  pub fn remove_planet(&mut self, id: usize) {
+    if self.planets.remove(&id).is_none() {
+      println!("WARNING: Tried to remove planet {} but it wasn't in the hashmap.", id);
+    } else {
+      self.initial_diagnostics = None;
+      self.forces_current = false;
+    }
+  }
+
+  // Editing a body changes the system's totals, so drift is measured afresh from the next step. Each edit returns
+  // false, leaving the body as it was, if the body is missing or the value is no use.
+  pub fn set_mass(&mut self, id: usize, mass: f32) -> bool {
+    if !(mass.is_finite() && mass > 0.0) {
+      println!("WARNING: Mass must be positive, got {}", mass);
+      return false;
+    }
+    self.edit_planet(id, |planet| planet.mass = mass)
+  }
+
+  pub fn set_radius(&mut self, id: usize, radius: f32) -> bool {
+    if !(radius.is_finite() && radius > 0.0) {
+      println!("WARNING: Radius must be positive, got {}", radius);
+      return false;
+    }
+    self.edit_planet(id, |planet| {
+      planet.radius = radius;
+      planet.update_color();
+    })
+  }
+
+  pub fn set_velocity(&mut self, id: usize, velocity: Vector2<f32>) -> bool {
+    if !(velocity.x.is_finite() && velocity.y.is_finite()) {
+      println!("WARNING: Velocity must be finite, got ({}, {})", velocity.x, velocity.y);
+      return false;
+    }
+    self.edit_planet(id, |planet| planet.velocity = velocity)
+  }
+
+  fn edit_planet(&mut self, id: usize, edit: impl FnOnce(&mut Planet)) -> bool {
+    match self.planets.get(&id) {
+      Some(planet) => {
+        edit(&mut planet.borrow_mut());
+        self.initial_diagnostics = None;
+        self.forces_current = false;
+        true
+      },
+      None => {
+        println!("WARNING: Tried to edit planet {} but it wasn't in the hashmap.", id);
+        false
+      },
+    }
+  }
+
+  // The body whose disc covers `point`, the closest if several do
+  pub fn body_at(&self, point: Point2<f32>) -> Option<usize> {
+    self.planets.iter()
+      .map(|(id, planet)| (*id, planet.borrow()))
+      .filter(|(_, planet)| (planet.position - point).magnitude_squared() <= planet.radius.powi(2))
+      .min_by(|(_, a), (_, b)| (a.position - point).magnitude_squared().total_cmp(&(b.position - point).magnitude_squared()))
+      .map(|(id, _)| id)
+  }
+
+  // Heaviest body including halos, the lowest ID winning ties
+  pub fn heaviest_body(&self) -> Option<usize> {
+    self.planets.iter()
+      .map(|(id, planet)| (*id, planet.borrow().total_mass()))
+      .max_by(|(id_a, a), (id_b, b)| a.total_cmp(b).then(id_b.cmp(id_a)))
+      .map(|(id, _)| id)
+  }
+
+  // Centre of mass of every body and its velocity, or None with nothing to weigh
+  pub fn centre_of_momentum(&self) -> Option<(Point2<f32>, Vector2<f32>)> {
+    let mut mass = 0.0;
+    let mut weighted_position = Vector2::<f64>::zeros();
+    let mut momentum = Vector2::<f64>::zeros();
+    for planet in self.planets.values() {
+      let planet = planet.borrow();
+      let m = planet.total_mass() as f64;
+      mass += m;
+      weighted_position += planet.position.coords.cast::<f64>() * m;
+      momentum += planet.velocity.cast::<f64>() * m;
+    }
+    if mass <= 0.0 {
+      return None;
+    }
+    Some((Point2::from((weighted_position/mass).cast::<f32>()), (momentum/mass).cast::<f32>()))
+  }
+
+  // Takes the system's overall velocity off every body, so the centre of mass stays where it is from now on.
+  // Returns the velocity taken off.
+  pub fn to_centre_of_momentum_frame(&mut self) -> Vector2<f32> {
+    let Some((_, velocity)) = self.centre_of_momentum() else { return Vector2::zeros() };
+    for planet in self.planets.values() {
+      planet.borrow_mut().velocity -= velocity;
+    }
+    self.initial_diagnostics = None;
+    velocity
+  }
+
+  // Makes sure every body is stored under its own ID and that the next ID handed out is unused,
+  // describing the first problem found otherwise
+  pub fn check_ids(&self) -> Result<(), String> {
+    for (id, planet) in self.planets.iter() {
+      let planet_id = planet.borrow().id;
+      if planet_id != *id {
+        return Err(format!("body {} is stored under ID {}", planet_id, id));
+      }
+      if *id >= self.planet_id_count {
+        return Err(format!("body {} is at or beyond the next free ID {}", id, self.planet_id_count));
+      }
+    }
+    Ok(())
+  }
+
+  // A body whose position or velocity has become NaN or infinite, which means the run has blown up
+  pub fn find_non_finite(&self) -> Option<usize> {
+    let mut ids: Vec<&usize> = self.planets.keys().collect();
+    ids.sort_unstable();
+    ids.into_iter().copied().find(|id| {
+      let planet = self.planets[id].borrow();
+      !(planet.position.x.is_finite() && planet.position.y.is_finite() &&
+        planet.velocity.x.is_finite() && planet.velocity.y.is_finite())
+    })
+  }
+
+  // Totals measured at the end of the last step
+  pub fn diagnostics(&self) -> &Diagnostics {
+    &self.diagnostics
+  }
+
+  // Relative change in the conserved quantities since the start of the run
+  pub fn drift(&self) -> Drift {
+    match self.initial_diagnostics.as_ref() {
+      Some(initial) => self.diagnostics.drift_from(initial, self.time - self.initial_time),
+      None => Drift::default(),
+    }
+  }
+
+  // The body `id` is orbiting: the nearest heavier body it is gravitationally bound to, or failing that
+  // the heavier body pulling on it the hardest.
+  pub fn find_primary(&self, id: usize) -> Option<usize> {
+    let body = self.planets.get(&id)?.borrow();
+
+    let mut nearest_bound: Option<(usize, f32)> = None;
+    let mut strongest_pull: Option<(usize, f32)> = None;
+    for (other_id, other) in self.planets.iter() {
+      let other = other.borrow();
+      if *other_id == id || other.total_mass() <= body.total_mass() {
+        continue;
+      }
+
+      let square_dist = (other.position - body.position).magnitude_squared();
+      let specific_energy = (other.velocity - body.velocity).magnitude_squared()/2.0 -
+        self.config.gravitational_constant * (other.total_mass() + body.total_mass())/square_dist.sqrt();
+      let pull = other.total_mass()/square_dist;
+
+      if specific_energy < 0.0 && nearest_bound.is_none_or(|(_, d)| square_dist < d) {
+        nearest_bound = Some((*other_id, square_dist));
+      }
+      if strongest_pull.is_none_or(|(_, p)| pull > p) {
+        strongest_pull = Some((*other_id, pull));
+      }
+    }
+
+    nearest_bound.or(strongest_pull).map(|(other_id, _)| other_id)
+  }
+
+  // Orbit of the body `id` around `primary`, or around the automatically detected primary if None.
+  // Returns the primary used along with the elements.
+  pub fn orbital_elements(&self, id: usize, primary: Option<usize>) -> Option<(usize, OrbitalElements)> {
+    let primary = primary.or_else(|| self.find_primary(id))?;
+    if primary == id {
+      return None;
+    }
+
+    let body = self.planets.get(&id)?.borrow();
+    let primary_body = self.planets.get(&primary)?.borrow();
+    Some((primary, tools::orbital_elements(self.config.gravitational_constant, &primary_body, &body)))
+  }
+
+  // With the tree the potential energy comes from the tree too, as summing every pair would undo its speed-up
+  fn measure(&self) -> Diagnostics {
+    let planets: Vec<_> = self.planets.values().map(|pl| pl.borrow()).collect();
+    match self.gravity_solver {
+      GravitySolver::Direct => Diagnostics::measure(planets.iter().map(|pl| &**pl), self.config.gravitational_constant),
+      GravitySolver::BarnesHut => Diagnostics::measure_with_potential(planets.iter().map(|pl| &**pl), self.tree_potential_energy()),
+    }
+  }
+
+  pub fn step(&mut self, dt: f32) {
+    let dt_duration = Duration::from_secs_f32(dt);
+
+    if self.initial_diagnostics.is_none() {
+      self.initial_diagnostics = Some(self.measure());
+      self.initial_time = self.time;
+    }
+
+    // For holding planets that have collided
+    let mut collided_planets: Vec<usize> = Vec::with_capacity(self.planets.len()/2);
+    let mut planets_to_remove: Vec<usize> = Vec::with_capacity(self.planets.len()/2);
+
+    // Leapfrog's first half kick needs the forces where the bodies are now. Those are left over from the last
+    // step, unless bodies have been added, edited or merged since.
+    if self.integrator == Integrator::Leapfrog && !self.forces_current {
+      for pl in self.planets.values() {
+        pl.borrow_mut().clear_forces();
+      }
+      match self.gravity_solver {
+        GravitySolver::Direct => self.direct_gravity(),
+        GravitySolver::BarnesHut => self.tree_gravity(),
+      }
+    }
+
+    // Update planets, using the forces found at the end of the last step
+    for (_, pl) in self.planets.iter() {
+      let mut pl = pl.borrow_mut();
+      match self.integrator {
+        Integrator::Euler => pl.update(dt, &dt_duration),
+        Integrator::Leapfrog => {
+          pl.kick(dt/2.0);
+          pl.drift(dt, &dt_duration);
+          pl.clear_forces();
+        },
+      }
+      if self.config.teleport_on_edges {
+        pl.wrap_around(self.config.edges);
+      }
+    }
+
+    match self.gravity_solver {
+      GravitySolver::Direct => self.direct_forces(dt, &mut collided_planets, &mut planets_to_remove),
+      GravitySolver::BarnesHut => self.tree_forces(dt, &mut collided_planets, &mut planets_to_remove),
+    }
+
+    self.planets.retain(|id, _| !planets_to_remove.contains(id));
+
+    // Second half kick with the new forces, which are kept for the first half of the next step
+    if self.integrator == Integrator::Leapfrog {
+      for (_, pl) in self.planets.iter() {
+        pl.borrow_mut().kick(dt/2.0);
+      }
+    }
+
+    self.forces_current = planets_to_remove.is_empty();
+    self.time += dt as f64;
+    self.steps += 1;
+    self.diagnostics = self.measure();
+  }
+
+  // Gravity between every pair of bodies, checking each pair for contact along the way
+  fn direct_forces(&self, dt: f32, collided_planets: &mut Vec<usize>, planets_to_remove: &mut Vec<usize>) {
+    let g = self.config.gravitational_constant;
+    let keys: Vec<&usize> = self.planets.keys().collect();
+    let len = self.planets.len();
+    if len == 0 {
+      return;
+    }
+
+    for i in 0..len-1 {
+      let already_collided = collided_planets.contains(keys[i]);
+      if !already_collided {
+        let pl1 = self.planets.get(keys[i]).expect("Couldn't get planet 1");
+        for j in i+1..len {
+          let already_collided = collided_planets.contains(keys[j]);
+          if !already_collided {
+            let pl2 = self.planets.get(keys[j]).expect("Couldn't get planet 2");
+
+            let (colliding, dist_vec, square_distance, min_dist) = Self::separation(pl1, pl2);
+
+            // Check for collision even if they have spawn protection, since I do not want to apply grav
+            // force when planets are inside of each other (as they become very speedy).
+            if colliding {
+              let pl1_gone = self.contact(dt, *keys[i], *keys[j], collided_planets, planets_to_remove);
+              if self.collision_mode == CollisionMode::SoftSphere {
+                // Gravity inside the overlap falls off linearly towards the centre instead of blowing up
+                tools::newtonian_grav(g, &mut pl1.borrow_mut(), &mut pl2.borrow_mut(), min_dist.powi(2), dist_vec);
+              }
+              if pl1_gone {
+                break;
+              }
+            } else {
+              tools::newtonian_grav(g, &mut pl1.borrow_mut(), &mut pl2.borrow_mut(), square_distance, dist_vec);
+            }
+          }
+        }
+      }
+    }
+  }
+
+  // Gravity alone between every pair, with overlapping bodies pulling as if just touching. For when the forces
+  // are needed without stepping, so nothing merges or pushes apart.
+  fn direct_gravity(&self) {
+    let g = self.config.gravitational_constant;
+    let planets: Vec<_> = self.planets.values().collect();
+    for (i, pl1) in planets.iter().enumerate() {
+      for pl2 in planets[i+1..].iter() {
+        let (_, dist_vec, square_distance, min_dist) = Self::separation(pl1, pl2);
+        tools::newtonian_grav(g, &mut pl1.borrow_mut(), &mut pl2.borrow_mut(), square_distance.max(min_dist.powi(2)), dist_vec);
+      }
+    }
+  }
+
+  // Bodies with halos, which go without the tree, and the rest along with their places in the tree
+  fn tree_bodies(&self) -> (Vec<usize>, Vec<usize>, Vec<TreeBody>) {
+    let mut ids: Vec<usize> = self.planets.keys().copied().collect();
+    ids.sort_unstable();
+    let (halo_ids, plain_ids): (Vec<usize>, Vec<usize>) = ids.iter()
+      .partition(|id| self.planets[id].borrow().halo.is_some());
+    let bodies = plain_ids.iter().map(|id| {
+      let pl = self.planets[id].borrow();
+      TreeBody { position: pl.position, mass: pl.mass, radius: pl.radius }
+    }).collect();
+    (halo_ids, plain_ids, bodies)
+  }
+
+  // Gravity from a Barnes-Hut tree, with contacts found by sorting the bodies along x.
+  // Unlike direct_forces, bodies that are about to merge still pull on each other this step.
+  fn tree_forces(&self, dt: f32, collided_planets: &mut Vec<usize>, planets_to_remove: &mut Vec<usize>) {
+    self.tree_gravity();
+
+    let mut ids: Vec<usize> = self.planets.keys().copied().collect();
+    ids.sort_unstable();
+
+    // Sweep along x: once a body starts to the right of another's far edge, no later body can reach it either
+    let mut spans: Vec<(f32, f32, usize)> = ids.iter().map(|id| {
+      let pl = self.planets[id].borrow();
+      (pl.position.x - pl.radius, pl.position.x + pl.radius, *id)
+    }).collect();
+    spans.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+    for i in 0..spans.len() {
+      for j in i+1..spans.len() {
+        if spans[j].0 > spans[i].1 {
+          break;
+        }
+        let (id1, id2) = (spans[i].2, spans[j].2);
+        if collided_planets.contains(&id1) || collided_planets.contains(&id2) ||
+          planets_to_remove.contains(&id1) || planets_to_remove.contains(&id2)
+        {
+          continue;
+        }
+        if Self::separation(&self.planets[&id1], &self.planets[&id2]).0 {
+          self.contact(dt, id1, id2, collided_planets, planets_to_remove);
+        }
+      }
+    }
+  }
+
+  // (colliding, vector from pl1 to pl2, squared distance, distance at which they touch)
+  fn separation(pl1: &RefCell<Planet>, pl2: &RefCell<Planet>) -> (bool, Vector2<f32>, f32, f32) {
+    let bpl1 = pl1.borrow();
+    let bpl2 = pl2.borrow();
+    let dist_vec = bpl2.position - bpl1.position;
+    let min_dist = bpl1.radius + bpl2.radius;
+    let square_dist = dist_vec.x.powi(2) + dist_vec.y.powi(2);
+    (
+      // AABB then circle collision
+      dist_vec.x.abs() <= min_dist && dist_vec.y.abs() <= min_dist && square_dist <= min_dist.powi(2),
+      dist_vec,
+      square_dist,
+      min_dist,
+    )
+  }
+
+  // Halos soften each pairing differently, so the few bodies carrying one are paired with everything directly
+  fn tree_gravity(&self) {
+    let g = self.config.gravitational_constant;
+    let (halo_ids, plain_ids, bodies) = self.tree_bodies();
+    for (n, id1) in halo_ids.iter().enumerate() {
+      for id2 in plain_ids.iter().chain(halo_ids[n+1..].iter()) {
+        let (pl1, pl2) = (&self.planets[id1], &self.planets[id2]);
+        let (_, dist_vec, square_distance, min_dist) = Self::separation(pl1, pl2);
+        tools::newtonian_grav(
+          g, &mut pl1.borrow_mut(), &mut pl2.borrow_mut(), square_distance.max(min_dist.powi(2)), dist_vec,
+        );
+      }
+    }
+
+    let tree = QuadTree::new(&bodies, self.config.barnes_hut_theta, g);
+    for (i, id) in plain_ids.iter().enumerate() {
+      self.planets[id].borrow_mut().resultant_force += tree.force_on(i);
+    }
+  }
+
+  // Potential energy to go with tree_gravity, approximated the same way
+  fn tree_potential_energy(&self) -> f64 {
+    let g = self.config.gravitational_constant;
+    let (halo_ids, plain_ids, bodies) = self.tree_bodies();
+    let mut energy = 0.0;
+    for (n, id1) in halo_ids.iter().enumerate() {
+      for id2 in plain_ids.iter().chain(halo_ids[n+1..].iter()) {
+        energy += tools::gravitational_potential_energy(g, &self.planets[id1].borrow(), &self.planets[id2].borrow());
+      }
+    }
+
+    let tree = QuadTree::new(&bodies, self.config.barnes_hut_theta, g);
+    energy + (0..bodies.len()).map(|i| tree.potential_energy_of(i)).sum::<f64>()/2.0   // Each pair was counted twice
+  }
+
+  // Handles two touching bodies according to the collision mode. Returns true if the first was merged away.
+  fn contact(&self, dt: f32, id1: usize, id2: usize, collided_planets: &mut Vec<usize>, planets_to_remove: &mut Vec<usize>) -> bool {
+    let (pl1, pl2) = (&self.planets[&id1], &self.planets[&id2]);
+    match self.collision_mode {
+      CollisionMode::Merge => {
+        if pl1.borrow().has_spawn_protection() || pl2.borrow().has_spawn_protection() {
+          return false;
+        }
+        collided_planets.push(id1);
+        collided_planets.push(id2);
+        // The first body absorbs the second, except that when galaxy cores merge the heavier one does, so the
+        // merged galaxy keeps the bigger one's ID
+        let halos = pl1.borrow().halo.is_some() || pl2.borrow().halo.is_some();
+        if halos && pl1.borrow().total_mass() < pl2.borrow().total_mass() {
+          Self::collide_planets(&mut pl2.borrow_mut(), &pl1.borrow(), self.config.planet_density);
+          planets_to_remove.push(id1);
+          true
+        } else {
+          Self::collide_planets(&mut pl1.borrow_mut(), &pl2.borrow(), self.config.planet_density);
+          planets_to_remove.push(id2);
+          false
+        }
+      },
+      CollisionMode::SoftSphere => {
+        let (_, dist_vec, square_distance, _) = Self::separation(pl1, pl2);
+        tools::soft_sphere_contact(&self.config, dt, &mut pl1.borrow_mut(), &mut pl2.borrow_mut(), square_distance, dist_vec);
+        false
+      },
+    }
+  }
+
+  fn collide_planets(pl1: &mut Planet, pl2: &Planet, density: f32) {  // Makes pl1 the new planet
+    // Conservation of momentum
+    let (m1, m2) = (pl1.total_mass(), pl2.total_mass());
+    let total_mass = m1 + m2;
+    let total_momentum = m1 * pl1.velocity + m2 * pl2.velocity;
+    // Use centre of mass as new position
+    let centre_of_mass = Point2::new(
+      (pl1.position.x * m1 + pl2.position.x * m2)/total_mass,
+      (pl1.position.y * m1 + pl2.position.y * m2)/total_mass
+    );
+    let velocity = total_momentum/total_mass;   // Inelastic collision
+    // Conservation of angular momentum: the pair's orbit about its centre of mass becomes spin
+    let angular_momentum = pl1.angular_momentum(centre_of_mass, velocity) +
+      pl2.angular_momentum(centre_of_mass, velocity);
+
+    // Galaxy cores keep their combined volume, as their masses say nothing about their size
+    pl1.radius = if pl1.halo.is_some() || pl2.halo.is_some() {
+      tools::inverse_volume_of_sphere(tools::volume_of_sphere(pl1.radius) + tools::volume_of_sphere(pl2.radius))
+    } else {
+      tools::inverse_volume_of_sphere((pl1.mass + pl2.mass)/density)
+    };
+    pl1.mass += pl2.mass;
+    pl1.halo = Halo::merged(pl1.halo, pl2.halo);
+    pl1.position = centre_of_mass;
+    pl1.velocity = velocity;
+    pl1.spin = angular_momentum/pl1.moment_of_inertia();
+    pl1.update_color(); // Will have changed colour due to increase in mass
+  }
+}
@@ -0,0 +1,106 @@
+use serde::{Serialize, Deserialize};
+
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::planet::PlanetTrail;
+use crate::simulation::Simulation;
+
+// Bumped whenever a change to the saved types would stop older snapshots loading correctly
+pub const SNAPSHOT_VERSION: u32 = 2;
+// Version 1 trails were aged by the wall clock, so they're dropped when loading one and grow back as it runs
+const TRAILLESS_VERSION: u32 = 1;
+
+// The full state of a simulation, and optionally the trails drawn behind its bodies, saved as JSON
+#[derive(Deserialize)]
+pub struct Snapshot {
+  pub simulation: Simulation,
+  #[serde(default)]
+  pub trails: Option<HashMap<usize, RefCell<PlanetTrail>>>,
+}
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+  version: u32,
+  simulation: &'a Simulation,
+  trails: Option<&'a HashMap<usize, RefCell<PlanetTrail>>>,
+}
+
+#[derive(Deserialize)]
+struct TraillessSnapshot {
+  simulation: Simulation,
+}
+
+// Read first, so a snapshot from another version is reported as such rather than as whichever field changed
+#[derive(Deserialize)]
+struct Header {
+  version: u32,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+  Io { path: PathBuf, source: io::Error },
+  Format(serde_json::Error),
+  Version { found: u32 },
+  Inconsistent(String),     // Parsed, but the bodies don't agree with each other or the ID counter, or a setting is invalid
+}
+
+impl fmt::Display for SnapshotError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SnapshotError::Io { path, source } => write!(f, "couldn't access {}: {}", path.display(), source),
+      SnapshotError::Format(error) => write!(f, "snapshot is malformed: {}", error),
+      SnapshotError::Version { found } => write!(f,
+        "snapshot is version {}, but this build reads versions {} to {}", found, TRAILLESS_VERSION, SNAPSHOT_VERSION,
+      ),
+      SnapshotError::Inconsistent(reason) => write!(f, "snapshot is inconsistent: {}", reason),
+    }
+  }
+}
+
+impl std::error::Error for SnapshotError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      SnapshotError::Io { source, .. } => Some(source),
+      SnapshotError::Format(error) => Some(error),
+      _ => None,
+    }
+  }
+}
+
+pub fn to_json(simulation: &Simulation, trails: Option<&HashMap<usize, RefCell<PlanetTrail>>>) -> Result<String, SnapshotError> {
+  serde_json::to_string(&SnapshotRef { version: SNAPSHOT_VERSION, simulation, trails })
+    .map_err(SnapshotError::Format)
+}
+
+pub fn from_json(text: &str) -> Result<Snapshot, SnapshotError> {
+  let header: Header = serde_json::from_str(text).map_err(SnapshotError::Format)?;
+  let snapshot = match header.version {
+    SNAPSHOT_VERSION => serde_json::from_str(text).map_err(SnapshotError::Format)?,
+    TRAILLESS_VERSION => {
+      let snapshot: TraillessSnapshot = serde_json::from_str(text).map_err(SnapshotError::Format)?;
+      Snapshot { simulation: snapshot.simulation, trails: None }
+    },
+    found => return Err(SnapshotError::Version { found }),
+  };
+  snapshot.simulation.check_ids().map_err(SnapshotError::Inconsistent)?;
+  snapshot.simulation.config().validate().map_err(SnapshotError::Inconsistent)?;
+  Ok(snapshot)
+}
+
+pub fn save(path: impl AsRef<Path>, simulation: &Simulation, trails: Option<&HashMap<usize, RefCell<PlanetTrail>>>) -> Result<(), SnapshotError> {
+  let path = path.as_ref();
+  fs::write(path, to_json(simulation, trails)?)
+    .map_err(|source| SnapshotError::Io { path: path.to_path_buf(), source })
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Snapshot, SnapshotError> {
+  let path = path.as_ref();
+  let text = fs::read_to_string(path)
+    .map_err(|source| SnapshotError::Io { path: path.to_path_buf(), source })?;
+  from_json(&text)
+}
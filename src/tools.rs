@@ -1,10 +1,12 @@
-use ggez::graphics::{self, Mesh, Canvas, DrawMode, DrawParam};
-use ggez::{Context, GameResult};
-
-use nalgebra::{Vector2, Point2};
+use nalgebra::Vector2;
+use serde::Deserialize;
 
 use std::f32::consts::PI;
-use crate::{G, planet::Planet};
+use crate::{TWO_PI, config::SimConfig, planet::Planet};
+
+// Largest angle a contact's oscillation may turn through in one step (omega dt). Stiffer contacts ring faster
+// than the integrators can follow and gain energy every bounce.
+const MAX_CONTACT_STEP_ANGLE: f32 = 0.5;
 
 pub fn volume_of_sphere(radius: f32) -> f32 {
   (4.0/3.0) * PI * radius.powi(3)
@@ -22,14 +24,108 @@ pub fn get_components(magnitude: f32, angle: f32) -> Vector2<f32> {
   Vector2::new(magnitude * angle.cos(), magnitude * angle.sin())
 }
 
+// z component of the 3D cross product of two vectors in the plane
+pub fn cross(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+  a.x * b.y - a.y * b.x
+}
+
 // F = (GMm/|r|^2) * r_norm
 //   = (GMm/|r|^2) * r * 1/|r|
 //   = (GMm/|r|^3) * r
-pub fn newtonian_grav(pl1: &mut Planet, pl2: &mut Planet, dist_squared: f32, dist_vec: Vector2<f32>) {
-  let force_vec = dist_vec * (G * pl1.mass * pl2.mass/dist_squared.sqrt().powi(3));
+//
+// Halos are Plummer spheres, so each pairing involving one is softened by its scale radius a:
+//   F = (GMm/(|r|^2 + a^2)^(3/2)) * r,  with a^2 = a1^2 + a2^2 between two halos
+pub fn newtonian_grav(g: f32, pl1: &mut Planet, pl2: &mut Planet, dist_squared: f32, dist_vec: Vector2<f32>) {
+  let mut coupling = pl1.mass * pl2.mass/dist_squared.sqrt().powi(3);
+  for (mass, softening, halo_mass) in halo_pairings(pl1, pl2) {
+    coupling += mass * halo_mass/(dist_squared + softening).sqrt().powi(3);
+  }
+  let force_vec = dist_vec * (g * coupling);
+
+  pl1.resultant_force += force_vec;
+  pl2.resultant_force -= force_vec;
+}
+
+// Potential energy of a pair, matching the force applied by newtonian_grav.
+// U = -GMm/r, except while the bodies overlap (r < D = r1 + r2), where gravity is applied at the
+// clamped distance D and so falls off linearly towards the centre:  U = -GMm (3D^2 - r^2)/(2D^3)
+// Halo pairings are Plummer potentials, U = -GMm/sqrt(r^2 + a^2).
+pub fn gravitational_potential_energy(g: f32, pl1: &Planet, pl2: &Planet) -> f64 {
+  let gmm = g as f64 * pl1.mass as f64 * pl2.mass as f64;
+  let dist = (pl2.position - pl1.position).cast::<f64>().norm();
+  let min_dist = (pl1.radius + pl2.radius) as f64;
+
+  let mut energy = if dist >= min_dist {
+    -gmm/dist
+  } else {
+    -gmm * (3.0 * min_dist.powi(2) - dist.powi(2))/(2.0 * min_dist.powi(3))
+  };
+  for (mass, softening, halo_mass) in halo_pairings(pl1, pl2) {
+    energy -= g as f64 * mass as f64 * halo_mass as f64/(dist.powi(2) + softening as f64).sqrt();
+  }
+
+  energy
+}
+
+// Each attracting pair involving a halo, as (mass, squared softening length, halo mass)
+fn halo_pairings(pl1: &Planet, pl2: &Planet) -> impl Iterator<Item = (f32, f32, f32)> {
+  let with_halo = |body: &Planet, halo_owner: &Planet| halo_owner.halo
+    .map(|halo| (body.mass, halo.scale_radius.powi(2), halo.mass));
+  let between_halos = match (pl1.halo, pl2.halo) {
+    (Some(h1), Some(h2)) => Some((h1.mass, h1.scale_radius.powi(2) + h2.scale_radius.powi(2), h2.mass)),
+    _ => None,
+  };
+
+  with_halo(pl1, pl2).into_iter()
+    .chain(with_halo(pl2, pl1))
+    .chain(between_halos)
+}
+
+// Hertzian soft-sphere contact force (discrete element method) between two overlapping bodies.
+// Normal:   F_n = 4/3 E* sqrt(R* d) d - c v_n
+//   where d is the overlap, R* = r1 r2/(r1 + r2) the effective radius and E* the contact stiffness.
+//   The damping coefficient c is chosen so a head-on contact loses energy according to the
+//   coefficient of restitution e:  c = -2 sqrt(5/6) ln(e)/sqrt(ln(e)^2 + pi^2) * sqrt(S_n m*)
+//   with S_n = 2 E* sqrt(R* d) and m* = m1 m2/(m1 + m2).
+// Tangential: viscous friction against the sliding velocity of the two surfaces at the contact point,
+//   capped by the Coulomb limit mu |F_n|. Friction acts at each surface, so it also exerts a torque r_i F_t.
+// E* is lowered where need be so the contact oscillates at no more than omega = sqrt(S_n/m*) with
+// omega dt = MAX_CONTACT_STEP_ANGLE, keeping light, small bodies stable at the step `dt`.
+pub fn soft_sphere_contact(config: &SimConfig, dt: f32, pl1: &mut Planet, pl2: &mut Planet, dist_squared: f32, dist_vec: Vector2<f32>) {
+  let dist = dist_squared.sqrt();
+  let overlap = pl1.radius + pl2.radius - dist;
+  if overlap <= 0.0 || dist <= f32::EPSILON {   // Not touching, or centres coincide so there is no normal
+    return;
+  }
+
+  let normal = dist_vec/dist;   // Points from pl1 to pl2
+  let tangent = Vector2::new(-normal.y, normal.x);
+
+  let eff_radius = pl1.radius * pl2.radius/(pl1.radius + pl2.radius);
+  let eff_mass = pl1.total_mass() * pl2.total_mass()/(pl1.total_mass() + pl2.total_mass());
+  let contact_root = (eff_radius * overlap).sqrt();
+  let max_stiffness = eff_mass * (MAX_CONTACT_STEP_ANGLE/dt).powi(2);
+  let modulus = config.contact_stiffness.min(max_stiffness/(2.0 * contact_root));
+  let contact_stiffness = 2.0 * modulus * contact_root;
+
+  let ln_e = config.contact_restitution.ln();
+  let damping = -2.0 * (5.0_f32/6.0).sqrt() * ln_e/(ln_e.powi(2) + PI.powi(2)).sqrt() * (contact_stiffness * eff_mass).sqrt();
+
+  let rel_velocity = pl2.velocity - pl1.velocity;
+  let normal_speed = rel_velocity.dot(&normal);    // Negative when approaching
+  // Surfaces move with v + w x r, where the contact point is at +r1 n on pl1 and -r2 n on pl2
+  let tangential_speed = rel_velocity.dot(&tangent) - pl1.spin * pl1.radius - pl2.spin * pl2.radius;
+
+  let elastic_force = 4.0/3.0 * modulus * contact_root * overlap;
+  let normal_force = (elastic_force - damping * normal_speed).max(0.0);   // Contacts can push but never pull
+  let friction_limit = config.contact_friction * normal_force;
+  let friction_force = (damping * tangential_speed).clamp(-friction_limit, friction_limit);
 
+  let force_vec = tangent * friction_force - normal * normal_force;   // Force on pl1
   pl1.resultant_force += force_vec;
   pl2.resultant_force -= force_vec;
+  pl1.resultant_torque += pl1.radius * friction_force;
+  pl2.resultant_torque += pl2.radius * friction_force;
 }
 
 // Returns the magnitude of the velocity (speed) needed for a circular orbit around another planet
@@ -38,6 +134,120 @@ pub fn newtonian_grav(pl1: &mut Planet, pl2: &mut Planet, dist_squared: f32, dis
 // GMm/2r = 1/2 mv^2
 // GM/2r = 1/2 v^2
 // sqrt(GM/r) = v
-pub fn circular_orbit_speed(host_mass: f32, radius: f32) -> f32 {
-  (G * host_mass/radius).sqrt()
-}
\ No newline at end of file
+pub fn circular_orbit_speed(g: f32, host_mass: f32, radius: f32) -> f32 {
+  (g * host_mass/radius).sqrt()
+}
+
+// Osculating Keplerian elements of one body's orbit relative to another
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrbitalElements {
+  pub semi_major_axis: f32,         // Negative for unbound (hyperbolic) orbits
+  pub eccentricity: f32,
+  pub argument_of_periapsis: f32,   // Angle of periapsis from the x axis (rad)
+  pub true_anomaly: f32,            // Angle from periapsis to the body, in the direction of motion (rad)
+  pub period: Option<f32>,          // None when the orbit is unbound
+  pub clockwise: bool,              // Direction of motion on screen
+}
+
+// Two-body orbit of `body` around `primary`, with mu = G(M + m).
+// h = r x v,  e = ((v^2 - mu/|r|) r - (r.v) v)/mu,  a = -mu/2E  where E = v^2/2 - mu/|r|
+pub fn orbital_elements(g: f32, primary: &Planet, body: &Planet) -> OrbitalElements {
+  const CIRCULAR_ECCENTRICITY: f32 = 1.0e-6;   // Below this, periapsis is undefined and measured from the x axis
+
+  let mu = g * (primary.total_mass() + body.total_mass());
+  let r = body.position - primary.position;
+  let v = body.velocity - primary.velocity;
+  let dist = r.magnitude();
+
+  let angular_momentum = cross(r, v);
+  let ecc_vec = (r * (v.magnitude_squared() - mu/dist) - v * r.dot(&v))/mu;
+  let eccentricity = ecc_vec.magnitude();
+  let specific_energy = v.magnitude_squared()/2.0 - mu/dist;
+  let semi_major_axis = -mu/(2.0 * specific_energy);
+
+  let argument_of_periapsis = if eccentricity > CIRCULAR_ECCENTRICITY {
+    get_angle(ecc_vec).rem_euclid(TWO_PI)
+  } else {
+    0.0
+  };
+  // Angle swept from periapsis to the body, flipped so it always increases along the orbit
+  let periapsis_dir = get_components(1.0, argument_of_periapsis);
+  let swept = cross(periapsis_dir, r).atan2(periapsis_dir.dot(&r));
+  let clockwise = angular_momentum >= 0.0;
+  let true_anomaly = if clockwise { swept } else { -swept }.rem_euclid(TWO_PI);
+
+  OrbitalElements {
+    semi_major_axis,
+    eccentricity,
+    argument_of_periapsis,
+    true_anomaly,
+    period: if specific_energy < 0.0 {
+      Some(TWO_PI * (semi_major_axis.powi(3)/mu).sqrt())
+    } else {
+      None
+    },
+    clockwise,
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrbitDirection {
+  #[default]
+  Prograde,     // Same sense as the primary's spin, or clockwise on screen if it isn't spinning
+  Retrograde,
+}
+
+// Orbit to place a new body on, relative to its primary
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrbitSpec {
+  pub semi_major_axis: f32,         // Negative for hyperbolic orbits
+  #[serde(default)]
+  pub eccentricity: f32,
+  #[serde(default)]
+  pub argument_of_periapsis: f32,   // Angle of periapsis from the x axis (rad)
+  #[serde(default)]
+  pub true_anomaly: f32,            // Angle from periapsis to the starting position, in the direction of motion (rad)
+  #[serde(default)]
+  pub direction: OrbitDirection,
+}
+
+impl OrbitSpec {
+  // p = a(1 - e^2), or None if the shape isn't a conic: closed orbits need a > 0 and open ones a < 0
+  pub fn semi_latus_rectum(&self) -> Option<f32> {
+    let p = self.semi_major_axis * (1.0 - self.eccentricity.powi(2));
+    (self.eccentricity >= 0.0 && p > 0.0 && p.is_finite()).then_some(p)
+  }
+
+  // An open orbit only covers the true anomalies between its asymptotes
+  pub fn reaches_true_anomaly(&self) -> bool {
+    1.0 + self.eccentricity * self.true_anomaly.cos() > 0.0
+  }
+}
+
+// Position and velocity relative to the primary for a conic with semi-latus rectum p = a(1 - e^2).
+// Taking p lets galaxy encounters, given by pericentre, be parabolic (e = 1). An OrbitSpec gives a instead, so
+// bodies can't be put on a parabolic orbit through one.
+//   r = p/(1 + e cos v)
+//   v = sqrt(mu/p) (-sin(v) P + (e + cos(v)) Q)
+// where P points at periapsis and Q is P turned a quarter turn in the direction of motion.
+// Returns None if the true anomaly lies beyond the asymptotes of an open orbit.
+pub fn orbit_state(mu: f32, semi_latus_rectum: f32, eccentricity: f32, argument_of_periapsis: f32,
+                   true_anomaly: f32, clockwise: bool) -> Option<(Vector2<f32>, Vector2<f32>)> {
+  let denominator = 1.0 + eccentricity * true_anomaly.cos();
+  if semi_latus_rectum <= 0.0 || denominator <= 0.0 {
+    return None;
+  }
+
+  let sense = if clockwise { 1.0 } else { -1.0 };
+  let radius = semi_latus_rectum/denominator;
+  let position = get_components(radius, argument_of_periapsis + sense * true_anomaly);
+
+  let p_hat = get_components(1.0, argument_of_periapsis);
+  let q_hat = get_components(1.0, argument_of_periapsis + sense * PI/2.0);
+  let velocity = (p_hat * -true_anomaly.sin() + q_hat * (eccentricity + true_anomaly.cos())) *
+    (mu/semi_latus_rectum).sqrt();
+
+  Some((position, velocity))
+}
@@ -0,0 +1,214 @@
+use nalgebra::Point2;
+use serde::{Serialize, Deserialize};
+
+// Physical units for scenarios and readouts. The simulation itself always works in pixels, simulated seconds
+// and whatever mass unit makes its gravitational constant come out right. A unit system says how many pixels
+// one unit of length is and how many simulated seconds one unit of time is; the mass unit then follows from
+// the system's real G:
+//   G_real M/L^3 T^2 = G_sim M_sim/L_sim^3 T_sim^2,  so  M_sim = M G_real/G_sim (L_sim/L)^3 (T/T_sim)^2
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitSystem {
+  #[default]
+  Pixel,          // The simulation's own units
+  Si,             // Metres, kilograms, seconds
+  Astronomical,   // Astronomical units, solar masses, years
+  Planetary,      // Kilometres, Earth masses, days
+}
+
+const G_SI: f64 = 6.674_30e-11;
+const ASTRONOMICAL_UNIT: f64 = 1.495_978_707e11;   // m
+const JULIAN_YEAR: f64 = 365.25 * 86_400.0;        // s
+const EARTH_MASS: f64 = 5.972_17e24;               // kg
+
+impl UnitSystem {
+  // Metres, kilograms and seconds in one unit of length, mass and time, or None for pixel units
+  fn si_scales(self) -> Option<[f64; 3]> {
+    match self {
+      UnitSystem::Pixel => None,
+      UnitSystem::Si => Some([1.0, 1.0, 1.0]),
+      // The solar mass that makes G exactly 4 pi^2, so a body 1 AU from the Sun goes round once a year
+      UnitSystem::Astronomical => Some([
+        ASTRONOMICAL_UNIT,
+        4.0 * std::f64::consts::PI.powi(2) * ASTRONOMICAL_UNIT.powi(3)/(JULIAN_YEAR.powi(2) * G_SI),
+        JULIAN_YEAR,
+      ]),
+      UnitSystem::Planetary => Some([1.0e3, EARTH_MASS, 86_400.0]),
+    }
+  }
+
+  // SI value of one unit of `quantity`, or None for pixel units
+  pub fn si_scale(self, quantity: Quantity) -> Option<f64> {
+    let scales = self.si_scales()?;
+    Some(quantity.dimensions().iter().zip(scales.iter()).map(|(power, scale)| scale.powi(*power)).product())
+  }
+
+  // G in this system's units, or None for pixel units where it is whatever the simulation uses
+  fn gravitational_constant(self) -> Option<f64> {
+    self.si_scales().map(|[length, mass, time]| G_SI * mass * time.powi(2)/length.powi(3))
+  }
+
+  // Symbols for length, mass and time
+  fn symbols(self) -> [&'static str; 3] {
+    match self {
+      UnitSystem::Pixel => ["px", "u", "s"],
+      UnitSystem::Si => ["m", "kg", "s"],
+      UnitSystem::Astronomical => ["AU", "M_sun", "yr"],
+      UnitSystem::Planetary => ["km", "M_earth", "d"],
+    }
+  }
+}
+
+// The kinds of value that get converted, by their powers of length, mass and time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quantity {
+  Length,
+  Mass,
+  Time,
+  Velocity,
+  AngularVelocity,
+  Energy,
+  Momentum,
+  AngularMomentum,
+}
+
+impl Quantity {
+  fn dimensions(self) -> [i32; 3] {
+    match self {
+      Quantity::Length => [1, 0, 0],
+      Quantity::Mass => [0, 1, 0],
+      Quantity::Time => [0, 0, 1],
+      Quantity::Velocity => [1, 0, -1],
+      Quantity::AngularVelocity => [0, 0, -1],
+      Quantity::Energy => [2, 1, -2],
+      Quantity::Momentum => [1, 1, -1],
+      Quantity::AngularMomentum => [2, 1, -1],
+    }
+  }
+}
+
+// A unit system and how it maps onto the screen, as given in a scenario's [units] table:
+//   [units]
+//   system = "astronomical"
+//   length_scale = 150      # Pixels per AU
+//   time_scale = 4          # Simulated seconds per year
+//   origin = [640, 430]     # Where the origin of the scenario's coordinates is on screen
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Units {
+  pub system: UnitSystem,
+  pub length_scale: f64,    // Pixels per unit of length
+  pub time_scale: f64,      // Simulated seconds per unit of time
+  pub origin: [f32; 2],     // Pixel position of the origin
+}
+
+impl Default for Units {
+  fn default() -> Self {
+    Self {
+      system: UnitSystem::Pixel,
+      length_scale: 1.0,
+      time_scale: 1.0,
+      origin: [0.0, 0.0],
+    }
+  }
+}
+
+impl Units {
+  pub fn validate(&self) -> Result<(), String> {
+    if !(self.length_scale.is_finite() && self.length_scale > 0.0) {
+      return Err("`length_scale` must be positive".to_string());
+    }
+    if !(self.time_scale.is_finite() && self.time_scale > 0.0) {
+      return Err("`time_scale` must be positive".to_string());
+    }
+    Ok(())
+  }
+
+  // Conversions for a simulation whose gravitational constant is `g`
+  pub fn conversion(&self, g: f32) -> Conversion {
+    let mass_scale = match self.system.gravitational_constant() {
+      Some(real_g) => real_g/g as f64 * self.length_scale.powi(3)/self.time_scale.powi(2),
+      None => 1.0,
+    };
+    Conversion { units: *self, scales: [self.length_scale, mass_scale, self.time_scale] }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Conversion {
+  units: Units,
+  scales: [f64; 3],   // Simulation units per unit of length, mass and time
+}
+
+impl Conversion {
+  // Simulation units per unit of `quantity`
+  pub fn scale(&self, quantity: Quantity) -> f64 {
+    quantity.dimensions().iter().zip(self.scales.iter())
+      .map(|(power, scale)| scale.powi(*power))
+      .product()
+  }
+
+  pub fn to_sim(&self, quantity: Quantity, value: f32) -> f32 {
+    (value as f64 * self.scale(quantity)) as f32
+  }
+
+  pub fn from_sim(&self, quantity: Quantity, value: f64) -> f64 {
+    value/self.scale(quantity)
+  }
+
+  pub fn position_to_sim(&self, position: [f32; 2]) -> Point2<f32> {
+    Point2::new(
+      self.units.origin[0] + self.to_sim(Quantity::Length, position[0]),
+      self.units.origin[1] + self.to_sim(Quantity::Length, position[1]),
+    )
+  }
+
+  pub fn position_from_sim(&self, position: Point2<f64>) -> Point2<f64> {
+    Point2::new(
+      self.from_sim(Quantity::Length, position.x - self.units.origin[0] as f64),
+      self.from_sim(Quantity::Length, position.y - self.units.origin[1] as f64),
+    )
+  }
+
+  // Symbol for the unit of `quantity`, such as "AU/yr" or "kg m^2/s^2"
+  pub fn symbol(&self, quantity: Quantity) -> String {
+    let symbols = self.units.system.symbols();
+    let dimensions = quantity.dimensions();
+    let power = |symbol: &str, power: i32| match power.abs() {
+      1 => symbol.to_string(),
+      n => format!("{}^{}", symbol, n),
+    };
+
+    // Mass first, as in "kg m/s"
+    let order = [1, 0, 2];
+    let numerator: Vec<String> = order.iter().filter(|&&i| dimensions[i] > 0).map(|&i| power(symbols[i], dimensions[i])).collect();
+    let denominator: Vec<String> = order.iter().filter(|&&i| dimensions[i] < 0).map(|&i| power(symbols[i], dimensions[i])).collect();
+    let numerator = if numerator.is_empty() { "1".to_string() } else { numerator.join(" ") };
+    if denominator.is_empty() {
+      numerator
+    } else {
+      format!("{}/{}", numerator, denominator.join(" "))
+    }
+  }
+
+  // A simulation value written out in these units, to four significant figures
+  pub fn format(&self, quantity: Quantity, value: f64) -> String {
+    format!("{:.4} {}", Significant(self.from_sim(quantity, value)), self.symbol(quantity))
+  }
+}
+
+// Plain decimals for everyday magnitudes, scientific notation otherwise
+struct Significant(f64);
+
+impl std::fmt::Display for Significant {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let precision = f.precision().unwrap_or(4);
+    let magnitude = self.0.abs();
+    if magnitude != 0.0 && !(1.0e-3..1.0e5).contains(&magnitude) {
+      write!(f, "{:.*e}", precision - 1, self.0)
+    } else {
+      let decimals = if magnitude == 0.0 { 0 } else { (precision as i32 - 1 - magnitude.log10().floor() as i32).max(0) as usize };
+      write!(f, "{:.*}", decimals, self.0)
+    }
+  }
+}
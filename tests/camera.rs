@@ -0,0 +1,77 @@
+use nalgebra::{Point2, Vector2};
+
+use orbits::camera::{Camera, Follow};
+use orbits::simulation::Simulation;
+
+fn close(a: Point2<f32>, b: Point2<f32>) -> bool {
+  (a - b).magnitude() < 1.0e-3
+}
+
+#[test]
+fn starts_as_raw_pixels() {
+  let camera = Camera::new((1280.0, 860.0));
+  let point = Point2::new(100.0, 700.0);
+  assert!(close(camera.to_screen(point), point));
+  assert!(close(camera.to_world(point), point));
+}
+
+#[test]
+fn zooming_keeps_the_cursor_still() {
+  let mut camera = Camera::new((1280.0, 860.0));
+  let cursor = Point2::new(300.0, 200.0);
+  let under_cursor = camera.to_world(cursor);
+
+  camera.zoom_at(cursor, 4.0);
+  assert_eq!(camera.zoom, 4.0);
+  assert!(close(camera.to_screen(under_cursor), cursor));
+  // Everything else spreads out from the cursor
+  assert!(close(camera.to_screen(under_cursor + Vector2::new(10.0, 0.0)), cursor + Vector2::new(40.0, 0.0)));
+
+  camera.zoom_at(Point2::new(900.0, 600.0), 0.1);
+  let screen = Point2::new(17.0, 423.0);
+  assert!(close(camera.to_screen(camera.to_world(screen)), screen));
+}
+
+#[test]
+fn panning_drags_the_world_along() {
+  let mut camera = Camera::new((1280.0, 860.0));
+  camera.zoom_at(Point2::new(0.0, 0.0), 2.0);
+  let world = Point2::new(50.0, 50.0);
+  let before = camera.to_screen(world);
+
+  camera.pan(Vector2::new(30.0, -20.0));
+  assert!(close(camera.to_screen(world), before + Vector2::new(30.0, -20.0)));
+
+  camera.reset();
+  assert_eq!(camera, Camera::new((1280.0, 860.0)));
+}
+
+#[test]
+fn follows_bodies_and_the_barycentre() {
+  let mut simulation = Simulation::new();
+  let heavy = simulation.add_planet(Point2::new(100.0, 100.0), None, Some(3.0e9), 10.0, None);
+  let light = simulation.add_planet(Point2::new(500.0, 100.0), None, Some(1.0e9), 10.0, None);
+  let mut camera = Camera::new((1280.0, 860.0));
+
+  camera.follow = Follow::Heaviest;
+  camera.track(&simulation);
+  assert!(close(camera.centre, Point2::new(100.0, 100.0)));
+
+  camera.follow = camera.follow.next();
+  assert_eq!(camera.follow, Follow::Barycentre);
+  camera.track(&simulation);
+  assert!(close(camera.centre, Point2::new(200.0, 100.0)));
+
+  camera.follow = Follow::Body(light);
+  camera.track(&simulation);
+  assert!(close(camera.to_screen(Point2::new(500.0, 100.0)), Point2::new(640.0, 430.0)));
+
+  // Panning or losing the body lets go
+  camera.pan(Vector2::new(1.0, 0.0));
+  assert_eq!(camera.follow, Follow::Free);
+  camera.follow = Follow::Body(light);
+  simulation.remove_planet(light);
+  camera.track(&simulation);
+  assert_eq!(camera.follow, Follow::Free);
+  assert!(simulation.planets.contains_key(&heavy));
+}
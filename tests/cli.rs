@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn orbits_cli(args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_orbits-cli"))
+    .args(args)
+    .current_dir(env!("CARGO_MANIFEST_DIR"))
+    .output()
+    .expect("Couldn't run orbits-cli")
+}
+
+fn temp_path(name: &str) -> PathBuf {
+  std::env::temp_dir().join(format!("orbits-cli-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn runs_a_scenario_and_exports() {
+  let out = temp_path("run.csv");
+  let output = orbits_cli(&[
+    "run", "scenarios/binary_star.toml", "--steps", "200", "--dt", "0.01", "--out", out.to_str().unwrap(), "--interval", "0.5",
+  ]);
+  let rows = fs::read_to_string(&out).map(|text| text.lines().count());
+  let _ = fs::remove_file(&out);
+
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  assert!(String::from_utf8_lossy(&output.stdout).contains("step      200 (100.0%)"));
+  assert_eq!(rows.unwrap(), 1 + 3 * 5);    // Header, then three bodies at t = 0, 0.5, 1, 1.5 and 2
+}
+
+#[test]
+fn blowing_up_exits_with_status_2() {
+  let scenario = temp_path("blowup.toml");
+  fs::write(&scenario, "[[bodies]]\nposition = [0, 0]\nvelocity = [1e36, 0]\nradius = 1\n").unwrap();
+  let output = orbits_cli(&["run", scenario.to_str().unwrap(), "--steps", "10", "--dt", "1000"]);
+  fs::remove_file(&scenario).unwrap();
+
+  assert_eq!(output.status.code(), Some(2));
+  assert!(String::from_utf8_lossy(&output.stderr).contains("NaN or infinite"));
+}
+
+#[test]
+fn invalid_input_exits_with_status_1() {
+  for args in [
+    vec![],
+    vec!["walk"],
+    vec!["run"],
+    vec!["run", "scenarios/binary_star.toml", "--steps", "many"],
+    vec!["run", "scenarios/binary_star.toml", "--dt", "-1"],
+    vec!["run", "scenarios/binary_star.toml", "--colour", "red"],
+    vec!["run", "does/not/exist.toml"],
+  ] {
+    let output = orbits_cli(&args);
+    assert_eq!(output.status.code(), Some(1), "orbits-cli {:?}", args);
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: "));
+  }
+}
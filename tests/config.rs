@@ -0,0 +1,103 @@
+use nalgebra::Point2;
+
+use orbits::config::{Setting, SimConfig};
+use orbits::scenario::{Scenario, ScenarioError};
+use orbits::simulation::Simulation;
+use orbits::snapshot;
+
+#[test]
+fn scenarios_set_the_config() {
+  let scenario = Scenario::parse("
+    [config]
+    gravitational_constant = 2e-4
+    planet_density = 1000
+    teleport_on_edges = true
+
+    [[bodies]]
+    position = [0, 0]
+    radius = 2
+  ").unwrap();
+  let mut simulation = Simulation::new();
+  scenario.apply(&mut simulation).unwrap();
+
+  let config = simulation.config();
+  assert_eq!(config.gravitational_constant, 2.0e-4);
+  assert!(config.teleport_on_edges);
+  assert_eq!(config.spawn_radius, SimConfig::default().spawn_radius);
+  // Bodies without a mass take it from the configured density
+  let body = simulation.planets.values().next().unwrap().borrow();
+  assert_eq!(body.mass, orbits::planet::Planet::mass_from_radius(2.0, 1000.0));
+}
+
+#[test]
+fn invalid_config_is_rejected() {
+  let unknown = Scenario::parse("[config]\ngravity = 1\n");
+  assert!(matches!(unknown, Err(ScenarioError::Parse(_))));
+
+  match Scenario::parse("[config]\ncontact_restitution = 1.5\n") {
+    Err(ScenarioError::Invalid { item, reason }) => {
+      assert_eq!(item, "config");
+      assert!(reason.contains("contact_restitution"), "{}", reason);
+    },
+    other => panic!("expected an invalid config, got {:?}", other.map(|_| ())),
+  }
+
+  // Negative gravity would push everything apart, so it's refused too
+  assert_eq!(SimConfig { gravitational_constant: -1.0e-4, ..SimConfig::default() }.validate(), Err("`gravitational_constant` can't be negative".to_string()));
+  assert_eq!(SimConfig { gravitational_constant: 0.0, ..SimConfig::default() }.validate(), Ok(()));
+}
+
+#[test]
+fn adjusting_keeps_settings_valid() {
+  for setting in Setting::ALL {
+    for up in [true, false] {
+      let mut config = SimConfig::default();
+      for _ in 0..100 {
+        config.adjust(setting, up);
+      }
+      assert_eq!(config.validate(), Ok(()), "{:?} adjusted {}", setting, if up { "up" } else { "down" });
+    }
+
+    // And back down from high up
+    let mut config = SimConfig::default();
+    for _ in 0..200 {
+      config.adjust(setting, true);
+    }
+    for _ in 0..200 {
+      config.adjust(setting, false);
+      assert_eq!(config.validate(), Ok(()), "{:?} adjusted down from high up", setting);
+    }
+  }
+
+  // Far enough to run off either end of an f32, the scaled settings stay finite and positive
+  for setting in Setting::ALL {
+    let mut config = SimConfig::default();
+    for _ in 0..1000 {
+      config.adjust(setting, true);
+    }
+    assert_eq!(config.validate(), Ok(()), "{:?} adjusted far up", setting);
+    for _ in 0..2000 {
+      config.adjust(setting, false);
+    }
+    assert_eq!(config.validate(), Ok(()), "{:?} adjusted far down", setting);
+    assert!(config.gravitational_constant > 0.0 && config.spawn_radius > 0.0);
+  }
+
+  // The node count can reach the top of its range, and has to stay there going up and come back down from it
+  let mut config = SimConfig { trail_max_nodes: u32::MAX, ..SimConfig::default() };
+  config.adjust(Setting::TrailMaxNodes, true);
+  assert_eq!(config.trail_max_nodes, u32::MAX);
+  config.adjust(Setting::TrailMaxNodes, false);
+  assert_eq!(config.trail_max_nodes, (u32::MAX as u64 * 4/5) as u32);
+}
+
+#[test]
+fn snapshots_keep_the_config() {
+  let mut simulation = Simulation::new();
+  let config = SimConfig { gravitational_constant: 3.0e-4, contact_friction: 0.2, ..SimConfig::default() };
+  simulation.set_config(config);
+  simulation.add_planet(Point2::new(10.0, 10.0), None, None, 3.0, None);
+
+  let loaded = snapshot::from_json(&snapshot::to_json(&simulation, None).unwrap()).unwrap();
+  assert_eq!(*loaded.simulation.config(), config);
+}
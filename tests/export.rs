@@ -0,0 +1,149 @@
+use nalgebra::{Point2, Vector2};
+
+use std::fs;
+use std::path::PathBuf;
+
+use orbits::export::{ExportFormat, TrajectoryExporter, EXPORT_COLUMNS};
+use orbits::simulation::Simulation;
+
+fn temp_path(name: &str) -> PathBuf {
+  std::env::temp_dir().join(format!("orbits-export-{}-{}", std::process::id(), name))
+}
+
+fn three_bodies() -> Simulation {
+  let mut simulation = Simulation::new();
+  simulation.add_planet(Point2::new(100.0, 100.0), None, Some(1.0e9), 10.0, None);
+  simulation.add_planet(Point2::new(300.0, 100.0), Some(Vector2::new(0.0, 20.0)), None, 2.0, None);
+  simulation.add_planet(Point2::new(100.0, 300.0), Some(Vector2::new(20.0, 0.0)), None, 2.0, None);
+  simulation
+}
+
+// Steps one simulated second at 60 steps per second, sampling after each step
+fn run(exporter: &mut TrajectoryExporter, simulation: &mut Simulation) {
+  exporter.sample(simulation).unwrap();
+  for _ in 0..60 {
+    simulation.step(1.0/60.0);
+    exporter.sample(simulation).unwrap();
+  }
+}
+
+#[test]
+fn csv_has_a_row_per_body_per_sample() {
+  let path = temp_path("all.csv");
+  let mut simulation = three_bodies();
+  let mut exporter = TrajectoryExporter::new(ExportFormat::Csv, &path, None, 0.25).unwrap();
+  run(&mut exporter, &mut simulation);
+  let rows = exporter.finish().unwrap();
+
+  let text = fs::read_to_string(&path).unwrap();
+  fs::remove_file(&path).unwrap();
+  let lines: Vec<&str> = text.lines().collect();
+
+  assert_eq!(lines[0], EXPORT_COLUMNS.join(","));
+  assert_eq!(rows, 3 * 5);    // t = 0, 0.25, 0.5, 0.75 and 1
+  assert_eq!(lines.len() as u64, rows + 1);
+
+  let last: Vec<f64> = lines.last().unwrap().split(',').map(|value| value.parse().unwrap()).collect();
+  let body = simulation.planets[&2].borrow();
+  assert_eq!(last[1], 2.0);
+  assert_eq!(last[2] as f32, body.position.x);
+  assert_eq!(last[5] as f32, body.velocity.y);
+  assert_eq!(last[6] as f32, body.mass);
+}
+
+#[test]
+fn only_selected_bodies_are_exported() {
+  let path = temp_path("selected.csv");
+  let mut simulation = three_bodies();
+  let mut exporter = TrajectoryExporter::new(ExportFormat::Csv, &path, Some(&[1]), 0.0).unwrap();
+  run(&mut exporter, &mut simulation);
+  assert_eq!(exporter.finish().unwrap(), 61);
+
+  let text = fs::read_to_string(&path).unwrap();
+  fs::remove_file(&path).unwrap();
+  assert!(text.lines().skip(1).all(|line| line.split(',').nth(1) == Some("1")));
+}
+
+// Reads a one dimensional .npy array, returning its dtype and raw data
+fn read_npy(path: &PathBuf) -> (String, usize, Vec<u8>) {
+  let bytes = fs::read(path).unwrap();
+  assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+  let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+  assert_eq!((10 + header_len) % 64, 0);
+  let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+  assert!(header.ends_with('\n'));
+
+  let dtype = header.split("'descr': '").nth(1).unwrap()[..3].to_string();
+  let shape: usize = header.split("'shape': (").nth(1).unwrap().split(',').next().unwrap().parse().unwrap();
+  (dtype, shape, bytes[10 + header_len..].to_vec())
+}
+
+#[test]
+fn columnar_export_writes_npy_arrays() {
+  let dir = temp_path("columns");
+  let mut simulation = three_bodies();
+  let mut exporter = TrajectoryExporter::new(ExportFormat::Columnar, &dir, None, 0.5).unwrap();
+  run(&mut exporter, &mut simulation);
+  let rows = exporter.finish().unwrap() as usize;
+  assert_eq!(rows, 3 * 3);
+
+  for column in EXPORT_COLUMNS {
+    let (dtype, shape, data) = read_npy(&dir.join(format!("{}.npy", column)));
+    assert_eq!(shape, rows);
+    let width = match column {
+      "time" => { assert_eq!(dtype, "<f8"); 8 },
+      "id" => { assert_eq!(dtype, "<u8"); 8 },
+      _ => { assert_eq!(dtype, "<f4"); 4 },
+    };
+    assert_eq!(data.len(), rows * width);
+  }
+
+  let (_, _, x) = read_npy(&dir.join("x.npy"));
+  let last_x = f32::from_le_bytes(x[x.len() - 4..].try_into().unwrap());
+  assert_eq!(last_x, simulation.planets[&2].borrow().position.x);
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unwritable_paths_are_reported() {
+  let error = TrajectoryExporter::new(ExportFormat::Csv, "/nonexistent/out.csv", None, 0.0).err().unwrap();
+  assert_eq!(error.path, PathBuf::from("/nonexistent/out.csv"));
+}
+
+#[test]
+fn samples_land_on_every_interval() {
+  // As an f32, 0.01 is just under a hundredth, so ten steps fall just short of each 0.1 sample. That mustn't
+  // push every sample a step late.
+  let path = temp_path("interval.csv");
+  let mut simulation = three_bodies();
+  let mut exporter = TrajectoryExporter::new(ExportFormat::Csv, &path, Some(&[0]), 0.1).unwrap();
+  exporter.sample(&simulation).unwrap();
+  for _ in 0..100 {
+    simulation.step(0.01);
+    exporter.sample(&simulation).unwrap();
+  }
+  assert_eq!(exporter.finish().unwrap(), 11);
+
+  let text = fs::read_to_string(&path).unwrap();
+  fs::remove_file(&path).unwrap();
+  for (i, line) in text.lines().skip(1).enumerate() {
+    let time: f64 = line.split(',').next().unwrap().parse().unwrap();
+    assert!((time - i as f64 * 0.1).abs() < 1.0e-6, "sample {} at {}", i, time);
+  }
+}
+
+#[test]
+fn a_replaced_scene_ends_the_export() {
+  let path = temp_path("replaced.csv");
+  let mut simulation = three_bodies();
+  let mut exporter = TrajectoryExporter::new(ExportFormat::Csv, &path, None, 0.25).unwrap();
+  run(&mut exporter, &mut simulation);
+
+  // Clearing starts the time again, which mustn't be mixed into the same export
+  simulation.clear();
+  simulation.add_planet(Point2::new(100.0, 100.0), None, Some(1.0e9), 10.0, None);
+  let error = exporter.sample(&simulation).err().unwrap();
+  assert_eq!(error.path, path);
+  assert_eq!(exporter.finish().unwrap(), 3 * 5);
+  fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,101 @@
+use nalgebra::{Point2, Vector2};
+
+use orbits::camera::Camera;
+use orbits::frame::RotatingFrame;
+use orbits::simulation::Simulation;
+
+const DT: f32 = 1.0/60.0;
+
+// A light body on a circular orbit of radius 100 round a heavy one, going round once every 20 seconds or so
+fn pair() -> (Simulation, usize, usize) {
+  let mut simulation = Simulation::new();
+  let heavy = simulation.add_planet(Point2::new(400.0, 400.0), None, Some(1.0e9), 10.0, None);
+  let speed = (simulation.config().gravitational_constant * 1.0e9/100.0).sqrt();
+  let light = simulation.add_planet(Point2::new(500.0, 400.0), Some(Vector2::new(0.0, speed)), Some(1.0e3), 2.0, None);
+  (simulation, heavy, light)
+}
+
+fn close(a: Point2<f32>, b: Point2<f32>, tolerance: f32) -> bool {
+  (a - b).magnitude() < tolerance
+}
+
+#[test]
+fn the_pair_stands_still_in_its_frame() {
+  let (mut simulation, heavy, light) = pair();
+  let mut frame = RotatingFrame::new(&simulation, heavy, light).unwrap();
+  let start = simulation.planets[&light].borrow().position;
+  assert!(close(frame.current().to_frame(start), start, 1.0e-3));
+
+  // Half way round
+  for _ in 0..600 {
+    simulation.step(DT);
+    assert!(frame.record(&simulation, 1.0));
+  }
+  let pose = frame.current();
+  assert!(pose.angle.cos() < -0.9, "turned {} radians", pose.angle);
+  for (id, planet) in simulation.planets.iter() {
+    let planet = planet.borrow();
+    let original = if *id == light { start } else { Point2::new(400.0, 400.0) };
+    assert!(close(pose.to_frame(planet.position), original, 1.0), "body {} drawn at {}", id, pose.to_frame(planet.position));
+  }
+}
+
+#[test]
+fn frame_velocities_include_the_rotation() {
+  let (mut simulation, heavy, light) = pair();
+  let mut frame = RotatingFrame::new(&simulation, heavy, light).unwrap();
+  for _ in 0..100 {
+    simulation.step(DT);
+    frame.record(&simulation, 1.0);
+  }
+
+  // Standing still in the frame means keeping up with the light body
+  let pose = frame.current();
+  let planet = simulation.planets[&light].borrow();
+  let in_frame = pose.to_frame(planet.position);
+  assert!(close(pose.from_frame(in_frame), planet.position, 1.0e-3));
+  // To within how far the orbit is from circular after integrating it
+  let velocity = pose.velocity_from_frame(in_frame, Vector2::zeros());
+  assert!((velocity - planet.velocity).magnitude() < 0.01 * planet.velocity.magnitude(), "{} against {}", velocity, planet.velocity);
+}
+
+#[test]
+fn poses_are_kept_for_drawing_trails() {
+  let (mut simulation, heavy, light) = pair();
+  let mut frame = RotatingFrame::new(&simulation, heavy, light).unwrap();
+  assert!(frame.pose_at(-1.0).is_none());
+
+  for _ in 0..120 {
+    simulation.step(DT);
+    frame.record(&simulation, 0.5);
+  }
+  // Only the last half second is kept
+  assert_eq!(frame.pose_at(simulation.time), Some(frame.current()));
+  assert!(frame.pose_at(simulation.time - 0.4).is_some());
+  assert!(frame.pose_at(simulation.time - 0.6).is_none());
+
+  simulation.remove_planet(light);
+  assert!(!frame.record(&simulation, 0.5));
+}
+
+#[test]
+fn the_camera_draws_through_the_frame() {
+  let (mut simulation, heavy, light) = pair();
+  let mut frame = RotatingFrame::new(&simulation, heavy, light).unwrap();
+  for _ in 0..300 {
+    simulation.step(DT);
+    frame.record(&simulation, 1.0);
+  }
+
+  let mut camera = Camera::new((1280.0, 860.0));
+  camera.zoom_at(Point2::new(200.0, 300.0), 2.0);
+  let inertial = camera.to_screen(simulation.planets[&light].borrow().position);
+  camera.frame = Some(frame.current());
+  let position = simulation.planets[&light].borrow().position;
+  assert!(!close(camera.to_screen(position), inertial, 10.0));
+  assert!(close(camera.to_world(camera.to_screen(position)), position, 1.0e-2));
+
+  // Resetting the view keeps the frame
+  camera.reset();
+  assert_eq!(camera.frame, Some(frame.current()));
+}
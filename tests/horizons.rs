@@ -0,0 +1,94 @@
+use orbits::horizons::{self, HorizonsError};
+use orbits::scenario::{Scenario, ScenarioError};
+use orbits::simulation::Simulation;
+use orbits::units::Quantity;
+
+const KM_PER_AU: f64 = 1.495_978_707e8;
+
+fn fixture() -> Vec<horizons::Target> {
+  horizons::load(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/horizons.txt")).unwrap()
+}
+
+#[test]
+fn reads_both_layouts() {
+  let targets = fixture();
+  assert_eq!(targets.len(), 2);
+
+  let earth = &targets[0];
+  assert_eq!((earth.name.as_str(), earth.id), ("Earth", Some(399)));
+  assert_eq!(earth.states.len(), 2);
+  assert_eq!(earth.states[1].epoch, 2451555.0);
+  assert!(earth.state_at(Some(2451555.0)).is_some());
+  assert!(earth.state_at(Some(2451550.0)).is_none());
+
+  let mars = &targets[1];
+  assert_eq!((mars.name.as_str(), mars.id), ("Mars", Some(499)));
+  let state = mars.state_at(None).unwrap();
+  assert_eq!(state.position.x, 2.080409339037969e8);
+  assert_eq!(state.velocity.y, 2.629705600760069e1);
+}
+
+#[test]
+fn equatorial_tables_are_rotated_onto_the_ecliptic() {
+  let earth = fixture()[0].states[0];
+  // The Earth barely leaves the ecliptic, though its ICRF Z is over a third of an AU
+  assert!(earth.position.z.abs() < 1.0e3, "z = {} km", earth.position.z);
+  assert!(earth.velocity.z.abs() < 1.0e-3, "vz = {} km/s", earth.velocity.z);
+  // AU and AU/day become km and km/s
+  assert!((earth.position.magnitude()/KM_PER_AU - 0.98331).abs() < 1.0e-4);
+  assert!((earth.velocity.magnitude() - 30.29).abs() < 0.01, "{} km/s", earth.velocity.magnitude());
+}
+
+#[test]
+fn malformed_tables_name_the_line() {
+  let missing_units = "Target body name: Earth (399)\n$$SOE\n$$EOE\n";
+  assert!(matches!(horizons::parse(missing_units), Err(HorizonsError::Format { line: 2, .. })));
+
+  let cut_short = "Target body name: Earth (399)\nOutput units : KM-S\n$$SOE\n2451545.0 = A.D. 2000-Jan-01\n X = 1 Y = 2 Z = 3\n$$EOE\n";
+  assert!(matches!(horizons::parse(cut_short), Err(HorizonsError::Format { line: 4, .. })));
+
+  assert!(matches!(horizons::parse("Not a Horizons file\n"), Err(HorizonsError::Format { .. })));
+}
+
+#[test]
+fn scenario_imports_the_inner_planets() {
+  let scenario = Scenario::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios/horizons.toml")).unwrap();
+  let mut simulation = Simulation::new();
+  scenario.apply(&mut simulation).unwrap();
+  assert_eq!(simulation.planets.len(), 5);
+
+  let units = simulation.units.conversion(simulation.config().gravitational_constant);
+  let sun = simulation.planets[&0].borrow();
+  assert!((units.from_sim(Quantity::Mass, sun.mass as f64) - 1.0).abs() < 1.0e-3);
+  assert_eq!(units.from_sim(Quantity::Length, sun.radius as f64) as f32, 0.1);
+
+  // Earth goes round in about a year
+  let (primary, elements) = simulation.orbital_elements(3, None).unwrap();
+  assert_eq!(primary, 0);
+  assert!((units.from_sim(Quantity::Length, elements.semi_major_axis as f64) - 1.0).abs() < 1.0e-3);
+  assert!((units.from_sim(Quantity::Time, elements.period.unwrap() as f64) - 1.0).abs() < 0.01);
+}
+
+#[test]
+fn imports_check_their_bodies() {
+  let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+  let apply = |text: &str| {
+    let mut scenario = Scenario::parse(text)?;
+    scenario.directory = Some(dir.into());
+    scenario.apply(&mut Simulation::new())
+  };
+
+  let pixels = apply("[[horizons]]\npath = \"horizons.txt\"\nradius = 1\n");
+  assert!(matches!(pixels, Err(ScenarioError::Invalid { item, .. }) if item == "horizons[0]"));
+
+  let units = "[units]\nsystem = \"planetary\"\n";
+  assert!(apply(&format!("{}[[horizons]]\npath = \"horizons.txt\"\nradius = 1000\n", units)).is_ok());
+  match apply(&format!("{}[[horizons]]\npath = \"horizons.txt\"\nradius = 1000\nmasses = {{ Pluto = 1 }}\n", units)) {
+    Err(ScenarioError::Invalid { reason, .. }) => assert!(reason.contains("Pluto"), "{}", reason),
+    other => panic!("expected an unknown target, got {:?}", other),
+  }
+  match apply(&format!("{}[[horizons]]\npath = \"horizons.txt\"\nradius = 1000\nepoch = 2451546.0\n", units)) {
+    Err(ScenarioError::Invalid { reason, .. }) => assert!(reason.contains("no state at JD 2451546"), "{}", reason),
+    other => panic!("expected a missing epoch, got {:?}", other),
+  }
+}
@@ -0,0 +1,52 @@
+use nalgebra::{Point2, Vector2};
+
+use orbits::preview::{predict, Outcome};
+use orbits::simulation::{CollisionMode, Simulation};
+
+// A star with GM = 1e5, so a circular orbit 100 out goes at about 31.6 and takes about 20 seconds
+fn star() -> (Simulation, usize) {
+  let mut simulation = Simulation::new();
+  let star = simulation.add_planet(Point2::new(0.0, 0.0), None, Some(1.0e9), 10.0, None);
+  (simulation, star)
+}
+
+#[test]
+fn circular_orbits_survive() {
+  let (simulation, _) = star();
+  let speed = (1.0e5f32/100.0).sqrt();
+  let prediction = predict(&simulation, Point2::new(100.0, 0.0), Vector2::new(0.0, speed), 2.0, 10.0, 1.0/60.0);
+
+  assert_eq!(prediction.outcome, Outcome::Survives);
+  assert_eq!(prediction.path.len(), 601);
+  for point in prediction.path.iter() {
+    assert!((point.coords.magnitude() - 100.0).abs() < 2.0, "drifted to {}", point);
+  }
+  // Half way round after half a period
+  assert!(prediction.path.last().unwrap().x < -95.0);
+  // Previewing leaves the simulation alone
+  assert_eq!(simulation.planets.len(), 1);
+}
+
+#[test]
+fn collisions_name_the_body_hit() {
+  for mode in [CollisionMode::Merge, CollisionMode::SoftSphere] {
+    let (mut simulation, star) = star();
+    simulation.collision_mode = mode;
+    let prediction = predict(&simulation, Point2::new(100.0, 0.0), Vector2::new(-30.0, 0.0), 2.0, 10.0, 1.0/60.0);
+    match prediction.outcome {
+      Outcome::Collides { with, time } => {
+        assert_eq!(with, star, "{:?}", mode);
+        assert!(time > 0.5 && time < 3.0, "{:?} hit after {}", mode, time);
+      },
+      other => panic!("{:?}: expected a collision, got {:?}", mode, other),
+    }
+  }
+}
+
+#[test]
+fn fast_bodies_escape() {
+  let (simulation, _) = star();
+  let prediction = predict(&simulation, Point2::new(100.0, 0.0), Vector2::new(100.0, 0.0), 2.0, 10.0, 1.0/60.0);
+  assert!(matches!(prediction.outcome, Outcome::Escapes { .. }), "{:?}", prediction.outcome);
+  assert!(prediction.path.len() < 10);
+}
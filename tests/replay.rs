@@ -0,0 +1,152 @@
+use nalgebra::{Point2, Vector2};
+
+use std::collections::HashMap;
+
+use orbits::replay::{Replay, ReplayCursor, ReplayError, ReplayEvent, ReplayRecorder, REPLAY_QUANTUM, KEYFRAME_INTERVAL};
+use orbits::simulation::Simulation;
+
+const FRAMES: usize = 300;
+
+// Records a moon system, keeping the exact positions of every frame to compare playback against
+fn record() -> (Replay, Vec<HashMap<usize, Point2<f32>>>) {
+  let mut simulation = Simulation::new();
+  simulation.add_planet_with_moons(Point2::new(400.0, 400.0), None, None, 30.0, 60, (10.0, 80.0), (0.5, 1.5), (0.0, 0.2), true, None);
+
+  let mut recorder = ReplayRecorder::new();
+  let mut positions = Vec::new();
+  for _ in 0..FRAMES {
+    simulation.step(1.0/60.0);
+    recorder.record(&simulation);
+    positions.push(simulation.planets.iter().map(|(id, planet)| (*id, planet.borrow().position)).collect());
+  }
+  (recorder.finish(), positions)
+}
+
+fn assert_matches(cursor: &ReplayCursor, expected: &HashMap<usize, Point2<f32>>) {
+  let bodies: Vec<_> = cursor.bodies().collect();
+  assert_eq!(bodies.len(), expected.len());
+  for body in bodies {
+    let error = (body.position - expected[&body.id]).norm();
+    assert!(error <= REPLAY_QUANTUM, "body {} is {} px out", body.id, error);
+  }
+}
+
+#[test]
+fn playback_matches_the_recorded_run() {
+  let (replay, positions) = record();
+  assert_eq!(replay.frame_count(), FRAMES);
+
+  let mut cursor = ReplayCursor::new();
+  for (frame, expected) in positions.iter().enumerate() {
+    cursor.seek(&replay, frame).unwrap();
+    assert_matches(&cursor, expected);
+  }
+}
+
+#[test]
+fn scrubbing_backwards_matches_playing_forwards() {
+  let (replay, positions) = record();
+
+  let mut cursor = ReplayCursor::new();
+  for frame in (0..FRAMES).rev().step_by(7) {
+    cursor.seek(&replay, frame).unwrap();
+    assert_eq!(cursor.frame(), Some(frame));
+    assert_matches(&cursor, &positions[frame]);
+  }
+}
+
+#[test]
+fn file_round_trip() {
+  let (replay, _) = record();
+  let bytes = replay.to_bytes();
+  assert_eq!(Replay::from_bytes(&bytes).unwrap(), replay);
+
+  let path = std::env::temp_dir().join(format!("orbits-replay-{}.orbrec", std::process::id()));
+  replay.save(&path).unwrap();
+  let loaded = Replay::load(&path);
+  std::fs::remove_file(&path).unwrap();
+  assert_eq!(loaded.unwrap(), replay);
+}
+
+#[test]
+fn recording_is_compact() {
+  let (replay, positions) = record();
+  let body_frames: usize = positions.iter().map(|frame| frame.len()).sum();
+  let bytes_per_body_frame = replay.to_bytes().len() as f32/body_frames as f32;
+  assert!(bytes_per_body_frame < 5.0, "{} bytes per body per frame", bytes_per_body_frame);
+}
+
+#[test]
+fn merges_are_recorded_as_events() {
+  let mut simulation = Simulation::new();
+  let a = simulation.add_planet(Point2::new(100.0, 100.0), Some(Vector2::new(30.0, 0.0)), None, 5.0, None);
+  let b = simulation.add_planet(Point2::new(130.0, 100.0), Some(Vector2::new(-30.0, 0.0)), None, 4.0, None);
+
+  let mut recorder = ReplayRecorder::new();
+  recorder.record(&simulation);
+  for _ in 0..KEYFRAME_INTERVAL - 1 {
+    simulation.step(1.0/60.0);
+    recorder.record(&simulation);
+  }
+  let replay = recorder.finish();
+
+  // Either body may be the one absorbed
+  let (survivor, absorbed) = if simulation.planets.contains_key(&a) { (a, b) } else { (b, a) };
+  assert_eq!(replay.events(0).len(), 2);
+  let events: Vec<ReplayEvent> = (1..replay.frame_count()).flat_map(|frame| replay.events(frame)).collect();
+  assert!(events.contains(&ReplayEvent::Destroyed { id: absorbed }));
+  assert!(events.iter().any(|event| matches!(event, ReplayEvent::Changed { id, .. } if *id == survivor)));
+
+  let mut cursor = ReplayCursor::new();
+  cursor.seek(&replay, replay.frame_count() - 1).unwrap();
+  let survivors: Vec<_> = cursor.bodies().collect();
+  assert_eq!(survivors.len(), 1);
+  assert_eq!(survivors[0].radius, simulation.planets[&survivor].borrow().radius);
+}
+
+#[test]
+fn frames_are_found_by_time() {
+  let (replay, _) = record();
+  assert_eq!(replay.frame_at(-1.0), 0);
+  assert_eq!(replay.frame_at(replay.frame_time(42)), 42);
+  assert_eq!(replay.frame_at(replay.frame_time(42) + 0.001), 42);
+  assert_eq!(replay.frame_at(f64::MAX), FRAMES - 1);
+}
+
+#[test]
+fn rejects_damaged_files() {
+  let bytes = record().0.to_bytes();
+  assert!(matches!(Replay::from_bytes(&bytes[..bytes.len() - 3]), Err(ReplayError::Format(_))));
+  assert!(matches!(Replay::from_bytes(b"not a recording"), Err(ReplayError::Format(_))));
+
+  // A length far past the end of the file is refused rather than allocated
+  let mut header = bytes[..13].to_vec();
+  header.extend_from_slice(&[KEYFRAME_INTERVAL as u8, 1]);
+  header.extend_from_slice(&0f64.to_le_bytes());
+  header.extend_from_slice(&[1, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+  assert!(matches!(Replay::from_bytes(&header), Err(ReplayError::Format(_))));
+  assert!(matches!(Replay::load("/nonexistent/run.orbrec"), Err(ReplayError::Io { .. })));
+}
+
+#[test]
+fn refuses_frames_from_before_the_last() {
+  let mut simulation = Simulation::new();
+  simulation.add_planet_with_moons(Point2::new(400.0, 400.0), None, None, 30.0, 10, (10.0, 80.0), (0.5, 1.5), (0.0, 0.2), true, None);
+
+  let mut recorder = ReplayRecorder::new();
+  for _ in 0..10 {
+    simulation.step(1.0/60.0);
+    assert!(recorder.record(&simulation));
+  }
+
+  // Replacing the scene starts its time again
+  simulation.clear();
+  simulation.add_planet_with_moons(Point2::new(400.0, 400.0), None, None, 30.0, 10, (10.0, 80.0), (0.5, 1.5), (0.0, 0.2), true, None);
+  simulation.step(1.0/60.0);
+  assert!(!recorder.record(&simulation));
+  assert_eq!(recorder.frame_count(), 10);
+
+  let replay = recorder.finish();
+  assert_eq!(replay.frame_at(replay.frame_time(4)), 4);
+  assert_eq!(replay.frame_at(f64::MAX), 9);
+}
@@ -0,0 +1,78 @@
+use orbits::scenario::{Scenario, ScenarioError};
+use orbits::simulation::Simulation;
+
+fn reason(result: Result<Scenario, ScenarioError>) -> (String, String) {
+  match result {
+    Err(ScenarioError::Invalid { item, reason }) => (item, reason),
+    other => panic!("expected an invalid scenario, got {:?}", other.map(|_| ())),
+  }
+}
+
+#[test]
+fn impossible_orbits_are_rejected_before_loading() {
+  // Caught when the scenario is read, rather than after some of it has been added to a simulation
+  let text = "
+    [[bodies]]
+    position = [400, 400]
+    radius = 10
+
+    [[bodies]]
+    primary = 0
+    radius = 2
+    orbit = { semi_major_axis = 100, eccentricity = 1.5 }
+  ";
+  let (item, reason_text) = reason(Scenario::parse(text));
+  assert_eq!(item, "bodies[1]");
+  assert!(reason_text.contains("semi-major axis"), "{}", reason_text);
+}
+
+#[test]
+fn open_orbits_must_reach_their_true_anomaly() {
+  let (item, reason_text) = reason(Scenario::parse("
+    [[bodies]]
+    position = [400, 400]
+    radius = 10
+
+    [[bodies]]
+    primary = 0
+    radius = 2
+    orbit = { semi_major_axis = -100, eccentricity = 1.5, true_anomaly = 3.0 }
+  "));
+  assert_eq!(item, "bodies[1]");
+  assert!(reason_text.contains("asymptotes"), "{}", reason_text);
+}
+
+#[test]
+fn king_clusters_need_a_w0_in_range() {
+  let king = |w0: f32| format!("
+    [[generators]]
+    kind = \"king_cluster\"
+    centre = [400, 400]
+    count = 50
+    mass = 1e9
+    core_radius = 20
+    w0 = {}
+    body_radius = 1
+  ", w0);
+  for w0 in [0.0, -1.0, 15.5, 100.0] {
+    let (item, reason_text) = reason(Scenario::parse(&king(w0)));
+    assert_eq!(item, "generators[0]");
+    assert!(reason_text.contains("`w0`"), "{}", reason_text);
+  }
+  // The deepest allowed still builds
+  let mut simulation = Simulation::new();
+  Scenario::parse(&king(15.0)).unwrap().apply(&mut simulation).unwrap();
+  assert_eq!(simulation.planets.len(), 50);
+}
+
+#[test]
+fn galaxy_disks_must_reach_past_the_nucleus() {
+  let (item, reason_text) = reason(Scenario::parse("
+    [[generators]]
+    kind = \"galaxy\"
+    centre = [400, 400]
+    spec = { nucleus_radius = 10, disk_radius = 30 }
+  "));
+  assert_eq!(item, "generators[0]");
+  assert!(reason_text.contains("`disk_radius`"), "{}", reason_text);
+}
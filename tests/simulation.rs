@@ -0,0 +1,298 @@
+use nalgebra::{Point2, Vector2};
+
+use orbits::barnes_hut::{QuadTree, TreeBody};
+use orbits::simulation::{CollisionMode, GravitySolver, Integrator, Simulation};
+use orbits::config::SimConfig;
+use orbits::generators::{GalaxySpec, HaloKind};
+use orbits::planet::Halo;
+use orbits::tools;
+
+fn cluster(seed: u64) -> Simulation {
+  let mut simulation = Simulation::new();
+  simulation.seed(seed);
+  simulation.add_plummer_cluster(Point2::new(400.0, 400.0), None, 300, 1.0e9, 60.0, 0.5, true);
+  simulation
+}
+
+fn positions(simulation: &Simulation) -> Vec<(usize, Point2<f32>)> {
+  let mut positions: Vec<_> = simulation.planets.iter().map(|(id, planet)| (*id, planet.borrow().position)).collect();
+  positions.sort_unstable_by_key(|(id, _)| *id);
+  positions
+}
+
+#[test]
+fn seeded_generators_repeat() {
+  assert_eq!(positions(&cluster(7)), positions(&cluster(7)));
+  assert_ne!(positions(&cluster(7)), positions(&cluster(8)));
+}
+
+#[test]
+fn tree_forces_approximate_direct_forces() {
+  let simulation = cluster(1);
+  let bodies: Vec<TreeBody> = simulation.planets.values().map(|planet| {
+    let planet = planet.borrow();
+    TreeBody { position: planet.position, mass: planet.mass, radius: planet.radius }
+  }).collect();
+  let g = SimConfig::default().gravitational_constant;
+  let tree = QuadTree::new(&bodies, 0.5, g);
+
+  let mut total_error = 0.0;
+  let mut total_force = 0.0;
+  for (i, body) in bodies.iter().enumerate() {
+    let mut direct = Vector2::new(0.0, 0.0);
+    for (_, other) in bodies.iter().enumerate().filter(|(j, _)| *j != i) {
+      let dist_vec = other.position - body.position;
+      let dist_squared = dist_vec.magnitude_squared().max((body.radius + other.radius).powi(2));
+      direct += dist_vec * (g * body.mass * other.mass/dist_squared.sqrt().powi(3));
+    }
+    total_error += (tree.force_on(i) - direct).magnitude();
+    total_force += direct.magnitude();
+  }
+
+  assert!(total_error/total_force < 0.02, "mean relative error {}", total_error/total_force);
+}
+
+#[test]
+fn barnes_hut_follows_direct_gravity() {
+  let mut direct = cluster(3);
+  let mut tree = cluster(3);
+  for simulation in [&mut direct, &mut tree] {
+    simulation.collision_mode = CollisionMode::SoftSphere;
+  }
+  tree.gravity_solver = GravitySolver::BarnesHut;
+
+  for _ in 0..60 {
+    direct.step(1.0/60.0);
+    tree.step(1.0/60.0);
+  }
+
+  let (direct, tree) = (positions(&direct), positions(&tree));
+  assert_eq!(direct.len(), tree.len());
+  let mean_offset = direct.iter().zip(tree.iter())
+    .map(|((_, a), (_, b))| (a - b).magnitude())
+    .sum::<f32>()/direct.len() as f32;
+  assert!(mean_offset < 0.5, "bodies ended {} apart on average", mean_offset);
+}
+
+#[test]
+fn leapfrog_measures_energy_better_than_euler() {
+  let energy_drift = |integrator| {
+    let mut simulation = Simulation::new();
+    simulation.integrator = integrator;
+    let star = simulation.add_planet(Point2::new(0.0, 0.0), None, Some(1.0e9), 10.0, None);
+    let orbit = orbits::tools::OrbitSpec {
+      semi_major_axis: 200.0,
+      eccentricity: 0.5,
+      argument_of_periapsis: 0.0,
+      true_anomaly: 0.0,
+      direction: orbits::tools::OrbitDirection::Prograde,
+    };
+    simulation.add_planet_in_orbit(star, &orbit, None, 2.0).unwrap();
+
+    let mut worst: f64 = 0.0;
+    for _ in 0..2000 {
+      simulation.step(1.0/60.0);
+      worst = worst.max(simulation.drift().energy.abs());
+    }
+    worst
+  };
+
+  let (euler, leapfrog) = (energy_drift(Integrator::Euler), energy_drift(Integrator::Leapfrog));
+  assert!(leapfrog < euler/2.0, "leapfrog drift {} against euler {}", leapfrog, euler);
+}
+
+#[test]
+fn centre_of_momentum_frame_stops_drift() {
+  let mut simulation = Simulation::new();
+  let star = simulation.add_planet(Point2::new(300.0, 300.0), Some(Vector2::new(20.0, -5.0)), Some(1.0e9), 10.0, None);
+  simulation.add_planet(Point2::new(500.0, 300.0), Some(Vector2::new(-3.0, 40.0)), Some(1.0e7), 3.0, None);
+  assert_eq!(simulation.heaviest_body(), Some(star));
+  assert_eq!(simulation.body_at(Point2::new(305.0, 295.0)), Some(star));
+  assert_eq!(simulation.body_at(Point2::new(400.0, 300.0)), None);
+
+  let (_, before) = simulation.centre_of_momentum().unwrap();
+  assert_eq!(simulation.to_centre_of_momentum_frame(), before);
+  let (centre, velocity) = simulation.centre_of_momentum().unwrap();
+  assert!(velocity.magnitude() < 1.0e-4, "still moving at {}", velocity);
+
+  for _ in 0..200 {
+    simulation.step(1.0/60.0);
+  }
+  let (after, _) = simulation.centre_of_momentum().unwrap();
+  assert!((after - centre).magnitude() < 0.01, "centre of mass moved {}", (after - centre).magnitude());
+}
+
+#[test]
+fn edits_go_through_the_simulation() {
+  let mut simulation = Simulation::new();
+  let star = simulation.add_planet(Point2::new(300.0, 300.0), None, Some(1.0e9), 10.0, None);
+  let moon = simulation.add_planet(Point2::new(400.0, 300.0), Some(Vector2::new(0.0, 30.0)), Some(1.0e6), 2.0, None);
+  for _ in 0..10 {
+    simulation.step(1.0/60.0);
+  }
+
+  simulation.set_mass(star, 2.0e9);
+  simulation.set_radius(moon, 4.0);
+  simulation.set_velocity(moon, Vector2::new(5.0, 0.0));
+  {
+    let (star, moon) = (simulation.planets[&star].borrow(), simulation.planets[&moon].borrow());
+    assert_eq!(star.mass, 2.0e9);
+    assert_eq!(moon.radius, 4.0);
+    assert_eq!(moon.velocity, Vector2::new(5.0, 0.0));
+  }
+  // Drift is measured from the edited state, not the one before
+  simulation.step(1.0/60.0);
+  assert!(simulation.drift().energy.abs() < 1.0e-3, "energy drift {}", simulation.drift().energy);
+
+  simulation.remove_planet(moon);
+  assert_eq!(simulation.planets.len(), 1);
+}
+
+#[test]
+fn edits_that_would_break_a_body_are_refused() {
+  let mut simulation = Simulation::new();
+  let star = simulation.add_planet(Point2::new(300.0, 300.0), None, Some(1.0e9), 10.0, None);
+
+  assert!(!simulation.set_mass(star, 0.0));
+  assert!(!simulation.set_mass(star, f32::NAN));
+  assert!(!simulation.set_radius(star, -1.0));
+  assert!(!simulation.set_radius(star, f32::INFINITY));
+  assert!(!simulation.set_mass(star + 1, 1.0));
+  assert!(!simulation.set_velocity(star, Vector2::new(f32::NAN, 0.0)));
+  assert!(!simulation.set_velocity(star, Vector2::new(0.0, f32::NEG_INFINITY)));
+  {
+    let star = simulation.planets[&star].borrow();
+    assert_eq!(star.mass, 1.0e9);
+    assert_eq!(star.radius, 10.0);
+    assert_eq!(star.velocity, Vector2::zeros());
+  }
+
+  simulation.step(1.0/60.0);
+  assert!(simulation.planets[&star].borrow().spin.is_finite());
+}
+
+#[test]
+fn merged_bodies_take_the_planet_density() {
+  let mut simulation = Simulation::new();
+  simulation.add_planet(Point2::new(100.0, 100.0), None, Some(1.0e6), 5.0, None);
+  simulation.add_planet(Point2::new(105.0, 100.0), None, Some(2.0e6), 4.0, None);
+  simulation.step(1.0/60.0);
+
+  assert_eq!(simulation.planets.len(), 1);
+  let merged = simulation.planets.values().next().unwrap().borrow();
+  let radius = tools::inverse_volume_of_sphere(3.0e6/simulation.config().planet_density);
+  assert_eq!(merged.mass, 3.0e6);
+  assert!((merged.radius - radius).abs() < radius * 1.0e-5, "radius {}, expected {}", merged.radius, radius);
+}
+
+#[test]
+fn merged_galaxy_cores_keep_the_heavier_id_and_their_volume() {
+  let mut simulation = Simulation::new();
+  let light = simulation.add_planet(Point2::new(105.0, 100.0), None, Some(5.0e6), 4.0, None);
+  let heavy = simulation.add_planet(Point2::new(100.0, 100.0), None, Some(1.0e6), 5.0, None);
+  simulation.planets[&heavy].borrow_mut().halo = Some(Halo { mass: 1.0e9, scale_radius: 20.0 });
+  simulation.step(1.0/60.0);
+
+  assert!(!simulation.planets.contains_key(&light));
+  let merged = simulation.planets[&heavy].borrow();
+  let radius = tools::inverse_volume_of_sphere(tools::volume_of_sphere(4.0) + tools::volume_of_sphere(5.0));
+  assert_eq!(merged.mass, 6.0e6);
+  assert_eq!(merged.halo, Some(Halo { mass: 1.0e9, scale_radius: 20.0 }));
+  assert!((merged.radius - radius).abs() < radius * 1.0e-5, "radius {}, expected {}", merged.radius, radius);
+}
+
+#[test]
+fn small_soft_spheres_bounce_without_gaining_energy() {
+  // Stiff enough contacts would ring faster than the step can follow, and fling small bodies apart
+  for integrator in [Integrator::Euler, Integrator::Leapfrog] {
+    let mut simulation = Simulation::new();
+    simulation.integrator = integrator;
+    simulation.collision_mode = CollisionMode::SoftSphere;
+    simulation.set_config(SimConfig { gravitational_constant: 0.0, ..SimConfig::default() });
+    let a = simulation.add_planet(Point2::new(100.0, 100.0), Some(Vector2::new(5.0, 0.0)), None, 0.5, None);
+    let b = simulation.add_planet(Point2::new(102.0, 100.0), Some(Vector2::new(-5.0, 0.0)), None, 0.5, None);
+    for _ in 0..120 {
+      simulation.step(1.0/60.0);
+    }
+
+    let (a, b) = (simulation.planets[&a].borrow(), simulation.planets[&b].borrow());
+    let separating = (b.velocity - a.velocity).x;
+    assert!(separating > 0.0 && separating <= 10.0, "{:?} left them separating at {}", integrator, separating);
+  }
+}
+
+#[test]
+fn leapfrog_starts_with_the_forces_where_bodies_are() {
+  // A body dropped from rest falls a dt^2/2 in the first step, and is then moving at a dt
+  let mut simulation = Simulation::new();
+  simulation.integrator = Integrator::Leapfrog;
+  // Dropped at the origin, where an f32 position is precise enough to see the first step's fall
+  simulation.add_planet(Point2::new(200.0, 0.0), None, Some(1.0e9), 10.0, None);
+  let dropped = simulation.add_planet(Point2::new(0.0, 0.0), None, Some(1.0), 1.0, None);
+  let acceleration = SimConfig::default().gravitational_constant * 1.0e9/200.0f32.powi(2);
+  let dt = 1.0/60.0;
+
+  simulation.step(dt);
+  let planet = simulation.planets[&dropped].borrow();
+  let fallen = planet.position.x;
+  assert!((fallen/(acceleration * dt.powi(2)/2.0) - 1.0).abs() < 0.01, "fell {}", fallen);
+  assert!((planet.velocity.x/(acceleration * dt) - 1.0).abs() < 0.01, "moving at {}", planet.velocity.x);
+}
+
+#[test]
+fn barnes_hut_stays_fast_for_large_scenes() {
+  let large = |solver| {
+    let mut simulation = Simulation::new();
+    simulation.seed(5);
+    simulation.gravity_solver = solver;
+    simulation.add_plummer_cluster(Point2::new(0.0, 0.0), None, 2000, 1.0e10, 400.0, 0.5, true);
+    simulation
+  };
+  let (mut direct, mut tree) = (large(GravitySolver::Direct), large(GravitySolver::BarnesHut));
+  // The first step also takes the starting measurements
+  direct.step(1.0/60.0);
+  tree.step(1.0/60.0);
+
+  let start = std::time::Instant::now();
+  direct.step(1.0/60.0);
+  let direct_time = start.elapsed();
+  let start = std::time::Instant::now();
+  tree.step(1.0/60.0);
+  let tree_time = start.elapsed();
+
+  // Energy included, which would cost as much as direct gravity if every pair were summed. The gap only widens
+  // with more bodies; this many keeps the test quick without optimisations.
+  assert!(tree_time * 2 < direct_time, "tree step took {:?} against {:?} direct", tree_time, direct_time);
+  let (exact, approximate) = (direct.diagnostics().potential_energy, tree.diagnostics().potential_energy);
+  assert!(((approximate - exact)/exact).abs() < 0.01, "tree potential {} against {}", approximate, exact);
+}
+
+#[test]
+fn galaxy_disks_fill_a_window_far_out() {
+  // The disk starts 50 scale lengths out, where hardly any of an untruncated disk's bodies would land
+  let spec = GalaxySpec { nucleus_radius: 50.0, disk_scale_length: 3.0, disk_radius: 160.0, bulge_particles: 0, ..GalaxySpec::default() };
+  let mut simulation = Simulation::new();
+  let centre = Point2::new(400.0, 400.0);
+  let ids = simulation.add_galaxy(centre, None, &spec);
+
+  assert_eq!(ids.len(), 1 + spec.disk_particles);
+  for id in &ids[1..] {
+    let r = (simulation.planets[id].borrow().position - centre).norm();
+    assert!((149.9..=160.1).contains(&r), "disk body {} at radius {}", id, r);
+  }
+}
+
+#[test]
+fn galaxies_without_bulge_particles_have_no_bulge() {
+  let bodies = |bulge_mass: f32| {
+    let spec = GalaxySpec { bulge_mass, bulge_particles: 0, halo: HaloKind::None, ..GalaxySpec::default() };
+    let mut simulation = Simulation::new();
+    simulation.seed(7);
+    simulation.add_galaxy(Point2::new(400.0, 400.0), None, &spec);
+    let mut velocities: Vec<_> = simulation.planets.iter().map(|(id, planet)| (*id, planet.borrow().velocity)).collect();
+    velocities.sort_unstable_by_key(|(id, _)| *id);
+    (spec.total_mass(), velocities)
+  };
+  // The bulge's mass mustn't speed up the disk when there are no bulge bodies to supply it
+  assert!(bodies(1.0e9) == bodies(0.0));
+}
@@ -0,0 +1,146 @@
+use nalgebra::{Point2, Vector2};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use orbits::planet::PlanetTrail;
+use orbits::simulation::{CollisionMode, Simulation};
+use orbits::snapshot::{self, SnapshotError, SNAPSHOT_VERSION};
+
+fn sample_simulation() -> Simulation {
+  let mut simulation = Simulation::new();
+  simulation.collision_mode = CollisionMode::SoftSphere;
+  simulation.add_planet(Point2::new(100.0, 100.0), None, Some(1.0e9), 10.0, None);
+  simulation.add_planet(Point2::new(250.0, 100.0), Some(Vector2::new(0.0, 25.0)), None, 3.0, Some(Duration::from_secs(2)));
+  let removed = simulation.add_planet(Point2::new(400.0, 400.0), None, None, 1.0, None);
+  simulation.add_planet(Point2::new(100.0, 300.0), Some(Vector2::new(-4.0, 1.5)), None, 2.0, None);
+  simulation.remove_planet(removed);
+
+  for _ in 0..30 {
+    simulation.step(1.0/60.0);
+  }
+  simulation
+}
+
+fn assert_same_bodies(a: &Simulation, b: &Simulation) {
+  assert_eq!(a.planets.len(), b.planets.len());
+  for (id, planet) in a.planets.iter() {
+    let other = b.planets.get(id).unwrap_or_else(|| panic!("body {} missing after loading", id));
+    assert_eq!(*planet.borrow(), *other.borrow());
+  }
+}
+
+#[test]
+fn round_trip_keeps_every_body_exactly() {
+  let simulation = sample_simulation();
+  let loaded = snapshot::from_json(&snapshot::to_json(&simulation, None).unwrap()).unwrap();
+
+  assert_same_bodies(&simulation, &loaded.simulation);
+  assert_eq!(loaded.simulation.collision_mode, CollisionMode::SoftSphere);
+  assert_eq!(loaded.simulation.time, simulation.time);
+  assert_eq!(loaded.simulation.steps, simulation.steps);
+  assert_eq!(loaded.simulation.diagnostics(), simulation.diagnostics());
+  assert!(loaded.trails.is_none());
+}
+
+#[test]
+fn spawn_protection_survives_round_trip() {
+  let simulation = sample_simulation();
+  let loaded = snapshot::from_json(&snapshot::to_json(&simulation, None).unwrap()).unwrap();
+
+  let protected: Vec<usize> = loaded.simulation.planets.iter()
+    .filter(|(_, planet)| planet.borrow().has_spawn_protection())
+    .map(|(id, _)| *id)
+    .collect();
+  assert_eq!(protected, vec![1]);
+}
+
+#[test]
+fn loaded_simulation_continues_handing_out_ids() {
+  let mut simulation = sample_simulation();
+  let mut loaded = snapshot::from_json(&snapshot::to_json(&simulation, None).unwrap()).unwrap().simulation;
+
+  let expected = simulation.add_planet(Point2::new(0.0, 0.0), None, None, 1.0, None);
+  assert_eq!(loaded.add_planet(Point2::new(0.0, 0.0), None, None, 1.0, None), expected);
+  assert_eq!(expected, 4);
+}
+
+#[test]
+fn loaded_simulation_steps_like_the_original() {
+  let mut simulation = sample_simulation();
+  let mut loaded = snapshot::from_json(&snapshot::to_json(&simulation, None).unwrap()).unwrap().simulation;
+
+  for _ in 0..60 {
+    simulation.step(1.0/60.0);
+    loaded.step(1.0/60.0);
+  }
+
+  // Forces may be summed in a different order, so allow for rounding
+  for (id, planet) in simulation.planets.iter() {
+    let (a, b) = (planet.borrow(), loaded.planets[id].borrow());
+    assert!((a.position - b.position).norm() < 1.0e-3, "body {} drifted apart: {} vs {}", id, a.position, b.position);
+    assert!((a.velocity - b.velocity).norm() < 1.0e-3);
+  }
+  assert_eq!(simulation.steps, loaded.steps);
+}
+
+#[test]
+fn trails_round_trip() {
+  let simulation = sample_simulation();
+  let mut trails = HashMap::new();
+  for (id, planet) in simulation.planets.iter() {
+    let mut trail = PlanetTrail::new(planet.borrow().position, 0.0, planet.borrow().color());
+    trail.add_node(planet.borrow().position + Vector2::new(5.0, 0.0), 0.1);
+    trails.insert(*id, RefCell::new(trail));
+  }
+
+  let loaded = snapshot::from_json(&snapshot::to_json(&simulation, Some(&trails)).unwrap()).unwrap();
+  let loaded_trails = loaded.trails.expect("trails were saved");
+  assert_eq!(loaded_trails.len(), trails.len());
+  for (id, trail) in trails.iter() {
+    assert_eq!(loaded_trails[id].borrow().node_count(), trail.borrow().node_count());
+  }
+}
+
+#[test]
+fn saves_to_and_loads_from_a_file() {
+  let simulation = sample_simulation();
+  let path = std::env::temp_dir().join(format!("orbits-snapshot-{}.json", std::process::id()));
+
+  snapshot::save(&path, &simulation, None).unwrap();
+  let loaded = snapshot::load(&path);
+  std::fs::remove_file(&path).unwrap();
+
+  assert_same_bodies(&simulation, &loaded.unwrap().simulation);
+}
+
+#[test]
+fn version_1_loads_without_its_trails() {
+  let simulation = sample_simulation();
+  // Trail nodes were saved with their wall clock age back then
+  let json = snapshot::to_json(&simulation, None).unwrap()
+    .replacen(&format!("\"version\":{}", SNAPSHOT_VERSION), "\"version\":1", 1)
+    .replacen("\"trails\":null", "\"trails\":{\"0\":{\"nodes\":[{\"pos\":[100.0,100.0],\"age\":{\"secs\":0,\"nanos\":5000000}}],\
+      \"node_placement_timer\":{\"secs\":0,\"nanos\":0},\"has_parent\":true}}", 1);
+  assert!(json.contains("node_placement_timer"));
+
+  let loaded = snapshot::from_json(&json).unwrap();
+  assert_same_bodies(&simulation, &loaded.simulation);
+  assert!(loaded.trails.is_none());
+}
+
+#[test]
+fn rejects_other_versions() {
+  let json = snapshot::to_json(&sample_simulation(), None).unwrap()
+    .replacen(&format!("\"version\":{}", SNAPSHOT_VERSION), "\"version\":999", 1);
+
+  assert!(matches!(snapshot::from_json(&json), Err(SnapshotError::Version { found: 999 })));
+}
+
+#[test]
+fn rejects_malformed_files() {
+  assert!(matches!(snapshot::from_json("{\"version\": 1, \"simulation\": 5}"), Err(SnapshotError::Format(_))));
+  assert!(matches!(snapshot::from_json("not json"), Err(SnapshotError::Format(_))));
+  assert!(matches!(snapshot::load("/nonexistent/snapshot.json"), Err(SnapshotError::Io { .. })));
+}
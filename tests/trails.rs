@@ -0,0 +1,63 @@
+use ggez::graphics::Color;
+use nalgebra::Point2;
+
+use orbits::config::SimConfig;
+use orbits::planet::{Planet, PlanetTrail};
+
+fn body_at(x: f32) -> Planet {
+  Planet::new(0, Point2::new(x, 0.0), None, 1.0, 5.0, None)
+}
+
+#[test]
+fn trails_age_with_simulated_time() {
+  let config = SimConfig::default();
+  let planet = body_at(0.0);
+  let mut trail = PlanetTrail::new(planet.position, 0.0, Color::WHITE);
+
+  // Paused: however many frames go by, nothing is placed or fades
+  for _ in 0..1000 {
+    trail.update(0.0, Some(&planet), &config);
+  }
+  assert_eq!(trail.node_count(), 1);
+
+  // Once the body is gone the trail dies after its lifetime in simulated seconds
+  let lifetime = config.trail_node_lifetime as f64;
+  trail.update(lifetime/2.0, None, &config);
+  assert_eq!(trail.node_count(), 1);
+  assert!(!trail.is_dead());
+  trail.update(lifetime, None, &config);
+  assert!(trail.is_dead());
+}
+
+#[test]
+fn fast_bodies_place_nodes_by_distance() {
+  let config = SimConfig::default();
+  let mut trail = PlanetTrail::new(Point2::origin(), 0.0, Color::WHITE);
+
+  // Well under a node period per update, but a node spacing further along each time
+  let dt = config.trail_node_period as f64/10.0;
+  for i in 1..=10 {
+    let planet = body_at(i as f32 * config.trail_node_spacing);
+    trail.update(i as f64 * dt, Some(&planet), &config);
+  }
+  assert_eq!(trail.node_count(), 11);
+
+  // A slow body only gets one a period
+  let mut planet = body_at(0.0);
+  let mut slow = PlanetTrail::new(planet.position, 0.0, Color::WHITE);
+  for i in 1..=15 {
+    planet.position.x = i as f32 * 0.01 * config.trail_node_spacing;
+    slow.update(i as f64 * dt, Some(&planet), &config);
+  }
+  assert_eq!(slow.node_count(), 2);
+}
+
+#[test]
+fn trails_are_capped() {
+  let config = SimConfig { trail_max_nodes: 20, trail_node_lifetime: 1.0e6, ..SimConfig::default() };
+  let mut trail = PlanetTrail::new(Point2::origin(), 0.0, Color::WHITE);
+  for i in 1..=100 {
+    trail.update(i as f64, Some(&body_at(i as f32)), &config);
+  }
+  assert_eq!(trail.node_count(), 20);
+}
@@ -0,0 +1,74 @@
+use orbits::config::SimConfig;
+use orbits::scenario::{Scenario, ScenarioError};
+use orbits::simulation::Simulation;
+use orbits::units::{Quantity, UnitSystem, Units};
+
+fn astronomical() -> Units {
+  Units { system: UnitSystem::Astronomical, length_scale: 150.0, time_scale: 4.0, origin: [640.0, 430.0] }
+}
+
+#[test]
+fn solar_system_reads_in_years() {
+  let scenario = Scenario::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios/solar_system.toml")).unwrap();
+  let mut simulation = Simulation::new();
+  scenario.apply(&mut simulation).unwrap();
+  assert_eq!(simulation.units, astronomical());
+
+  let units = simulation.units.conversion(simulation.config().gravitational_constant);
+  let sun = simulation.planets[&0].borrow();
+  assert!((units.from_sim(Quantity::Mass, sun.mass as f64) - 1.0).abs() < 1.0e-4);
+  let position = units.position_from_sim(nalgebra::Point2::new(sun.position.x as f64, sun.position.y as f64));
+  assert!(position.coords.magnitude() < 1.0e-6);
+
+  // Earth goes round once a year
+  let (primary, elements) = simulation.orbital_elements(3, None).unwrap();
+  assert_eq!(primary, 0);
+  let period = units.from_sim(Quantity::Time, elements.period.unwrap() as f64);
+  assert!((period - 1.0).abs() < 0.01, "period {} yr", period);
+  assert!((units.from_sim(Quantity::Length, elements.semi_major_axis as f64) - 1.0).abs() < 1.0e-3);
+}
+
+#[test]
+fn conversions_round_trip() {
+  let g = SimConfig::default().gravitational_constant as f64;
+  for system in [UnitSystem::Pixel, UnitSystem::Si, UnitSystem::Astronomical, UnitSystem::Planetary] {
+    let units = Units { system, length_scale: 3.0, time_scale: 0.5, origin: [10.0, 20.0] }.conversion(g as f32);
+    for quantity in [Quantity::Length, Quantity::Mass, Quantity::Time, Quantity::Velocity, Quantity::Energy, Quantity::AngularMomentum] {
+      let back = units.from_sim(quantity, units.to_sim(quantity, 2.5) as f64);
+      assert!((back - 2.5).abs() < 1.0e-5, "{:?} {:?} came back as {}", system, quantity, back);
+    }
+
+    // The simulation's G becomes the system's own once masses, lengths and times are converted
+    if system == UnitSystem::Astronomical {
+      let real_g = g * units.scale(Quantity::Mass) * units.scale(Quantity::Time).powi(2)/units.scale(Quantity::Length).powi(3);
+      assert!((real_g - 4.0 * std::f64::consts::PI.powi(2)).abs() < 1.0e-3);
+    }
+  }
+}
+
+#[test]
+fn symbols_and_formatting() {
+  let units = astronomical().conversion(1.0e-4);
+  assert_eq!(units.symbol(Quantity::Velocity), "AU/yr");
+  assert_eq!(units.symbol(Quantity::Energy), "M_sun AU^2/yr^2");
+  assert_eq!(units.symbol(Quantity::AngularVelocity), "1/yr");
+  assert_eq!(units.format(Quantity::Time, 6.0), "1.500 yr");
+
+  let pixels = Units::default().conversion(1.0e-4);
+  assert_eq!(pixels.format(Quantity::Mass, 2.5e9), "2.500e9 u");
+  assert_eq!(pixels.format(Quantity::Length, 0.0), "0 px");
+}
+
+#[test]
+fn invalid_units_are_rejected() {
+  let unknown = Scenario::parse("[units]\nsystem = \"imperial\"\n");
+  assert!(matches!(unknown, Err(ScenarioError::Parse(_))));
+
+  match Scenario::parse("[units]\nsystem = \"si\"\nlength_scale = 0\n") {
+    Err(ScenarioError::Invalid { item, reason }) => {
+      assert_eq!(item, "units");
+      assert!(reason.contains("length_scale"), "{}", reason);
+    },
+    other => panic!("expected invalid units, got {:?}", other.map(|_| ())),
+  }
+}